@@ -0,0 +1,161 @@
+//! Writes the SOLANA_ESCROW_FIXTURES of tests/solana_execution.rs: bridge_escrow
+//! accounts holding single domain USDT -> USDT intents won by a fresh solver,
+//! serialized with the program's own account types so no chain is needed.
+//!
+//!     cargo run --example solana_escrow_fixtures -- fixtures/
+//!
+//! Prints the bridge_escrow program id, to dump the program the accounts belong
+//! to (`solana program dump <id> bridge_escrow.so`).
+
+use anchor_lang::AccountSerialize;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use example_solver::chains::{
+    OperationInput, OperationOutput, PostIntentInfo, SwapTransferInput, SwapTransferOutput,
+};
+use serde_json::json;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::solana_program::program_option::COption;
+use spl_token::solana_program::program_pack::Pack;
+use spl_token::state::{Account, AccountState};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+// the auctioneer send_funds_to_user is called with
+const AUCTIONEER: &str = "5zCZ3jk8EZnJyG7fhDqD6tmqiYTLZjik5HUpGMnHrZfC";
+// (intent_id, amount_in, amount_out) in USDT units
+const INTENTS: [(&str, u64, u64); 2] = [
+    ("fixture-1", 100_000_000, 99_000_000),
+    ("fixture-2", 25_000_000, 24_900_000),
+];
+
+// one --account-dir file, in the `solana account --output json` format
+fn write_account(dir: &Path, pubkey: &Pubkey, owner: &Pubkey, data: &[u8]) {
+    let account = json!({
+        "pubkey": pubkey.to_string(),
+        "account": {
+            "lamports": LAMPORTS_PER_SOL,
+            "data": [STANDARD.encode(data), "base64"],
+            "owner": owner.to_string(),
+            "executable": false,
+            "rentEpoch": 0,
+            "space": data.len(),
+        }
+    });
+    fs::write(dir.join(format!("{pubkey}.json")), account.to_string())
+        .unwrap_or_else(|e| panic!("Failed to write {pubkey}: {e}"));
+}
+
+fn anchor_data<T: AccountSerialize>(account: &T) -> Vec<u8> {
+    let mut data = Vec::new();
+    account
+        .try_serialize(&mut data)
+        .expect("Failed to serialize account");
+    data
+}
+
+fn main() {
+    let dir = Path::new(&env::args().nth(1).unwrap_or_else(|| String::from("fixtures"))).to_path_buf();
+    let accounts = dir.join("accounts");
+    fs::create_dir_all(&accounts).unwrap_or_else(|e| panic!("Failed to create {:?}: {e}", accounts));
+
+    let program_id = bridge_escrow::ID;
+    let usdt = Pubkey::from_str(USDT).unwrap();
+    let solver = Keypair::new();
+    let user = Keypair::new();
+    let timeout = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 365 * 24 * 3600;
+
+    let (auctioneer_state, _) = Pubkey::find_program_address(&[b"auctioneer"], &program_id);
+    write_account(
+        &accounts,
+        &auctioneer_state,
+        &program_id,
+        &anchor_data(&bridge_escrow::Auctioneer {
+            authority: Pubkey::from_str(AUCTIONEER).unwrap(),
+        }),
+    );
+
+    let mut intents = Vec::new();
+    for (intent_id, amount_in, amount_out) in INTENTS {
+        let (intent_state, _) =
+            Pubkey::find_program_address(&[b"intent", intent_id.as_bytes()], &program_id);
+        write_account(
+            &accounts,
+            &intent_state,
+            &program_id,
+            &anchor_data(&bridge_escrow::Intent {
+                intent_id: intent_id.to_string(),
+                user_in: user.pubkey(),
+                user_out: user.pubkey().to_string(),
+                token_in: usdt,
+                amount_in,
+                token_out: USDT.to_string(),
+                amount_out: amount_out.to_string(),
+                timeout_timestamp_in_sec: timeout,
+                creation_time_in_sec: timeout - 365 * 24 * 3600,
+                winner_solver: solver.pubkey(),
+                single_domain: true,
+            }),
+        );
+
+        intents.push(json!({
+            "intent_id": intent_id,
+            "amount": amount_out.to_string(),
+            "intent": PostIntentInfo {
+                function_name: String::from("transfer"),
+                src_chain: String::from("solana"),
+                dst_chain: String::from("solana"),
+                inputs: OperationInput::SwapTransfer(SwapTransferInput {
+                    token_in: USDT.to_string(),
+                    amount_in: amount_in.to_string(),
+                    src_chain_user: user.pubkey().to_string(),
+                    timeout: timeout.to_string(),
+                }),
+                outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+                    token_out: USDT.to_string(),
+                    amount_out: amount_out.to_string(),
+                    dst_chain_user: user.pubkey().to_string(),
+                }),
+            },
+        }));
+    }
+
+    // token_in the users escrowed, paid out to the solver on each fill
+    let mut escrow = vec![0u8; Account::LEN];
+    Account::pack(
+        Account {
+            mint: usdt,
+            owner: auctioneer_state,
+            amount: INTENTS.iter().map(|(_, amount_in, _)| amount_in).sum(),
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        },
+        &mut escrow,
+    )
+    .unwrap();
+    write_account(
+        &accounts,
+        &get_associated_token_address(&auctioneer_state, &usdt),
+        &spl_token::id(),
+        &escrow,
+    );
+
+    let fixtures = json!({
+        "solver_keypair": solver.to_base58_string(),
+        "intents": intents,
+    });
+    fs::write(dir.join("intents.json"), serde_json::to_string_pretty(&fixtures).unwrap())
+        .expect("Failed to write intents.json");
+
+    println!("{program_id}");
+}
@@ -0,0 +1,488 @@
+//! Admin HTTP/JSON API, to change the solver settings kept behind RwLocks while
+//! it runs. Disabled unless ADMIN_TOKEN is set, every request must carry
+//! `Authorization: Bearer <ADMIN_TOKEN>`.
+//!
+//! GET  /fees                  FLAT_FEES as {"<src_chain>:<dst_chain>": [src_cost, dst_cost]}
+//! POST /fees                  same shape, merged into FLAT_FEES
+//! GET  /comission             {"comission": 10}
+//! PUT  /comission             {"comission": 10}
+//! GET  /mantis_tokens         MANTIS_TOKENS as {"<mantis_token>": "<solana_token>"}
+//! POST /mantis_tokens         same shape, merged into MANTIS_TOKENS
+//! DELETE /mantis_tokens/<token>
+//! GET  /risk                  risk limits and kill switch
+//! PUT  /risk                  any of the GET /risk fields, null clears a limit
+//! GET  /routes/paused         {"<src_chain>:<dst_chain>": "<reason>"}
+//! POST /routes/pause          {"src_chain", "dst_chain", "reason"}
+//! POST /routes/resume         {"src_chain", "dst_chain"}
+//! GET  /intents               intents bid on and not settled yet (INTENTS)
+//! GET  /intents/failed        won intents left in the journal
+//! POST /intents/<id>/retry    executes a failed intent again if it still needs a fill,
+//!                             409 while it is being filled
+
+use crate::chains::{execute_intent, intent_needs_fill, INTENTS};
+use crate::dry_run::{DryRun, DRY_RUN};
+use crate::inventory::invalidate_balances;
+use crate::journal::{mark_done, pending};
+use crate::risk::{kill_switch_engaged, set_kill_switch, RiskLimits, RISK_LIMITS};
+use crate::routers::{COMISSION, FLAT_FEES, MANTIS_TOKENS, PAUSED_ROUTES};
+use hyper::header::AUTHORIZATION;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server};
+use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::env;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+lazy_static! {
+    // intents a POST /intents/<id>/retry is filling
+    static ref RETRYING: Arc<RwLock<HashSet<String>>> = Arc::new(RwLock::new(HashSet::new()));
+}
+
+fn respond(status: u16, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error(status: u16, e: impl ToString) -> Response<Body> {
+    respond(status, json!({ "error": e.to_string() }))
+}
+
+// compares in constant time, so the token can't be guessed byte by byte
+fn authorized(request: &Request<Body>, token: &str) -> bool {
+    let Some(given) = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn route(route: &str) -> Result<(String, String), String> {
+    route
+        .split_once(':')
+        .map(|(src_chain, dst_chain)| (src_chain.to_string(), dst_chain.to_string()))
+        .ok_or_else(|| format!("route {route} is not src_chain:dst_chain"))
+}
+
+fn field<'a>(body: &'a Value, name: &str) -> Result<&'a str, String> {
+    body.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("{name} is required"))
+}
+
+async fn fees() -> Value {
+    let fees: Map<String, Value> = FLAT_FEES
+        .read()
+        .await
+        .iter()
+        .map(|((src_chain, dst_chain), costs)| (format!("{src_chain}:{dst_chain}"), json!(costs)))
+        .collect();
+    Value::Object(fees)
+}
+
+async fn update_fees(body: Value) -> Result<Value, String> {
+    let updates: HashMap<String, (u32, u32)> =
+        serde_json::from_value(body).map_err(|e| e.to_string())?;
+    let mut parsed = Vec::new();
+    for (key, costs) in updates {
+        parsed.push((route(&key)?, costs));
+    }
+
+    FLAT_FEES.write().await.extend(parsed);
+    Ok(fees().await)
+}
+
+async fn update_comission(body: Value) -> Result<Value, String> {
+    let comission = body
+        .get("comission")
+        .and_then(Value::as_u64)
+        .and_then(|comission| u32::try_from(comission).ok())
+        .ok_or("comission must be a number")?;
+
+    *COMISSION.write().await = comission;
+    Ok(json!({ "comission": comission }))
+}
+
+async fn update_mantis_tokens(body: Value) -> Result<Value, String> {
+    let tokens: HashMap<String, String> =
+        serde_json::from_value(body).map_err(|e| e.to_string())?;
+
+    let mut mantis_tokens = MANTIS_TOKENS.write().await;
+    mantis_tokens.extend(tokens);
+    Ok(json!(*mantis_tokens))
+}
+
+fn risk_json(limits: &RiskLimits) -> Value {
+    let exposure: HashMap<&String, String> = limits
+        .max_open_exposure
+        .iter()
+        .map(|(chain, max)| (chain, max.to_string()))
+        .collect();
+
+    json!({
+        "max_notional_per_intent": limits.max_notional_per_intent.as_ref().map(BigInt::to_string),
+        "max_open_exposure": exposure,
+        "max_daily_loss": limits.max_daily_loss.as_ref().map(BigInt::to_string),
+        "token_allowlist": limits.token_allowlist,
+        "token_denylist": limits.token_denylist,
+        "max_price_impact_pct": limits.max_price_impact_pct,
+        "kill_switch": kill_switch_engaged(),
+    })
+}
+
+// null clears the limit, amounts are strings in bridge token units
+fn optional_amount(value: &Value, name: &str) -> Result<Option<BigInt>, String> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(amount) => BigInt::from_str(amount)
+            .map(Some)
+            .map_err(|e| format!("invalid {name} {amount}: {e}")),
+        _ => Err(format!("{name} must be a string or null")),
+    }
+}
+
+fn token_set(value: &Value, name: &str) -> Result<HashSet<String>, String> {
+    let tokens: Vec<String> =
+        serde_json::from_value(value.clone()).map_err(|e| format!("invalid {name}: {e}"))?;
+    Ok(tokens.iter().map(|token| token.to_lowercase()).collect())
+}
+
+// updated as a whole or not at all
+async fn update_risk(body: Value) -> Result<Value, String> {
+    let Value::Object(updates) = body else {
+        return Err("expected an object".to_string());
+    };
+
+    let mut limits = RISK_LIMITS.read().await.clone();
+    let mut kill_switch = None;
+    for (name, value) in &updates {
+        match name.as_str() {
+            "max_notional_per_intent" => {
+                limits.max_notional_per_intent = optional_amount(value, name)?
+            }
+            "max_daily_loss" => limits.max_daily_loss = optional_amount(value, name)?,
+            "max_open_exposure" => {
+                let exposure: HashMap<String, String> = serde_json::from_value(value.clone())
+                    .map_err(|e| format!("invalid {name}: {e}"))?;
+                limits.max_open_exposure = HashMap::new();
+                for (chain, max) in exposure {
+                    let max = BigInt::from_str(&max)
+                        .map_err(|e| format!("invalid {name} {chain} {max}: {e}"))?;
+                    limits.max_open_exposure.insert(chain, max);
+                }
+            }
+            "token_allowlist" => limits.token_allowlist = token_set(value, name)?,
+            "token_denylist" => limits.token_denylist = token_set(value, name)?,
+            "max_price_impact_pct" => {
+                limits.max_price_impact_pct = match value {
+                    Value::Null => None,
+                    _ => Some(
+                        value
+                            .as_f64()
+                            .ok_or_else(|| format!("{name} must be a number"))?,
+                    ),
+                }
+            }
+            "kill_switch" => {
+                kill_switch = Some(value.as_bool().ok_or("kill_switch must be a boolean")?)
+            }
+            _ => return Err(format!("unknown risk setting {name}")),
+        }
+    }
+
+    *RISK_LIMITS.write().await = limits.clone();
+    if let Some(engaged) = kill_switch {
+        set_kill_switch(engaged);
+    }
+    Ok(risk_json(&limits))
+}
+
+async fn paused_routes() -> Value {
+    let routes: Map<String, Value> = PAUSED_ROUTES
+        .read()
+        .await
+        .iter()
+        .map(|((src_chain, dst_chain), reason)| (format!("{src_chain}:{dst_chain}"), json!(reason)))
+        .collect();
+    Value::Object(routes)
+}
+
+async fn pause_route(body: Value) -> Result<Value, String> {
+    let route = (
+        field(&body, "src_chain")?.to_string(),
+        field(&body, "dst_chain")?.to_string(),
+    );
+    let reason = body
+        .get("reason")
+        .and_then(Value::as_str)
+        .unwrap_or("admin")
+        .to_string();
+
+    println!("Route {} -> {} paused by admin: {reason}", route.0, route.1);
+    PAUSED_ROUTES.write().await.insert(route, reason);
+    Ok(paused_routes().await)
+}
+
+async fn resume_route(body: Value) -> Result<Value, String> {
+    let route = (
+        field(&body, "src_chain")?.to_string(),
+        field(&body, "dst_chain")?.to_string(),
+    );
+
+    println!("Route {} -> {} resumed by admin", route.0, route.1);
+    PAUSED_ROUTES.write().await.remove(&route);
+    Ok(paused_routes().await)
+}
+
+// journal entries whose auction the main loop is done with
+async fn failed_intents() -> Result<Value, String> {
+    let journal = pending().await?;
+    let in_flight = INTENTS.read().await;
+    let failed: HashMap<String, Value> = journal
+        .into_iter()
+        .filter(|(intent_id, _)| !in_flight.contains_key(intent_id))
+        .map(|(intent_id, entry)| (intent_id, json!(entry)))
+        .collect();
+    Ok(json!(failed))
+}
+
+// errors come with their status: 409 while the intent is handled elsewhere
+async fn retry(intent_id: &str) -> Result<Value, (u16, String)> {
+    if *DRY_RUN != DryRun::Off {
+        return Err((400, "fills are only simulated in dry run".to_string()));
+    }
+    if INTENTS.read().await.contains_key(intent_id) {
+        return Err((409, format!("intent {intent_id} is still being handled")));
+    }
+    if !RETRYING.write().await.insert(intent_id.to_string()) {
+        return Err((409, format!("intent {intent_id} is already being retried")));
+    }
+
+    let result = retry_fill(intent_id).await.map_err(|e| (400, e));
+    RETRYING.write().await.remove(intent_id);
+    result
+}
+
+async fn retry_fill(intent_id: &str) -> Result<Value, String> {
+    let entry = pending()
+        .await?
+        .remove(intent_id)
+        .ok_or_else(|| format!("intent {intent_id} is not in the journal"))?;
+
+    // filled, cancelled or timed out since it failed
+    if !intent_needs_fill(intent_id, &entry.intent, &entry.amount)
+        .await
+        .map_err(|e| format!("Could not check intent {intent_id}: {e}"))?
+    {
+        mark_done(intent_id).await;
+        return Ok(json!({ "intent_id": intent_id, "filled": false }));
+    }
+
+    println!("Retrying intent {intent_id} for admin");
+    execute_intent(
+        intent_id,
+        &entry.intent,
+        &entry.amount,
+        &entry.bridge_token(),
+    )
+    .await?;
+    mark_done(intent_id).await;
+    invalidate_balances().await;
+
+    Ok(json!({ "intent_id": intent_id, "filled": true }))
+}
+
+async fn handle(request: Request<Body>, token: String) -> Result<Response<Body>, Infallible> {
+    if !authorized(&request, &token) {
+        return Ok(error(401, "missing or wrong admin token"));
+    }
+
+    let method = request.method().clone();
+    let path = request.uri().path().trim_end_matches('/').to_string();
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+        .unwrap_or(Value::Null);
+    let segments: Vec<&str> = path.split('/').skip(1).collect();
+
+    let result = match (&method, segments.as_slice()) {
+        (&Method::GET, ["fees"]) => Ok(fees().await),
+        (&Method::POST, ["fees"]) => update_fees(body).await,
+        (&Method::GET, ["comission"]) => Ok(json!({ "comission": *COMISSION.read().await })),
+        (&Method::PUT, ["comission"]) => update_comission(body).await,
+        (&Method::GET, ["mantis_tokens"]) => Ok(json!(*MANTIS_TOKENS.read().await)),
+        (&Method::POST, ["mantis_tokens"]) => update_mantis_tokens(body).await,
+        (&Method::DELETE, ["mantis_tokens", mantis_token]) => {
+            MANTIS_TOKENS.write().await.remove(*mantis_token);
+            Ok(json!(*MANTIS_TOKENS.read().await))
+        }
+        (&Method::GET, ["risk"]) => Ok(risk_json(&RISK_LIMITS.read().await)),
+        (&Method::PUT, ["risk"]) => update_risk(body).await,
+        (&Method::GET, ["routes", "paused"]) => Ok(paused_routes().await),
+        (&Method::POST, ["routes", "pause"]) => pause_route(body).await,
+        (&Method::POST, ["routes", "resume"]) => resume_route(body).await,
+        (&Method::GET, ["intents"]) => Ok(json!(*INTENTS.read().await)),
+        (&Method::GET, ["intents", "failed"]) => failed_intents().await,
+        (&Method::POST, ["intents", intent_id, "retry"]) => match retry(intent_id).await {
+            Err((409, e)) => return Ok(error(409, e)),
+            result => result.map_err(|(_, e)| e),
+        },
+        _ => return Ok(error(404, format!("no admin route {method} {path}"))),
+    };
+
+    Ok(match result {
+        Ok(body) => respond(200, body),
+        Err(e) => error(400, e),
+    })
+}
+
+/// Serves the admin API on ADMIN_ADDR (default 127.0.0.1:8902) when
+/// ADMIN_TOKEN is set.
+pub async fn run_admin_api() {
+    let Some(token) = env::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+    else {
+        return;
+    };
+    let addr: SocketAddr = env::var("ADMIN_ADDR")
+        .unwrap_or_else(|_| String::from("127.0.0.1:8902"))
+        .parse()
+        .expect("ADMIN_ADDR must be a socket address");
+    if !addr.ip().is_loopback() {
+        eprintln!("Admin API listening on {addr}, reachable from outside this host");
+    }
+
+    let make_service = make_service_fn(move |_| {
+        let token = token.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(request, token.clone()))) }
+    });
+
+    println!("Admin API listening on http://{addr}");
+    if let Err(e) = Server::bind(&addr).serve(make_service).await {
+        eprintln!("Admin API stopped: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chains::{OperationInput, OperationOutput, PostIntentInfo, SwapTransferInput, SwapTransferOutput};
+    use crate::mock_routers::ROUTER_ENV;
+
+    fn request(method: Method, path: &str, authorization: Option<&str>) -> Request<Body> {
+        let mut request = Request::builder().method(method).uri(path);
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn only_the_exact_token_is_authorized() {
+        let check = |authorization: Option<&str>| {
+            authorized(&request(Method::GET, "/fees", authorization), "secret")
+        };
+
+        assert!(check(Some("Bearer secret")));
+        assert!(!check(None));
+        assert!(!check(Some("secret")));
+        assert!(!check(Some("Bearer secreT")));
+        assert!(!check(Some("Bearer secre")));
+        assert!(!check(Some("Bearer secrets")));
+    }
+
+    #[tokio::test]
+    async fn unauthorized_requests_get_401() {
+        let response = handle(request(Method::GET, "/fees", Some("Bearer wrong")), String::from("secret"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn risk_update_with_an_invalid_field_changes_nothing() {
+        // the quoting tests read the limits
+        let _env = ROUTER_ENV.lock().await;
+        let before = risk_json(&RISK_LIMITS.read().await);
+        let kill_switch = kill_switch_engaged();
+
+        let update = json!({
+            "kill_switch": !kill_switch,
+            "max_daily_loss": "1000000",
+            "token_denylist": ["0xdac17f958d2ee523a2206206994597c13d831ec7"],
+            "max_price_impact_pct": "high",
+        });
+        assert_eq!(
+            update_risk(update).await,
+            Err("max_price_impact_pct must be a number".to_string())
+        );
+        assert_eq!(risk_json(&RISK_LIMITS.read().await), before);
+        assert_eq!(kill_switch_engaged(), kill_switch);
+    }
+
+    fn intent() -> PostIntentInfo {
+        PostIntentInfo {
+            function_name: String::from("transfer"),
+            src_chain: String::from("solana"),
+            dst_chain: String::from("solana"),
+            inputs: OperationInput::SwapTransfer(SwapTransferInput {
+                token_in: String::from("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB"),
+                amount_in: String::from("1000000"),
+                src_chain_user: String::from("5zCZ3jk8EZnJyG7fhDqD6tmqiYTLZjik5HUpGMnHrZfC"),
+                timeout: String::from("1700000000"),
+            }),
+            outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+                token_out: String::from("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB"),
+                amount_out: String::from("990000"),
+                dst_chain_user: String::from("5zCZ3jk8EZnJyG7fhDqD6tmqiYTLZjik5HUpGMnHrZfC"),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_conflicts_with_an_intent_being_filled() {
+        INTENTS
+            .write()
+            .await
+            .insert(String::from("admin-in-flight"), intent());
+        let response = handle(
+            request(Method::POST, "/intents/admin-in-flight/retry", Some("Bearer secret")),
+            String::from("secret"),
+        )
+        .await
+        .unwrap();
+        INTENTS.write().await.remove("admin-in-flight");
+        assert_eq!(response.status(), 409);
+
+        RETRYING
+            .write()
+            .await
+            .insert(String::from("admin-retrying"));
+        let retried = retry("admin-retrying").await;
+        RETRYING.write().await.remove("admin-retrying");
+        assert_eq!(
+            retried,
+            Err((409, "intent admin-retrying is already being retried".to_string()))
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use crate::chains::{OperationOutput, PostIntentInfo};
+use crate::recorder::{replay, start_replay, stop_replay, Event, Record};
+use crate::risk::{RiskLimits, RISK_LIMITS};
+use crate::routers::{get_best_quote, COMISSION, FLAT_FEES};
+use crate::strategy::{parse_strategy, price_bid, strategy_from_env, BidInput, BidStrategy};
+use num_bigint::BigInt;
+use num_traits::Zero;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// One set of bidding parameters to replay a session with, unset fields keep
+/// the values from the environment.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Params {
+    pub name: String,
+    pub comission: Option<u32>,
+    // "src_chain:dst_chain" -> (src_chain_cost, dst_chain_cost)
+    #[serde(default)]
+    pub flat_fees: HashMap<String, (u32, u32)>,
+    pub max_notional_per_intent: Option<String>,
+    pub max_price_impact_pct: Option<f64>,
+    #[serde(default)]
+    pub token_denylist: Vec<String>,
+    // BID_STRATEGY spec, e.g. "fixed:30"
+    pub bid_strategy: Option<String>,
+}
+
+/// Wins and losses are only counted when the recording settles them: a bid at
+/// least as high as one that won still wins, one no higher than one that lost
+/// still loses. Anything else (intents we didn't bid on, raised bids on lost
+/// auctions, lowered bids on won ones) is unknown.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub name: String,
+    pub intents: u64,
+    pub bids: u64,
+    pub wins: u64,
+    pub losses: u64,
+    pub unknown: u64,
+    // over the wins, bridge token received on src_chain minus what the amount
+    // bid costs in bridge token on dst_chain at the quoted rate
+    pub pnl: BigInt,
+}
+
+impl Report {
+    pub fn win_rate(&self) -> f64 {
+        if self.wins + self.losses == 0 {
+            0f64
+        } else {
+            self.wins as f64 / (self.wins + self.losses) as f64
+        }
+    }
+}
+
+// parameters as configured, every set starts from them
+struct Baseline {
+    comission: u32,
+    flat_fees: HashMap<(String, String), (u32, u32)>,
+    risk_limits: RiskLimits,
+}
+
+// (intent_id, intent) of a code 1 message
+fn auction(message: &Value) -> Option<(String, PostIntentInfo)> {
+    let msg = message.get("msg")?;
+    let intent_id = msg.get("intent_id")?.as_str()?.to_string();
+    let intent = serde_json::from_value(msg.get("intent")?.clone()).ok()?;
+    Some((intent_id, intent))
+}
+
+// (intent_id, (amount we bid, won)) of every code 4 message
+fn outcomes(session: &[Record]) -> HashMap<String, (BigInt, bool)> {
+    session
+        .iter()
+        .filter_map(|record| match &record.event {
+            Event::Received { message } => serde_json::from_str::<Value>(message).ok(),
+            _ => None,
+        })
+        .filter(|message| message.get("code").and_then(Value::as_u64) == Some(4))
+        .filter_map(|message| {
+            let msg = message.get("msg")?;
+            let intent_id = msg.get("intent_id")?.as_str()?.to_string();
+            let amount = BigInt::from_str(msg.get("amount")?.as_str()?).ok()?;
+            let won = msg.get("msg")?.as_str()?.contains("won");
+            Some((intent_id, (amount, won)))
+        })
+        .collect()
+}
+
+async fn apply(params: &Params, baseline: &Baseline) -> Result<(), String> {
+    *COMISSION.write().await = params.comission.unwrap_or(baseline.comission);
+
+    let mut flat_fees = baseline.flat_fees.clone();
+    for (route, costs) in &params.flat_fees {
+        let (src_chain, dst_chain) = route.split_once(':').ok_or_else(|| {
+            format!(
+                "{}: flat_fees route {route} is not src_chain:dst_chain",
+                params.name
+            )
+        })?;
+        flat_fees.insert((src_chain.to_string(), dst_chain.to_string()), *costs);
+    }
+    *FLAT_FEES.write().await = flat_fees;
+
+    let mut risk_limits = baseline.risk_limits.clone();
+    if let Some(max) = &params.max_notional_per_intent {
+        risk_limits.max_notional_per_intent = Some(BigInt::from_str(max).map_err(|e| {
+            format!(
+                "{}: invalid max_notional_per_intent {max}: {e}",
+                params.name
+            )
+        })?);
+    }
+    if let Some(max) = params.max_price_impact_pct {
+        risk_limits.max_price_impact_pct = Some(max);
+    }
+    risk_limits.token_denylist.extend(
+        params
+            .token_denylist
+            .iter()
+            .map(|token| token.to_lowercase()),
+    );
+    *RISK_LIMITS.write().await = risk_limits;
+
+    Ok(())
+}
+
+async fn evaluate(
+    report: &mut Report,
+    strategy: &dyn BidStrategy,
+    intent_id: &str,
+    intent: &PostIntentInfo,
+    outcomes: &HashMap<String, (BigInt, bool)>,
+) {
+    report.intents += 1;
+
+    let quote = get_best_quote(intent).await;
+    let amount_out_min = match &intent.outputs {
+        OperationOutput::SwapTransfer(output) => {
+            BigInt::from_str(&output.amount_out).unwrap_or_default()
+        }
+        _ => BigInt::zero(),
+    };
+    let quote_amount = BigInt::from_str(&quote.amount_out).unwrap_or_default();
+    let bid = price_bid(
+        strategy,
+        &BidInput {
+            intent,
+            quote: &quote,
+            quote_amount: quote_amount.clone(),
+            amount_out_min: amount_out_min.clone(),
+        },
+    )
+    .await
+    .amount;
+
+    if bid <= amount_out_min || quote_amount.is_zero() {
+        return;
+    }
+    report.bids += 1;
+
+    match outcomes.get(intent_id) {
+        Some((amount, true)) if &bid >= amount => {
+            report.wins += 1;
+            // the bridge token the bid costs at the quoted rate, the margin
+            // quote_amount - bid stays with us
+            report.pnl +=
+                &quote.bridge_amount_src - &quote.bridge_amount_dst * &bid / &quote_amount;
+            strategy.record_result(intent, true).await;
+        }
+        Some((amount, false)) if &bid <= amount => {
+            report.losses += 1;
+            strategy.record_result(intent, false).await;
+        }
+        _ => report.unknown += 1,
+    }
+}
+
+/// Feeds the auctions of a recorded session through get_best_quote once per
+/// parameter set, quoting from the router quotes and balances recorded
+/// alongside them. Each auction sees the quotes recorded up to the next one.
+pub async fn backtest(session: &[Record], params: &[Params]) -> Result<Vec<Report>, String> {
+    let outcomes = outcomes(session);
+    let baseline = Baseline {
+        comission: *COMISSION.read().await,
+        flat_fees: FLAT_FEES.read().await.clone(),
+        risk_limits: RISK_LIMITS.read().await.clone(),
+    };
+
+    let mut reports = Vec::new();
+    for params in params {
+        apply(params, &baseline).await?;
+        start_replay().await;
+        // a fresh one per set, so adaptive strategies don't learn across sets
+        let strategy = match &params.bid_strategy {
+            Some(spec) => parse_strategy(spec),
+            None => strategy_from_env(),
+        }
+        .map_err(|e| format!("{}: {e}", params.name))?;
+
+        let mut report = Report {
+            name: params.name.clone(),
+            ..Report::default()
+        };
+        let mut pending: Option<(String, PostIntentInfo)> = None;
+
+        for record in session {
+            if let Event::Received { message } = &record.event {
+                let Ok(message) = serde_json::from_str::<Value>(message) else {
+                    continue;
+                };
+                if message.get("code").and_then(Value::as_u64) != Some(1) {
+                    continue;
+                }
+                // the previous auction's quotes are all in by now
+                if let Some((intent_id, intent)) = pending.take() {
+                    evaluate(
+                        &mut report,
+                        strategy.as_ref(),
+                        &intent_id,
+                        &intent,
+                        &outcomes,
+                    )
+                    .await;
+                }
+                pending = auction(&message);
+            } else {
+                replay(&record.event).await;
+            }
+        }
+        if let Some((intent_id, intent)) = pending.take() {
+            evaluate(
+                &mut report,
+                strategy.as_ref(),
+                &intent_id,
+                &intent,
+                &outcomes,
+            )
+            .await;
+        }
+
+        reports.push(report);
+    }
+
+    // the parameters as configured again, e.g. for the next backtest
+    stop_replay();
+    apply(&Params::default(), &baseline).await?;
+
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chains::{OperationInput, SwapTransferInput, SwapTransferOutput};
+    use crate::inventory::GAS_TOKEN;
+    use crate::mock_routers::ROUTER_ENV;
+    use serde_json::json;
+    use std::env;
+
+    const TOKEN_IN: &str = "So11111111111111111111111111111111111111112";
+    const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+    fn received(message: Value) -> Record {
+        Record {
+            timestamp_ms: 0,
+            event: Event::Received {
+                message: message.to_string(),
+            },
+        }
+    }
+
+    fn auction(intent_id: &str, amount_out_min: &str) -> Record {
+        let intent = PostIntentInfo {
+            function_name: String::from("transfer"),
+            src_chain: String::from("solana"),
+            dst_chain: String::from("solana"),
+            inputs: OperationInput::SwapTransfer(SwapTransferInput {
+                token_in: TOKEN_IN.to_string(),
+                amount_in: String::from("1000000"),
+                src_chain_user: String::from("5zCZ3jk8EZnJyG7fhDqD6tmqiYTLZjik5HUpGMnHrZfC"),
+                timeout: String::from("1700000000"),
+            }),
+            outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+                token_out: USDT.to_string(),
+                amount_out: amount_out_min.to_string(),
+                dst_chain_user: String::from("5zCZ3jk8EZnJyG7fhDqD6tmqiYTLZjik5HUpGMnHrZfC"),
+            }),
+        };
+        received(json!({ "code": 1, "msg": { "intent_id": intent_id, "intent": intent } }))
+    }
+
+    fn result(intent_id: &str, amount: &str, msg: &str) -> Record {
+        received(json!({ "code": 4, "msg": { "intent_id": intent_id, "amount": amount, "msg": msg } }))
+    }
+
+    fn balance(token: &str, balance: &str) -> Record {
+        Record {
+            timestamp_ms: 0,
+            event: Event::Balance {
+                chain: String::from("solana"),
+                token: token.to_string(),
+                balance: balance.to_string(),
+            },
+        }
+    }
+
+    fn session() -> Vec<Record> {
+        vec![
+            balance(GAS_TOKEN, "1000000000"),
+            balance(USDT, "1000000000000"),
+            // 2 USDT per token_in
+            Record {
+                timestamp_ms: 0,
+                event: Event::Quote {
+                    chain: String::from("solana"),
+                    token_in: TOKEN_IN.to_string(),
+                    token_out: USDT.to_string(),
+                    amount_in: String::from("1000000"),
+                    amount_out: String::from("2000000"),
+                    price_impact_pct: 0.0,
+                },
+            },
+            auction("1", "1000000"),
+            auction("2", "1000000"),
+            auction("3", "1000000"),
+            // asks for more than the quote, not bid on
+            auction("4", "3000000"),
+            result("1", "1950000", "Congratulations, you won the auction"),
+            result("2", "2000000", "You lost the auction"),
+        ]
+    }
+
+    fn params(name: &str, comission: u32) -> Params {
+        Params {
+            name: name.to_string(),
+            comission: Some(comission),
+            flat_fees: HashMap::from([(String::from("solana:solana"), (0, 0))]),
+            bid_strategy: Some(String::from("fixed:100")),
+            ..Params::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_the_session_per_parameter_set() {
+        let _env = ROUTER_ENV.lock().await;
+        env::set_var("COMISSION", "100");
+        let comission = *COMISSION.read().await;
+
+        let reports = backtest(&session(), &[params("free", 0), params("1%", 1000)])
+            .await
+            .unwrap();
+        assert_eq!(*COMISSION.read().await, comission);
+
+        let counts = |report: &Report| {
+            (report.intents, report.bids, report.wins, report.losses, report.unknown)
+        };
+        // bids 1% under the quote: 1_980_000 beats the 1_950_000 that won auction
+        // 1, is no more than the 2_000_000 that lost auction 2
+        assert_eq!(counts(&reports[0]), (4, 3, 1, 1, 1));
+        // 2_000_000 received, 1_980_000 spent
+        assert_eq!(reports[0].pnl, BigInt::from(20_000));
+
+        // 1_980_000 quoted, 1_960_200 bid
+        assert_eq!(counts(&reports[1]), (4, 3, 1, 1, 1));
+        assert_eq!(reports[1].pnl, BigInt::from(39_800));
+        assert_eq!(reports[1].win_rate(), 0.5);
+    }
+}
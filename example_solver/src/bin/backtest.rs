@@ -0,0 +1,51 @@
+//! Replays a session recorded with SESSION_RECORD under different bidding
+//! parameters and reports the win rate and PnL of each set:
+//!
+//!     cargo run --bin backtest -- session.jsonl backtest.example.json
+//!
+//! The parameters file is a JSON list of sets, see `backtest::Params`. Fields a
+//! set leaves out keep the values from .env (COMISSION, RISK_*).
+
+use example_solver::backtest::{backtest, Params};
+use example_solver::recorder::read_session;
+use std::env;
+use std::fs;
+use std::process;
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: backtest <session.jsonl> <params.json>");
+        process::exit(1);
+    }
+
+    let session = read_session(&args[1]).unwrap_or_else(|e| panic!("{e}"));
+    let params: Vec<Params> = serde_json::from_str(
+        &fs::read_to_string(&args[2]).unwrap_or_else(|e| panic!("Failed to read {}: {e}", args[2])),
+    )
+    .unwrap_or_else(|e| panic!("Invalid parameters {}: {e}", args[2]));
+
+    let reports = backtest(&session, &params)
+        .await
+        .unwrap_or_else(|e| panic!("{e}"));
+
+    println!(
+        "{:<16} {:>8} {:>6} {:>6} {:>7} {:>8} {:>9} {:>16}",
+        "params", "intents", "bids", "wins", "losses", "unknown", "win rate", "pnl"
+    );
+    for report in reports {
+        println!(
+            "{:<16} {:>8} {:>6} {:>6} {:>7} {:>8} {:>8.1}% {:>16}",
+            report.name,
+            report.intents,
+            report.bids,
+            report.wins,
+            report.losses,
+            report.unknown,
+            report.win_rate() * 100f64,
+            report.pnl
+        );
+    }
+}
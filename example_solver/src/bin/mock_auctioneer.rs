@@ -0,0 +1,525 @@
+//! Local stand-in for the Composable auctioneer, for end-to-end runs of the
+//! solver without the live endpoint.
+//!
+//! Speaks the code 0-4 websocket protocol, checks solver signatures the way the
+//! auctioneer does, broadcasts the intents of a script, settles each auction
+//! with the scripted outcome and appends every message it sees to a JSON lines
+//! record. Point the solver at it with COMPOSABLE_ENDPOINT="ws://127.0.0.1:8900".
+//!
+//! MOCK_AUCTIONEER_ADDR     listen address, default 127.0.0.1:8900
+//! MOCK_AUCTIONEER_SCRIPT   script file, default mock_auctioneer.example.json
+//! MOCK_AUCTIONEER_RECORD   record file, default mock_auctioneer_record.jsonl
+//! MOCK_AUCTIONEER_PKEY     signs code 1 / code 4 like the real auctioneer (set
+//!                          the solver's AUCTIONEER_ADDRESS to its address)
+
+use ethers::prelude::*;
+use example_solver::chains::{create_keccak256_signature, verify_keccak256_signature};
+use example_solver::signer::EthereumSigner;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature as SolanaSignature;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Clone, Copy, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    // highest bid wins
+    #[default]
+    BestBid,
+    // earliest bid wins, whatever its amount
+    FirstBid,
+    // everyone loses, e.g. to test reservations are released
+    AllLose,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ScriptedIntent {
+    intent_id: String,
+    // PostIntentInfo as the solver deserializes it
+    intent: Value,
+    // wait this long after the previous intent before broadcasting
+    #[serde(default)]
+    delay_secs: u64,
+    // bids are collected for this long
+    #[serde(default = "default_auction_secs")]
+    auction_secs: u64,
+    #[serde(default)]
+    outcome: Outcome,
+    // rewrites the intent timeout to now + timeout_in_secs, so scripts don't expire
+    timeout_in_secs: Option<u64>,
+}
+
+fn default_auction_secs() -> u64 {
+    5
+}
+
+fn default_min_solvers() -> usize {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+struct Script {
+    // don't start broadcasting before this many solvers registered
+    #[serde(default = "default_min_solvers")]
+    min_solvers: usize,
+    intents: Vec<ScriptedIntent>,
+    // stop once the last auction is settled and solvers had this long to fill
+    exit_after_secs: Option<u64>,
+}
+
+struct Solver {
+    identity: Address,
+    sender: mpsc::UnboundedSender<Message>,
+}
+
+#[derive(Default)]
+struct State {
+    solvers: HashMap<String, Solver>,
+    // <intent_id, [(solver_id, amount)]> in arrival order
+    bids: HashMap<String, Vec<(String, U256)>>,
+    open_auctions: Vec<String>,
+}
+
+type SharedState = Arc<Mutex<State>>;
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn record(direction: &str, peer: &str, message: &Value) {
+    let path = env::var("MOCK_AUCTIONEER_RECORD")
+        .unwrap_or_else(|_| String::from("mock_auctioneer_record.jsonl"));
+    let line = json!({
+        "at": now_secs(),
+        "direction": direction,
+        "peer": peer,
+        "message": message,
+    });
+
+    match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(mut file) => {
+            if let Err(e) = writeln!(file, "{line}") {
+                eprintln!("Failed to record to {path}: {e}");
+            }
+        }
+        Err(e) => eprintln!("Failed to open {path}: {e}"),
+    }
+}
+
+// signed like the solver signs its own messages
+async fn sign(mut message: Value, key: Option<&LocalWallet>) -> Value {
+    let Some(key) = key else {
+        return message;
+    };
+
+    create_keccak256_signature(&mut message, &EthereumSigner::Local(Arc::new(key.clone())))
+        .await
+        .unwrap();
+    message
+}
+
+// the registering key is the identity when one is given, else the ethereum
+// solver address; every chain binding must be signed by its chain key
+fn verify_registration(message: &Value) -> Result<Address, String> {
+    let msg = message.get("msg").ok_or("message has no msg object")?;
+    let addresses: Vec<String> = msg
+        .get("solver_addresses")
+        .and_then(|addresses| serde_json::from_value(addresses.clone()).ok())
+        .ok_or("missing solver_addresses")?;
+    let ethereum_address = addresses
+        .first()
+        .and_then(|address| Address::from_str(address).ok())
+        .ok_or("first solver address is not an ethereum address")?;
+
+    let identity = match msg.get("identity").and_then(Value::as_str) {
+        Some(identity) => Address::from_str(identity).map_err(|e| format!("invalid identity: {e}"))?,
+        None => ethereum_address,
+    };
+
+    verify_keccak256_signature(message, identity)
+        .map_err(|e| format!("not signed by {identity:?}: {e}"))?;
+
+    if let Some(bindings) = msg.get("bindings").and_then(Value::as_array) {
+        let solver_id = msg.get("solver_id").and_then(Value::as_str).unwrap_or_default();
+        let statement = format!("Mantis solver {solver_id} identity {identity:?}");
+
+        for binding in bindings {
+            let chain = binding.get("chain").and_then(Value::as_str).unwrap_or_default();
+            let address = binding.get("address").and_then(Value::as_str).unwrap_or_default();
+            let signature = binding.get("signature").and_then(Value::as_str).unwrap_or_default();
+            if !addresses.iter().any(|solver_address| solver_address == address) {
+                return Err(format!("{chain} binding for unregistered address {address}"));
+            }
+
+            let valid = match chain {
+                "ethereum" => Signature::from_str(signature)
+                    .ok()
+                    .and_then(|signature| signature.recover(statement.as_str()).ok())
+                    .map_or(false, |signer| Address::from_str(address).ok() == Some(signer)),
+                "solana" => match (Pubkey::from_str(address), SolanaSignature::from_str(signature)) {
+                    (Ok(pubkey), Ok(signature)) => {
+                        signature.verify(pubkey.as_ref(), statement.as_bytes())
+                    }
+                    _ => false,
+                },
+                _ => false,
+            };
+            if !valid {
+                return Err(format!("invalid {chain} binding for {address}"));
+            }
+        }
+    }
+
+    Ok(identity)
+}
+
+async fn handle_message(
+    state: &SharedState,
+    peer: &str,
+    message: Value,
+    sender: &mpsc::UnboundedSender<Message>,
+    registered_as: &mut Option<String>,
+) -> Option<Value> {
+    let code = message.get("code").and_then(Value::as_u64);
+    let solver_id = message
+        .get("msg")
+        .and_then(|msg| msg.get("solver_id"))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+
+    match code {
+        Some(1) => match verify_registration(&message) {
+            Ok(identity) => {
+                println!("{peer} registered as solver {solver_id} ({identity:?})");
+                state.lock().await.solvers.insert(
+                    solver_id.clone(),
+                    Solver {
+                        identity,
+                        sender: sender.clone(),
+                    },
+                );
+                *registered_as = Some(solver_id);
+                Some(json!({ "code": 3, "msg": "Solver was successfully registered" }))
+            }
+            Err(e) => Some(json!({ "code": 0, "msg": format!("Registration rejected: {e}") })),
+        },
+        Some(2) => {
+            if registered_as.as_deref() != Some(solver_id.as_str()) {
+                return Some(json!({ "code": 0, "msg": "Solver not registered on this connection" }));
+            }
+
+            let mut state = state.lock().await;
+            let Some(identity) = state.solvers.get(&solver_id).map(|solver| solver.identity) else {
+                return Some(json!({ "code": 0, "msg": "Solver not registered" }));
+            };
+            if let Err(e) = verify_keccak256_signature(&message, identity) {
+                return Some(json!({ "code": 0, "msg": format!("Bid rejected: {e}") }));
+            }
+
+            let msg = &message["msg"];
+            let intent_id = msg["intent_id"].as_str().unwrap_or_default().to_string();
+            let amount = msg["amount"]
+                .as_str()
+                .and_then(|amount| U256::from_dec_str(amount).ok());
+            match amount {
+                Some(amount) if state.open_auctions.contains(&intent_id) => {
+                    state.bids.entry(intent_id).or_default().push((solver_id, amount));
+                    None
+                }
+                Some(_) => Some(json!({ "code": 0, "msg": format!("No open auction for {intent_id}") })),
+                None => Some(json!({ "code": 0, "msg": "Invalid bid amount" })),
+            }
+        }
+        _ => Some(json!({ "code": 0, "msg": "Unknown code" })),
+    }
+}
+
+async fn handle_connection(state: SharedState, stream: TcpStream) {
+    let peer = stream
+        .peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_default();
+    let ws_stream = match accept_async(stream).await {
+        Ok(ws_stream) => ws_stream,
+        Err(e) => {
+            eprintln!("Websocket handshake with {peer} failed: {e}");
+            return;
+        }
+    };
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+
+    // everything sent to this solver, by us or by the auction runner
+    let (sender, mut outbox) = mpsc::unbounded_channel::<Message>();
+    tokio::spawn(async move {
+        while let Some(message) = outbox.recv().await {
+            if ws_sender.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut registered_as = None;
+    while let Some(Ok(message)) = ws_receiver.next().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+
+        let message: Value = match serde_json::from_str(&text) {
+            Ok(message) => message,
+            Err(e) => {
+                let _ = sender.send(Message::text(
+                    json!({ "code": 0, "msg": format!("Invalid JSON: {e}") }).to_string(),
+                ));
+                continue;
+            }
+        };
+        record("in", &peer, &message);
+
+        if let Some(response) =
+            handle_message(&state, &peer, message, &sender, &mut registered_as).await
+        {
+            record("out", &peer, &response);
+            let _ = sender.send(Message::text(response.to_string()));
+        }
+    }
+
+    if let Some(solver_id) = registered_as {
+        println!("Solver {solver_id} disconnected");
+        state.lock().await.solvers.remove(&solver_id);
+    }
+}
+
+async fn broadcast(state: &SharedState, message: &Value) {
+    let state = state.lock().await;
+    for (solver_id, solver) in state.solvers.iter() {
+        record("out", solver_id, message);
+        let _ = solver.sender.send(Message::text(message.to_string()));
+    }
+}
+
+async fn run_auction(state: &SharedState, scripted: &ScriptedIntent, key: Option<&LocalWallet>) {
+    let mut intent = scripted.intent.clone();
+    if let Some(secs) = scripted.timeout_in_secs {
+        intent["inputs"]["SwapTransfer"]["timeout"] = Value::String((now_secs() + secs).to_string());
+    }
+
+    state.lock().await.open_auctions.push(scripted.intent_id.clone());
+    let message = sign(
+        json!({
+            "code": 1,
+            "msg": {
+                "intent_id": scripted.intent_id,
+                "intent": intent,
+            }
+        }),
+        key,
+    )
+    .await;
+    println!("Auction for {} opened", scripted.intent_id);
+    broadcast(state, &message).await;
+
+    tokio::time::sleep(Duration::from_secs(scripted.auction_secs)).await;
+
+    let mut state = state.lock().await;
+    state.open_auctions.retain(|intent_id| *intent_id != scripted.intent_id);
+    let bids = state.bids.remove(&scripted.intent_id).unwrap_or_default();
+
+    let winner = match scripted.outcome {
+        Outcome::BestBid => bids.iter().max_by_key(|(_, amount)| *amount),
+        Outcome::FirstBid => bids.first(),
+        Outcome::AllLose => None,
+    }
+    .map(|(solver_id, amount)| (solver_id.clone(), *amount));
+    println!("Auction for {} settled, winner {:?}", scripted.intent_id, winner);
+
+    for (solver_id, amount) in bids.iter() {
+        let won = winner.as_ref().map_or(false, |(winner, _)| winner == solver_id);
+        let message = sign(
+            json!({
+                "code": 4,
+                "msg": {
+                    "intent_id": scripted.intent_id,
+                    "amount": amount.to_string(),
+                    "msg": if won { "You won this auction!" } else { "You lost this auction" },
+                }
+            }),
+            key,
+        )
+        .await;
+
+        if let Some(solver) = state.solvers.get(solver_id) {
+            record("out", solver_id, &message);
+            let _ = solver.sender.send(Message::text(message.to_string()));
+        }
+    }
+}
+
+async fn run_script(state: SharedState, script: Script, key: Option<LocalWallet>) {
+    while state.lock().await.solvers.len() < script.min_solvers {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    // auctions overlap like on the live endpoint, each runs on its own
+    let mut auctions = Vec::new();
+    for scripted in script.intents {
+        tokio::time::sleep(Duration::from_secs(scripted.delay_secs)).await;
+        let state = state.clone();
+        let key = key.clone();
+        auctions.push(tokio::spawn(async move {
+            run_auction(&state, &scripted, key.as_ref()).await
+        }));
+    }
+    for auction in auctions {
+        let _ = auction.await;
+    }
+
+    if let Some(secs) = script.exit_after_secs {
+        println!("Script done, exiting in {secs}s");
+        tokio::time::sleep(Duration::from_secs(secs)).await;
+        std::process::exit(0);
+    }
+    println!("Script done");
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    let addr = env::var("MOCK_AUCTIONEER_ADDR").unwrap_or_else(|_| String::from("127.0.0.1:8900"));
+    let script_path = env::var("MOCK_AUCTIONEER_SCRIPT")
+        .unwrap_or_else(|_| String::from("mock_auctioneer.example.json"));
+
+    let script: Script = serde_json::from_str(
+        &fs::read_to_string(&script_path)
+            .unwrap_or_else(|e| panic!("Failed to read {script_path}: {e}")),
+    )
+    .unwrap_or_else(|e| panic!("Invalid script {script_path}: {e}"));
+
+    let key = env::var("MOCK_AUCTIONEER_PKEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+        .map(|key| key.parse::<LocalWallet>().expect("MOCK_AUCTIONEER_PKEY must be a private key"));
+    if let Some(key) = &key {
+        println!("Signing as auctioneer {:?}", key.address());
+    }
+
+    let listener = TcpListener::bind(&addr)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to bind {addr}: {e}"));
+    println!("Mock auctioneer listening on ws://{addr}, {} scripted intents", script.intents.len());
+
+    let state = SharedState::default();
+    tokio::spawn(run_script(state.clone(), script, key));
+
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(handle_connection(state.clone(), stream));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+    type Connection = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    const AUCTIONEER_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const SOLVER_KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    async fn send(connection: &mut Connection, message: Value, key: &LocalWallet) {
+        let message = sign(message, Some(key)).await;
+        connection.send(Message::text(message.to_string())).await.unwrap();
+    }
+
+    async fn receive(connection: &mut Connection) -> Value {
+        loop {
+            if let Message::Text(text) = connection.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn registered_solver_bids_and_wins() {
+        env::set_var(
+            "MOCK_AUCTIONEER_RECORD",
+            env::temp_dir().join("mock_auctioneer_test_record.jsonl"),
+        );
+        let auctioneer: LocalWallet = AUCTIONEER_KEY.parse().unwrap();
+        let solver: LocalWallet = SOLVER_KEY.parse().unwrap();
+
+        let state = SharedState::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepting = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(accepting.clone(), stream));
+            }
+        });
+        let (mut connection, _) = connect_async(format!("ws://{addr}")).await.unwrap();
+
+        // no bindings, the ethereum solver address is the identity
+        let registration = json!({
+            "code": 1,
+            "msg": {
+                "solver_id": "solver",
+                "solver_addresses": [
+                    format!("{:?}", solver.address()),
+                    "5zCZ3jk8EZnJyG7fhDqD6tmqiYTLZjik5HUpGMnHrZfC",
+                ],
+            }
+        });
+        send(&mut connection, registration, &solver).await;
+        assert_eq!(receive(&mut connection).await["code"], 3);
+
+        let scripted = ScriptedIntent {
+            intent_id: String::from("1"),
+            intent: json!({}),
+            delay_secs: 0,
+            auction_secs: 1,
+            outcome: Outcome::BestBid,
+            timeout_in_secs: None,
+        };
+        let auction_state = state.clone();
+        let auction_key = auctioneer.clone();
+        let auction = tokio::spawn(async move {
+            run_auction(&auction_state, &scripted, Some(&auction_key)).await
+        });
+
+        let opened = receive(&mut connection).await;
+        assert_eq!(opened["code"], 1);
+        assert_eq!(verify_keccak256_signature(&opened, auctioneer.address()), Ok(()));
+
+        // a bid signed by another key is refused, the signed one counts
+        let bid = json!({
+            "code": 2,
+            "msg": { "solver_id": "solver", "intent_id": "1", "amount": "1000000" }
+        });
+        send(&mut connection, bid.clone(), &auctioneer).await;
+        assert_eq!(receive(&mut connection).await["code"], 0);
+        send(&mut connection, bid, &solver).await;
+
+        let result = receive(&mut connection).await;
+        assert_eq!(result["code"], 4);
+        assert_eq!(result["msg"]["amount"], "1000000");
+        assert_eq!(result["msg"]["msg"], "You won this auction!");
+        assert_eq!(verify_keccak256_signature(&result, auctioneer.address()), Ok(()));
+        auction.await.unwrap();
+    }
+}
@@ -0,0 +1,32 @@
+//! Serves the mock Paraswap, Jupiter and gas oracle APIs of
+//! `example_solver::mock_routers` until killed.
+//!
+//! Point the solver at it with PARASWAP_API_URL="http://127.0.0.1:8901",
+//! QUOTE_API_URL="http://127.0.0.1:8901" and
+//! GAS_ORACLE_URL="http://127.0.0.1:8901/api?module=gastracker&action=gasoracle".
+//!
+//! MOCK_ROUTERS_ADDR        listen address, default 127.0.0.1:8901
+//! MOCK_ROUTERS_FIXTURES    fixtures file, optional:
+//!                          {"default_rate", "price_impact_pct", "rates", "responses"}
+
+use example_solver::mock_routers::{Fixtures, MockRouters};
+use std::env;
+use std::net::SocketAddr;
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    let addr: SocketAddr = env::var("MOCK_ROUTERS_ADDR")
+        .unwrap_or_else(|_| String::from("127.0.0.1:8901"))
+        .parse()
+        .expect("MOCK_ROUTERS_ADDR must be a socket address");
+
+    let fixtures = match env::var("MOCK_ROUTERS_FIXTURES").ok().filter(|path| !path.is_empty()) {
+        Some(path) => Fixtures::from_file(&path).unwrap_or_else(|e| panic!("{e}")),
+        None => Fixtures::default(),
+    };
+
+    let mock = MockRouters::bind(addr, fixtures).unwrap_or_else(|e| panic!("{e}"));
+    println!("Mock routers listening on {}", mock.url());
+    std::future::pending::<()>().await;
+}
@@ -1,10 +1,18 @@
 pub mod ethereum_chain {
-    use crate::chains::get_token_info;
-    use crate::chains::OperationOutput;
+    use crate::chains::{get_token_info, OperationOutput, BRIDGE_TOKENS};
     use crate::env;
     use crate::json;
-    use crate::routers::paraswap::paraswap_router::simulate_swap_paraswap;
-    use crate::routers::paraswap::paraswap_router::ParaswapParams;
+    use crate::inventory::{fills_from_inventory, keeps_token_in, reserved_amount};
+    use crate::risk::record_fill_pnl;
+    use crate::rpc::{ethereum_broadcast, ethereum_wait_for_receipt, RpcFailure, ETHEREUM};
+    use crate::signer::ethereum_signer;
+    use crate::routers::paraswap::paraswap_router::{
+        build_transaction, price, remember_bid_quote, simulate_swap_paraswap, take_bid_quote,
+        ParaswapParams, Side,
+    };
+    use crate::routers::uniswap::uniswap_router::{
+        build_swap as uniswap_build_swap, quote as uniswap_quote,
+    };
     use crate::OperationInput;
     use crate::PostIntentInfo;
     use crate::SOLVER_ADDRESSES;
@@ -12,13 +20,15 @@ pub mod ethereum_chain {
     use ethers::prelude::abigen;
     use ethers::prelude::*;
     use ethers::providers::{Http, Provider};
+    use ethers::types::transaction::eip2718::TypedTransaction;
+    use lazy_static::lazy_static;
     use num_bigint::BigInt;
-    use reqwest::Client;
+    use num_traits::ToPrimitive;
     use serde::Deserialize;
+    use std::collections::HashMap;
     use std::str::FromStr;
     use std::sync::Arc;
-    use std::thread::sleep;
-    use std::time::Duration;
+    use tokio::sync::RwLock;
 
     #[derive(Deserialize)]
     struct GasPrice {
@@ -54,6 +64,17 @@ pub mod ethereum_chain {
             "outputs": [{ "name": "", "type": "bool" }],
             "type": "function"
         },
+        {
+            "constant": false,
+            "inputs": [
+                { "name": "_from", "type": "address" },
+                { "name": "_to", "type": "address" },
+                { "name": "_value", "type": "uint256" }
+            ],
+            "name": "transferFrom",
+            "outputs": [{ "name": "", "type": "bool" }],
+            "type": "function"
+        },
         {
             "constant": false,
             "inputs": [
@@ -63,9 +84,21 @@ pub mod ethereum_chain {
             "name": "approve",
             "outputs": [{ "name": "", "type": "bool" }],
             "type": "function"
+        },
+        {
+            "constant": true,
+            "inputs": [
+                { "name": "_owner", "type": "address" },
+                { "name": "_spender", "type": "address" }
+            ],
+            "name": "allowance",
+            "outputs": [{ "name": "", "type": "uint256" }],
+            "type": "function"
         }]"#
     );
 
+    // `intents` is the getter ethereum_verify_intent reads, its layout is assumed
+    // (MockEscrow.sol copies it) and unconfirmed against the deployed escrow
     abigen!(
         Escrow,
         r#"[{
@@ -89,6 +122,23 @@ pub mod ethereum_chain {
             "payable": true,
             "stateMutability": "payable",
             "type": "function"
+        },
+        {
+            "constant": true,
+            "inputs": [{ "name": "", "type": "uint256" }],
+            "name": "intents",
+            "outputs": [
+                { "name": "tokenIn", "type": "address" },
+                { "name": "amountIn", "type": "uint256" },
+                { "name": "srcUser", "type": "address" },
+                { "name": "dstUser", "type": "string" },
+                { "name": "tokenOut", "type": "string" },
+                { "name": "amountOut", "type": "string" },
+                { "name": "winnerSolver", "type": "string" },
+                { "name": "timeout", "type": "uint256" }
+            ],
+            "stateMutability": "view",
+            "type": "function"
         }]"#
     );
 
@@ -99,36 +149,44 @@ pub mod ethereum_chain {
         ]"#
     );
 
+    lazy_static! {
+        // <token, decimals> decimals never change, no need to ask the RPC twice
+        static ref TOKEN_DECIMALS: Arc<RwLock<HashMap<Address, u8>>> = {
+            let m = HashMap::new();
+            Arc::new(RwLock::new(m))
+        };
+    }
+
     pub const ESCROW_SC_ETHEREUM: &str = "0x59880a68fafcE2E282866bdb741Cf0b20E95c1B7";
-    pub const PARASWAP: &str = "0x216b4b4ba9f3e719726886d34a177484278bfcae";
+    pub const PARASWAP: &str = "0x6a000f20005980200259b80c5102003040001068";
 
     pub async fn handle_ethereum_execution(
         intent: &PostIntentInfo,
         intent_id: U256,
         amount: &str,
-        single_domain: bool
+        single_domain: bool,
+        bridge_token: &str,
     ) -> Result<(), String> {
-        let usdt_contract_address = "0xdac17f958d2ee523a2206206994597c13d831ec7";
-
-        let rpc_url = env::var("ETHEREUM_RPC").expect("ETHEREUM_RPC must be set");
-        let private_key = env::var("ETHEREUM_PKEY").expect("ETHEREUM_PKEY must be set");
+        let rpc_url = ETHEREUM.url()?;
         let target_address: Address = Address::from_str(SOLVER_ADDRESSES.get(0).unwrap()).unwrap();
 
-        let provider = Arc::new(
-            Provider::<Http>::try_from(&rpc_url)
-                .map_err(|e| format!("Failed to create Ethereum provider: {}", e))?,
-        );
+        let provider = ETHEREUM.client()?;
 
-        let usdt_contract = UsdtContract::new(
-            Address::from_str(usdt_contract_address).unwrap(),
-            provider.clone(),
-        );
+        let bridge_token_address = Address::from_str(bridge_token)
+            .map_err(|e| format!("Invalid bridge token {bridge_token}: {}", e))?;
+        let bridge_decimals =
+            get_evm_token_decimals(&ERC20::new(bridge_token_address, provider.clone())).await;
 
-        let balance_ant = usdt_contract
-            .balance_of(target_address)
-            .call()
-            .await
-            .map_err(|e| format!("Failed to get USDT balance: {}", e))?;
+        let bridge_balance = move || {
+            ETHEREUM.with_failover(move |provider| async move {
+                UsdtContract::new(bridge_token_address, provider)
+                    .balance_of(target_address)
+                    .call()
+                    .await
+                    .map_err(|e| RpcFailure::contract("Failed to get bridge token balance", &e))
+            })
+        };
+        let balance_ant = bridge_balance().await?;
 
         let mut token_in = String::default();
         let mut token_out = String::default();
@@ -144,18 +202,35 @@ pub mod ethereum_chain {
             amount_in = transfer_input.amount_in.clone();
         }
 
-        // swap USDT -> token_out
-        if !token_out.eq_ignore_ascii_case(usdt_contract_address) {
-            if let Err(e) = ethereum_trasnfer_swap(&intent_id.to_string(), intent.clone(), amount).await {
+        let direct = !token_out.eq_ignore_ascii_case(bridge_token)
+            && fills_from_inventory(
+                Some(&intent_id.to_string()),
+                "ethereum",
+                &token_out,
+                &BigInt::from_str(amount).map_err(|e| format!("Invalid amount {amount}: {}", e))?,
+            )
+            .await;
+        let keep_token_in = single_domain && keeps_token_in("ethereum", &token_in).await;
+
+        // verifying may have eaten the time we had left. The margin covers the swap
+        // and the send, once we hold token_out we go through with the fill
+        intent
+            .check_timeout(0)
+            .map_err(|e| format!("Aborting intent {intent_id} before swapping: {e}"))?;
+
+        // swap bridge token -> token_out, unless the inventory holds enough token_out
+        if !token_out.eq_ignore_ascii_case(bridge_token) {
+            if direct {
+                println!("Filling intent {intent_id} with {token_out} from inventory");
+            } else if let Err(e) = ethereum_trasnfer_swap(&intent_id.to_string(), intent.clone(), amount, bridge_token).await {
                 return Err(format!(
-                    "Error occurred on Ethereum swap USDT -> token_out (solver must approve USDT to Paraswap 0x216b4b4ba9f3e719726886d34a177484278bfcae first): {}",
+                    "Error occurred on Ethereum swap {bridge_token} -> token_out (solver must approve it to Paraswap 0x6a000f20005980200259b80c5102003040001068 first): {}",
                     e
                 ));
             }
 
             if let Err(e) = approve_erc20(
                 &rpc_url,
-                &private_key,
                 &token_out,
                 ESCROW_SC_ETHEREUM,
                 amount,
@@ -178,7 +253,6 @@ pub mod ethereum_chain {
         // solver -> token_out -> user | user -> token_in -> solver
         if let Err(e) = ethereum_send_funds_to_user(
             &rpc_url,
-            &private_key,
             ESCROW_SC_ETHEREUM,
             intent_id,
             Address::from_str(&token_out).unwrap(),
@@ -190,26 +264,20 @@ pub mod ethereum_chain {
         )
         .await
         {
-            println!("Error occurred on Ethereum send token_out -> user & user sends token_in -> solver (solver must approve USDT to Escrow SC first): {}", e);
+            println!("Error occurred on Ethereum send token_out -> user & user sends token_in -> solver (solver must approve token_out to Escrow SC first): {}", e);
             return Err(e.to_string());
-        // swap token_in -> USDT
-        } else if intent.src_chain == intent.dst_chain
-            && !token_in.eq_ignore_ascii_case(usdt_contract_address)
-        {
+        } else if keep_token_in {
+            println!("Keeping {amount_in} {token_in} of intent {intent_id} as inventory");
+        // swap token_in -> bridge token
+        } else if single_domain && !token_in.eq_ignore_ascii_case(bridge_token) {
             if let Err(e) =
-                approve_erc20(&rpc_url, &private_key, &token_in, PARASWAP, &amount_in).await
+                approve_erc20(&rpc_url, &token_in, PARASWAP, &amount_in).await
             {
                 println!("Error approving {token_in} for solver: {e}");
                 return Err(e.to_string());
             }
 
-            let (token_out, token1_decimals) = match get_token_info("USDT", "ethereum") {
-                Some((token_out, token1_decimals)) => (token_out.to_string(), token1_decimals),
-                None => {
-                    println!("Failed to get token info for USDT on Ethereum");
-                    return Err("Failed to get token info".to_string());
-                }
-            };
+            let (token_out, token1_decimals) = (bridge_token.to_string(), bridge_decimals);
 
             let token0_decimals = get_evm_token_decimals(&ERC20::new(
                 Address::from_str(&token_in).unwrap(),
@@ -218,7 +286,7 @@ pub mod ethereum_chain {
             .await;
 
             let paraswap_params = ParaswapParams {
-                side: "SELL".to_string(),
+                side: Side::Sell,
                 chain_id: 1,
                 amount_in: BigInt::from_str(&amount_in).unwrap(),
                 token_in: Address::from_str(&token_in).unwrap(),
@@ -227,53 +295,208 @@ pub mod ethereum_chain {
                 token1_decimals: token1_decimals as u32,
                 wallet_address: Address::from_str(SOLVER_ADDRESSES.get(0).unwrap()).unwrap(),
                 receiver_address: Address::from_str(SOLVER_ADDRESSES.get(0).unwrap()).unwrap(),
-                client_aggregator: Client::new(),
             };
 
-            let (_res_amount, res_data, res_to) = simulate_swap_paraswap(paraswap_params)
-                .await
-                .map_err(|e| format!("Error simulating Paraswap swap: {}", e))?;
+            let result = match simulate_swap_paraswap(paraswap_params).await {
+                Ok((_res_amount, res_data, res_to, _)) => {
+                    send_tx(res_to, res_data, 1, 500_000, 0, rpc_url.clone()).await
+                }
+                Err(e) => {
+                    println!("Error simulating Paraswap swap: {e}, swapping on Uniswap instead");
+                    uniswap_swap(
+                        &rpc_url,
+                        Address::from_str(&token_in).unwrap(),
+                        Address::from_str(&token_out).unwrap(),
+                        &BigInt::from_str(&amount_in).unwrap(),
+                        &BigInt::from(0),
+                    )
+                    .await
+                }
+            };
 
-            if let Err(e) = send_tx(res_to, res_data, 1, 500_000, 0, rpc_url).await {
+            if let Err(e) = result {
                 println!("Error sending transaction on Ethereum: {}", e);
                 return Err(e.to_string());
             }
         }
 
-        if intent.src_chain == intent.dst_chain {
-            let balance_post = usdt_contract
-                .balance_of(target_address)
-                .call()
-                .await
-                .map_err(|e| format!("Failed to get post-swap USDT balance: {}", e))?;
-
-            let balance = if balance_post >= balance_ant {
-                balance_post - balance_ant
-            } else {
-                balance_ant - balance_post
-            };
+        let balance_post = bridge_balance().await?;
+        let bridge_delta = BigInt::from_str(&balance_post.to_string()).unwrap()
+            - BigInt::from_str(&balance_ant.to_string()).unwrap();
 
+        let pnl = record_fill_pnl(
+            &intent_id.to_string(),
+            bridge_delta,
+            direct,
+            !single_domain || keep_token_in,
+        )
+        .await;
+        if let Some(pnl) = pnl {
             println!(
-                "You have {} {} USDT on intent {intent_id}",
-                if balance_post >= balance_ant {
-                    "won"
-                } else {
-                    "lost"
-                },
-                balance.as_u128() as f64 / 1e6
+                "You have {} {} {bridge_token} on intent {intent_id}",
+                if pnl >= BigInt::from(0) { "won" } else { "lost" },
+                pnl.magnitude().to_f64().unwrap_or_default() / 10f64.powi(bridge_decimals as i32)
             );
         }
 
         Ok(())
     }
 
+    /// Compares the intent escrowed in ESCROW_SC_ETHEREUM with what the auctioneer
+    /// told us, including that we are the recorded winner for `amount`.
+    pub async fn ethereum_verify_intent(
+        intent_id: &str,
+        intent: &PostIntentInfo,
+        amount: &str,
+    ) -> Result<(), String> {
+        let provider = ETHEREUM.client()?;
+        let escrow = Escrow::new(Address::from_str(ESCROW_SC_ETHEREUM).unwrap(), provider);
+
+        let id = U256::from_dec_str(intent_id)
+            .map_err(|e| format!("Invalid ethereum intent_id {intent_id}: {}", e))?;
+        let (
+            escrow_token_in,
+            escrow_amount_in,
+            escrow_src_user,
+            escrow_dst_user,
+            escrow_token_out,
+            escrow_amount_out,
+            escrow_winner,
+            _timeout,
+        ) = escrow
+            .intents(id)
+            .call()
+            .await
+            .map_err(|e| format!("Failed to read intent {intent_id} from escrow: {}", e))?;
+
+        if escrow_token_in == Address::zero() {
+            return Err(format!("intent {intent_id} not found in escrow"));
+        }
+
+        let mut mismatches = Vec::new();
+
+        if let OperationInput::SwapTransfer(input) = &intent.inputs {
+            if Address::from_str(&input.token_in).ok() != Some(escrow_token_in) {
+                mismatches.push(format!("token_in {} != {:?}", input.token_in, escrow_token_in));
+            }
+            if U256::from_dec_str(&input.amount_in).ok() != Some(escrow_amount_in) {
+                mismatches.push(format!("amount_in {} != {}", input.amount_in, escrow_amount_in));
+            }
+            if Address::from_str(&input.src_chain_user).ok() != Some(escrow_src_user) {
+                mismatches.push(format!(
+                    "src_chain_user {} != {:?}",
+                    input.src_chain_user, escrow_src_user
+                ));
+            }
+        }
+        if let OperationOutput::SwapTransfer(output) = &intent.outputs {
+            if !output.token_out.eq_ignore_ascii_case(&escrow_token_out) {
+                mismatches.push(format!("token_out {} != {}", output.token_out, escrow_token_out));
+            }
+            if !output.dst_chain_user.eq_ignore_ascii_case(&escrow_dst_user) {
+                mismatches.push(format!(
+                    "dst_chain_user {} != {}",
+                    output.dst_chain_user, escrow_dst_user
+                ));
+            }
+        }
+        if !SOLVER_ADDRESSES
+            .iter()
+            .any(|solver| solver.eq_ignore_ascii_case(&escrow_winner))
+        {
+            mismatches.push(format!("winner {escrow_winner} is not us"));
+        }
+        if escrow_amount_out != amount {
+            mismatches.push(format!("amount_out {amount} != {escrow_amount_out}"));
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "intent {intent_id} does not match escrow: {}",
+                mismatches.join(", ")
+            ))
+        }
+    }
+
+    /// Dry run of a fill through eth_call, nothing is broadcast: the bridge token
+    /// -> token_out swap, or the escrow pulling token_out from us when no swap
+    /// is needed (which needs our balance and approval). Returns the token_out
+    /// `bridge_amount` buys at the current route, itself when no swap is needed.
+    pub async fn ethereum_simulate_fill(
+        intent: &PostIntentInfo,
+        amount: &str,
+        bridge_token: &str,
+        bridge_amount: &BigInt,
+    ) -> Result<BigInt, String> {
+        let provider = ETHEREUM.client()?;
+        let solver = ethereum_signer()?.address();
+
+        let OperationOutput::SwapTransfer(output) = &intent.outputs else {
+            return Err("only swap transfers can be simulated".to_string());
+        };
+        let token_out = Address::from_str(&output.token_out)
+            .map_err(|e| format!("Invalid token_out {}: {}", output.token_out, e))?;
+
+        if output.token_out.eq_ignore_ascii_case(bridge_token) {
+            let dst_user = Address::from_str(&output.dst_chain_user)
+                .map_err(|e| format!("Invalid dst_chain_user {}: {}", output.dst_chain_user, e))?;
+            let amount =
+                U256::from_dec_str(amount).map_err(|e| format!("Invalid amount {amount}: {}", e))?;
+
+            return ERC20::new(token_out, provider)
+                .transfer_from(solver, dst_user, amount)
+                .from(Address::from_str(ESCROW_SC_ETHEREUM).unwrap())
+                .call()
+                .await
+                .map(|_| bridge_amount.clone())
+                .map_err(|e| format!("Escrow can't take {amount} {:?} from us: {}", token_out, e));
+        }
+
+        let token_in = Address::from_str(bridge_token)
+            .map_err(|e| format!("Invalid bridge token {bridge_token}: {}", e))?;
+        let token0_decimals =
+            get_evm_token_decimals(&ERC20::new(token_in, provider.clone())).await;
+        let token1_decimals =
+            get_evm_token_decimals(&ERC20::new(token_out, provider.clone())).await;
+
+        let (amount_out, data, to, _) = simulate_swap_paraswap(ParaswapParams {
+            side: Side::Sell,
+            chain_id: 1,
+            amount_in: bridge_amount.clone(),
+            token_in,
+            token_out,
+            token0_decimals: token0_decimals as u32,
+            token1_decimals: token1_decimals as u32,
+            wallet_address: solver,
+            receiver_address: solver,
+        })
+        .await
+        .map_err(|e| format!("Failed to build swap: {}", e))?;
+
+        if amount_out < BigInt::from_str(amount).map_err(|e| format!("Invalid amount: {}", e))? {
+            return Err(format!("swap returns {amount_out} token_out, {amount} promised"));
+        }
+
+        let data = hex::decode(data.trim_start_matches("0x"))
+            .map_err(|e| format!("Failed to decode data: {}", e))?;
+        let tx: TypedTransaction = TransactionRequest::new().from(solver).to(to).data(data).into();
+
+        provider
+            .call(&tx, None)
+            .await
+            .map(|_| amount_out)
+            .map_err(|e| format!("Swap reverts: {}", e))
+    }
+
     pub async fn ethereum_trasnfer_swap(
         intent_id: &str,
         intent: PostIntentInfo,
         amount: &str,
+        bridge_token: &str,
     ) -> Result<(), String> {
-        let client_rpc =
-            env::var("ETHEREUM_RPC").map_err(|e| format!("ETHEREUM_RPC must be set: {}", e))?;
+        let client_rpc = ETHEREUM.url()?;
         let mut token_out = String::default();
 
         match intent.function_name.as_str() {
@@ -284,8 +507,6 @@ pub mod ethereum_chain {
 
                 match transfer_erc20(
                     &client_rpc,
-                    &env::var("ETHEREUM_PKEY")
-                        .map_err(|e| format!("ETHEREUM_PKEY must be set: {}", e))?,
                     &token_out,
                     SOLVER_ADDRESSES.get(0).unwrap(),
                     &amount.to_string(),
@@ -317,16 +538,19 @@ pub mod ethereum_chain {
                 }
             }
             "swap" => {
-                let (token_in, token0_decimals) = get_token_info("USDT", "ethereum")
-                    .ok_or_else(|| "Failed to get token info".to_string())?;
-
                 if let OperationOutput::SwapTransfer(transfer_output) = &intent.outputs {
                     token_out = transfer_output.token_out.clone();
                 }
 
-                let provider = Provider::<Http>::try_from(client_rpc.replace("wss", "https"))
-                    .map_err(|e| format!("Failed to create provider: {}", e))?;
-                let provider = Arc::new(provider);
+                let provider = ETHEREUM.client_for(&client_rpc)?;
+
+                let token_in = bridge_token;
+                let token0_decimals = get_evm_token_decimals(&ERC20::new(
+                    Address::from_str(token_in)
+                        .map_err(|e| format!("Invalid bridge token address: {}", e))?,
+                    provider.clone(),
+                ))
+                .await;
 
                 let token1_decimals = get_evm_token_decimals(&ERC20::new(
                     Address::from_str(&token_out)
@@ -335,27 +559,67 @@ pub mod ethereum_chain {
                 ))
                 .await;
 
-                let paraswap_params = ParaswapParams {
-                    side: "BUY".to_string(),
+                let amount_out =
+                    BigInt::from_str(amount).map_err(|e| format!("Invalid amount: {}", e))?;
+                let token_in = Address::from_str(token_in)
+                    .map_err(|e| format!("Invalid token_in address: {}", e))?;
+                let token_out = Address::from_str(&token_out)
+                    .map_err(|e| format!("Invalid token_out address: {}", e))?;
+
+                let mut paraswap_params = ParaswapParams {
+                    side: Side::Buy,
                     chain_id: 1,
-                    amount_in: BigInt::from_str(amount)
-                        .map_err(|e| format!("Invalid amount: {}", e))?,
-                    token_in: Address::from_str(&token_in)
-                        .map_err(|e| format!("Invalid token_in address: {}", e))?,
-                    token_out: Address::from_str(&token_out)
-                        .map_err(|e| format!("Invalid token_out address: {}", e))?,
+                    amount_in: amount_out.clone(),
+                    token_in,
+                    token_out,
                     token0_decimals: token0_decimals as u32,
                     token1_decimals: token1_decimals as u32,
                     wallet_address: Address::from_str(SOLVER_ADDRESSES.get(0).unwrap())
                         .map_err(|e| format!("Invalid wallet address: {}", e))?,
                     receiver_address: Address::from_str(SOLVER_ADDRESSES.get(0).unwrap())
                         .map_err(|e| format!("Invalid receiver address: {}", e))?,
-                    client_aggregator: Client::new(),
                 };
 
-                let (_res_amount, res_data, res_to) = simulate_swap_paraswap(paraswap_params)
-                    .await
-                    .map_err(|e| format!("Failed to simulate swap: {}", e))?;
+                // sell the bridge amount we bid with along the route we quoted, as
+                // long as it still covers what we promised
+                let bridge_amount = reserved_amount(intent_id, bridge_token).await;
+                let bid_quote = match bridge_amount.clone() {
+                    Some(bridge_amount) => take_bid_quote(token_in, token_out, &bridge_amount)
+                        .await
+                        .filter(|quote| quote.amount >= amount_out)
+                        .map(|quote| (bridge_amount, quote)),
+                    None => None,
+                };
+
+                let swap = match bid_quote {
+                    Some((bridge_amount, quote)) => {
+                        paraswap_params.side = Side::Sell;
+                        paraswap_params.amount_in = bridge_amount;
+                        build_transaction(&paraswap_params, &quote, &amount_out)
+                            .await
+                            .map_err(|e| format!("Failed to build swap: {}", e))
+                    }
+                    None => simulate_swap_paraswap(paraswap_params)
+                        .await
+                        .map(|(_res_amount, res_data, res_to, _)| (res_data, res_to))
+                        .map_err(|e| format!("Failed to simulate swap: {}", e)),
+                };
+
+                let (res_data, res_to) = match (swap, bridge_amount) {
+                    (Ok(swap), _) => swap,
+                    (Err(e), Some(bridge_amount)) => {
+                        println!("{e}, swapping on Uniswap instead");
+                        return uniswap_swap(
+                            &client_rpc,
+                            token_in,
+                            token_out,
+                            &bridge_amount,
+                            &amount_out,
+                        )
+                        .await;
+                    }
+                    (Err(e), None) => return Err(e),
+                };
 
                 let tx_hash = send_tx(res_to, res_data, 1, 500_000, 0, client_rpc).await;
 
@@ -375,17 +639,12 @@ pub mod ethereum_chain {
 
     async fn transfer_erc20(
         provider_url: &str,
-        private_key: &str,
         token_address: &str,
         recipient_address: &str,
         amount: &str,
     ) -> Result<TxHash, Box<dyn std::error::Error>> {
-        let provider = Provider::<Http>::try_from(provider_url)?;
-        let provider = Arc::new(provider);
-
-        let wallet: LocalWallet = private_key.parse()?;
-        let wallet = wallet.with_chain_id(1u64); // Mainnet
-        let wallet = Arc::new(SignerMiddleware::new(provider.clone(), wallet));
+        let provider = ETHEREUM.client_for(provider_url)?;
+        let wallet = Arc::new(SignerMiddleware::new(provider.clone(), ethereum_signer()?));
 
         let token_address = token_address.parse::<Address>()?;
         let erc20 = ERC20::new(token_address, wallet.clone());
@@ -407,19 +666,15 @@ pub mod ethereum_chain {
         value: u128,
         url: String,
     ) -> Result<(), String> {
-        let prvk = match env::var("ETHEREUM_PKEY") {
-            Ok(key) => match secp256k1::SecretKey::from_str(&key) {
-                Ok(prvk) => prvk,
-                Err(e) => return Err(format!("Failed to parse private key: {}", e)),
-            },
-            Err(_) => return Err("ETHEREUM_PKEY environment variable is not set".to_string()),
-        };
+        let signer = ethereum_signer()?;
 
         // Get gas
-        let response =
-            reqwest::get("https://api.etherscan.io/api?module=gastracker&action=gasoracle")
-                .await
-                .map_err(|e| format!("Failed to fetch gas price: {}", e))?;
+        let gas_oracle_url = env::var("GAS_ORACLE_URL").unwrap_or_else(|_| {
+            String::from("https://api.etherscan.io/api?module=gastracker&action=gasoracle")
+        });
+        let response = reqwest::get(gas_oracle_url)
+            .await
+            .map_err(|e| format!("Failed to fetch gas price: {}", e))?;
 
         let gas_response: GasResponse = response
             .json::<GasResponse>()
@@ -440,65 +695,93 @@ pub mod ethereum_chain {
         let priority_fee_per_gas: u128 = 2_000_000_000; // This is already in wei
         let max_fee_per_gas = base_fee_per_gas + priority_fee_per_gas;
 
-        // EIP-1559 transaction
-        let tx_object = web3::types::TransactionParameters {
-            to: Some(to),
-            gas: U256::from(gas),
-            value: U256::from(value),
-            data: web3::types::Bytes::from(
-                hex::decode(&data[2..]).map_err(|e| format!("Failed to decode data: {}", e))?,
-            ),
-            chain_id: Some(chain_id),
-            transaction_type: Some(web3::types::U64::from(2)),
-            access_list: None,
-            max_fee_per_gas: Some(U256::from(max_fee_per_gas)),
-            max_priority_fee_per_gas: Some(U256::from(priority_fee_per_gas)),
-            ..Default::default()
-        };
+        let from = signer.address();
+        let nonce = ETHEREUM
+            .with_failover_for(&url, |provider| async move {
+                provider
+                    .get_transaction_count(from, None)
+                    .await
+                    .map_err(|e| RpcFailure::ethereum("Failed to fetch nonce", &e))
+            })
+            .await?;
 
-        let web3_query = web3::Web3::new(
-            web3::transports::Http::new(&url)
-                .map_err(|e| format!("Failed to create HTTP transport: {}", e))?,
-        );
+        // EIP-1559 transaction
+        let tx_object: TypedTransaction = Eip1559TransactionRequest::new()
+            .from(signer.address())
+            .to(to)
+            .gas(gas)
+            .value(value)
+            .data(hex::decode(&data[2..]).map_err(|e| format!("Failed to decode data: {}", e))?)
+            .chain_id(chain_id)
+            .nonce(nonce)
+            .max_fee_per_gas(max_fee_per_gas)
+            .max_priority_fee_per_gas(priority_fee_per_gas)
+            .into();
+
+        let receipt = sign_and_broadcast(&tx_object).await?;
+        if receipt.status == Some(U64::from(1)) {
+            Ok(())
+        } else {
+            Err("Transaction failed".to_string())
+        }
+    }
 
-        let signed = web3_query
-            .accounts()
-            .sign_transaction(tx_object, &prvk)
+    // signs `tx` once and gives the raw transaction to every broadcast endpoint:
+    // sending it again can't pay twice, the nonce is part of the signature
+    async fn sign_and_broadcast(tx: &TypedTransaction) -> Result<TransactionReceipt, String> {
+        let signature = ethereum_signer()?
+            .sign_transaction(tx)
             .await
             .map_err(|e| format!("Failed to sign transaction: {}", e))?;
 
-        let tx_hash = web3_query
-            .eth()
-            .send_raw_transaction(signed.raw_transaction)
+        // every broadcast endpoint gets the transaction, the fastest one wins
+        let tx_hash = ethereum_broadcast(tx.rlp_signed(&signature))
             .await
             .map_err(|e| format!("Failed to send transaction: {}", e))?;
 
-        // println!("Transaction hash: {:?}", tx_hash);
-
-        // Poll for the transaction receipt
-        loop {
-            match web3_query.eth().transaction_receipt(tx_hash).await {
-                Ok(Some(receipt)) => {
-                    if receipt.status == Some(U64::from(1)) {
-                        // println!("Transaction confirmed: {:?}", receipt);
-                        return Ok(());
-                    } else {
-                        return Err("Transaction failed".to_string());
-                    }
-                }
-                Ok(None) => {
-                    // Receipt is not yet available, continue polling
-                    //println!("Transaction pending...");
-                    sleep(Duration::from_secs(5));
+        ethereum_wait_for_receipt(tx_hash).await
+    }
+
+    // `tx` from the solver with its nonce, fees and gas filled in, read with
+    // failover: a reverting gas estimate is the same on every endpoint
+    async fn fill_transaction(
+        provider_url: &str,
+        mut tx: TypedTransaction,
+    ) -> Result<TypedTransaction, String> {
+        let signer = ethereum_signer()?;
+        let from = signer.address();
+        tx.set_from(from);
+        tx.set_chain_id(signer.chain_id());
+
+        ETHEREUM
+            .with_failover_for(provider_url, |provider| {
+                let mut tx = tx.clone();
+                async move {
+                    let nonce = provider
+                        .get_transaction_count(from, None)
+                        .await
+                        .map_err(|e| RpcFailure::ethereum("Failed to fetch nonce", &e))?;
+                    tx.set_nonce(nonce);
+                    provider
+                        .fill_transaction(&mut tx, None)
+                        .await
+                        .map_err(|e| RpcFailure::ethereum("Failed to prepare transaction", &e))?;
+                    Ok(tx)
                 }
-                Err(e) => return Err(format!("Error while fetching transaction receipt: {}", e)),
-            }
-        }
+            })
+            .await
     }
 
     pub async fn get_evm_token_decimals(erc20: &ERC20<Provider<Http>>) -> u8 {
+        if let Some(decimals) = TOKEN_DECIMALS.read().await.get(&erc20.address()) {
+            return *decimals;
+        }
+
         match erc20.decimals().call().await {
-            Ok(decimals) => decimals,
+            Ok(decimals) => {
+                TOKEN_DECIMALS.write().await.insert(erc20.address(), decimals);
+                decimals
+            }
             Err(e) => {
                 eprintln!("Error getting decimals: {}", e);
                 0
@@ -506,16 +789,19 @@ pub mod ethereum_chain {
         }
     }
 
+    // returns (amount_out, price_impact_pct)
     pub async fn ethereum_simulate_swap(
         token_in: &str,
         amount_in: &str,
         token_out: &str,
-    ) -> BigInt {
-        let rpc_url = env::var("ETHEREUM_RPC").expect("ETHEREUM_RPC must be set");
-        let provider = Provider::<Http>::try_from(rpc_url)
-            .map_err(|e| e.to_string())
-            .unwrap();
-        let provider = Arc::new(provider);
+    ) -> (BigInt, f64) {
+        let provider = match ETHEREUM.client() {
+            Ok(provider) => provider,
+            Err(e) => {
+                eprintln!("{e}");
+                return (BigInt::from(0), 0f64);
+            }
+        };
 
         let token_in = Address::from_str(token_in).unwrap();
         let token_out = Address::from_str(token_out).unwrap();
@@ -524,7 +810,7 @@ pub mod ethereum_chain {
             get_evm_token_decimals(&ERC20::new(token_out, provider.clone())).await;
 
         let paraswap_params = ParaswapParams {
-            side: "SELL".to_string(),
+            side: Side::Sell,
             chain_id: 1,
             amount_in: BigInt::from_str(amount_in).unwrap(),
             token_in: token_in,
@@ -533,16 +819,120 @@ pub mod ethereum_chain {
             token1_decimals: token1_decimals as u32,
             wallet_address: Address::from_str(SOLVER_ADDRESSES.get(0).unwrap()).unwrap(),
             receiver_address: Address::from_str(SOLVER_ADDRESSES.get(0).unwrap()).unwrap(),
-            client_aggregator: Client::new(),
         };
 
-        let (_res_amount, _, _) = simulate_swap_paraswap(paraswap_params).await.unwrap();
-        _res_amount
+        // bidding only needs the price, the transaction is built if we win
+        let paraswap = price(&paraswap_params).await;
+
+        // ONCHAIN_QUOTER: "fallback" asks the pools only when Paraswap fails,
+        // "check" also compares every Paraswap quote against them, "off"
+        let onchain_quoter =
+            env::var("ONCHAIN_QUOTER").unwrap_or_else(|_| String::from("fallback"));
+        let onchain = match (onchain_quoter.as_str(), &paraswap) {
+            ("off", _) | ("fallback", Ok(_)) => None,
+            _ => match uniswap_quote(provider, token_in, token_out, &paraswap_params.amount_in).await {
+                Ok(onchain) => Some(onchain),
+                Err(e) => {
+                    eprintln!("Failed to get Uniswap quote: {e}");
+                    None
+                }
+            },
+        };
+
+        // only the leg selling a bridge token is built from its bid quote, the
+        // source leg is swapped at execution with a quote of its own
+        let sells_bridge_token = BRIDGE_TOKENS.iter().any(|token| {
+            get_token_info(token, "ethereum")
+                .and_then(|(address, _)| Address::from_str(address).ok())
+                == Some(token_in)
+        });
+
+        match (paraswap, onchain) {
+            (Ok(quote), Some(onchain)) => {
+                let max_deviation_bps = env::var("ONCHAIN_QUOTE_MAX_DEVIATION_BPS")
+                    .ok()
+                    .and_then(|bps| bps.parse::<u32>().ok())
+                    .unwrap_or(500);
+                let max_amount = &onchain.amount_out * BigInt::from(10_000 + max_deviation_bps)
+                    / BigInt::from(10_000);
+
+                // an aggregator promising much more than the pools is more likely
+                // a bad quote than a better route, bid what the pools give
+                if quote.amount > max_amount {
+                    println!(
+                        "Paraswap quote {} is more than {max_deviation_bps} bps above Uniswap {} ({:?}), using Uniswap",
+                        quote.amount, onchain.amount_out, onchain.pool
+                    );
+                    return (onchain.amount_out, quote.price_impact_pct);
+                }
+
+                if sells_bridge_token {
+                    remember_bid_quote(&paraswap_params, &quote).await;
+                }
+                (quote.amount, quote.price_impact_pct)
+            }
+            (Ok(quote), None) => {
+                if sells_bridge_token {
+                    remember_bid_quote(&paraswap_params, &quote).await;
+                }
+                (quote.amount, quote.price_impact_pct)
+            }
+            (Err(e), Some(onchain)) => {
+                eprintln!("Failed to get Paraswap price: {e}, using Uniswap quote");
+                // pools don't report USD values, so no price impact either
+                (onchain.amount_out, 0f64)
+            }
+            (Err(e), None) => {
+                eprintln!("Failed to get Paraswap price: {e}");
+                (BigInt::from(0), 0f64)
+            }
+        }
+    }
+
+    /// Swaps straight on Uniswap, for when Paraswap can't build the swap. The swap
+    /// reverts below the quote minus UNISWAP_SLIPPAGE_BPS, or below `min_amount_out`
+    /// when that is higher (what we promised the user).
+    pub async fn uniswap_swap(
+        rpc_url: &str,
+        token_in: Address,
+        token_out: Address,
+        amount_in: &BigInt,
+        min_amount_out: &BigInt,
+    ) -> Result<(), String> {
+        let provider = ETHEREUM.client_for(rpc_url)?;
+
+        let quote = uniswap_quote(provider.clone(), token_in, token_out, amount_in).await?;
+        if quote.amount_out < *min_amount_out {
+            return Err(format!(
+                "Uniswap only gives {} of the {} needed",
+                quote.amount_out, min_amount_out
+            ));
+        }
+        let min_amount_out = quote.min_amount_out().max(min_amount_out.clone());
+
+        approve_erc20(
+            rpc_url,
+            &format!("{:?}", token_in),
+            &format!("{:?}", quote.router()),
+            &amount_in.to_string(),
+        )
+        .await?;
+
+        let (data, to) = uniswap_build_swap(
+            provider,
+            &quote,
+            token_in,
+            token_out,
+            amount_in,
+            &min_amount_out,
+            Address::from_str(SOLVER_ADDRESSES.get(0).unwrap()).unwrap(),
+        )?;
+
+        send_tx(to, data, 1, 500_000, 0, rpc_url.to_string()).await
     }
 
     pub async fn ethereum_send_funds_to_user(
         provider_url: &str,
-        private_key: &str,
         contract_address: &str,
         intent_id: U256,
         token_out: Address,
@@ -552,15 +942,7 @@ pub mod ethereum_chain {
         solver_out: &str,
         value_in_wei: U256
     ) -> Result<TransactionReceipt, Box<dyn std::error::Error>> {
-        let provider = Provider::<Http>::try_from(provider_url)?;
-        let provider = Arc::new(provider);
-    
-        let wallet: LocalWallet = private_key.parse()?;
-        let wallet = wallet.with_chain_id(1u64); // Mainnet
-        let wallet = Arc::new(SignerMiddleware::new(provider.clone(), wallet));
-    
         let contract_address = contract_address.parse::<Address>()?;
-        let contract = Escrow::new(contract_address, wallet.clone());
     
         let solver_transfer_data = (
             intent_id,
@@ -571,43 +953,52 @@ pub mod ethereum_chain {
             solver_out.to_string()
         );
     
-        // let gas_price = provider.get_gas_price().await.unwrap();
-        let contract = contract
+        // signed once, then sent to every broadcast endpoint
+        let tx = Escrow::new(contract_address, ETHEREUM.client_for(provider_url)?)
             .send_funds_to_user(solver_transfer_data)
-            .value(value_in_wei);
-            // .gas_price(gas_price);  // Set the gas price
-    
-        let pending_tx = contract.send().await?;
-    
-        let tx_receipt = pending_tx
-            .await?
-            .expect("Failed to fetch transaction receipt");
+            .value(value_in_wei)
+            .tx;
+        let tx = fill_transaction(provider_url, tx).await?;
+        let tx_receipt = sign_and_broadcast(&tx).await?;
+        if tx_receipt.status != Some(U64::from(1)) {
+            return Err(format!("Transaction {:?} failed", tx_receipt.transaction_hash).into());
+        }
     
         Ok(tx_receipt)
     }
     
 
+    /// How much of `token_address` `spender_address` may move from the solver.
+    pub async fn erc20_allowance(token_address: &str, spender_address: &str) -> Result<U256, String> {
+        let owner = Address::from_str(SOLVER_ADDRESSES.get(0).unwrap())
+            .map_err(|e| format!("Invalid Ethereum solver address: {}", e))?;
+        let token_address = token_address
+            .parse::<Address>()
+            .map_err(|e| format!("Failed to parse token address: {}", e))?;
+        let spender: Address = spender_address
+            .parse::<Address>()
+            .map_err(|e| format!("Failed to parse spender address: {}", e))?;
+
+        ETHEREUM
+            .with_failover(|provider| async move {
+                ERC20::new(token_address, provider)
+                    .allowance(owner, spender)
+                    .call()
+                    .await
+                    .map_err(|e| RpcFailure::contract("Failed to get allowance", &e))
+            })
+            .await
+    }
+
     pub async fn approve_erc20(
         provider_url: &str,
-        private_key: &str,
         token_address: &str,
         spender_address: &str,
         amount: &str,
     ) -> Result<(), String> {
-        let provider = Provider::<Http>::try_from(provider_url)
-            .map_err(|e| format!("Failed to create provider: {}", e))?;
-        let provider = Arc::new(provider);
-
-        let wallet: LocalWallet = private_key
-            .parse()
-            .map_err(|e| format!("Failed to parse private key: {}", e))?;
-        let wallet = wallet.with_chain_id(1u64); // Mainnet
-        let wallet = Arc::new(SignerMiddleware::new(provider.clone(), wallet));
-
         let token_address = token_address
             .parse::<Address>()
             .map_err(|e| format!("Failed to parse token address: {}", e))?;
-        let erc20 = ERC20::new(token_address, wallet.clone());
 
         let spender: Address = spender_address
             .parse::<Address>()
@@ -615,15 +1006,17 @@ pub mod ethereum_chain {
         let amount =
             U256::from_dec_str(amount).map_err(|e| format!("Failed to parse amount: {}", e))?;
 
-        let tx = erc20.approve(spender, amount);
-        let pending_tx = tx
-            .send()
-            .await
-            .map_err(|e| format!("Failed to send transaction: {}", e))?;
-
-        pending_tx
-            .await
-            .map_err(|e| format!("Transaction failed: {}", e))?;
+        let tx = ERC20::new(token_address, ETHEREUM.client_for(provider_url)?)
+            .approve(spender, amount)
+            .tx;
+        let tx = fill_transaction(provider_url, tx).await?;
+        let receipt = sign_and_broadcast(&tx).await?;
+        if receipt.status != Some(U64::from(1)) {
+            return Err(format!(
+                "Transaction failed: approve {:?} reverted",
+                receipt.transaction_hash
+            ));
+        }
 
         Ok(())
     }
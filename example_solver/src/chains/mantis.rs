@@ -1,10 +1,10 @@
 pub mod mantis_chain {
     use crate::chains::solana::solana_chain::solana_send_funds_to_user;
     use crate::chains::*;
+    use crate::rpc::MANTIS;
     use crate::PostIntentInfo;
     use serde::{Deserialize, Serialize};
     use solana_sdk::pubkey::Pubkey;
-    use std::env;
     use std::str::FromStr;
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -27,7 +27,7 @@ pub mod mantis_chain {
         intent_info: &PostIntentInfo,
         intent_id: &str
     ) -> Result<(), String> {
-        let rpc_url = env::var("MANTIS_RPC").expect("MANTIS_RPC must be set");
+        let rpc_url = MANTIS.url()?;
 
         let mut user = String::default();
         let mut token_in = String::default();
@@ -6,15 +6,20 @@ use lazy_static::lazy_static;
 use std::collections::HashMap;
 
 use crate::env;
+use crate::signer::{
+    ethereum_signer, identity_signer, previous_identity_signer, solana_signer, EthereumSigner,
+};
 use ethers::prelude::*;
-use ethers::signers::LocalWallet;
 use ethers::utils::hash_message;
 use ethers::utils::keccak256;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signer::Signer as _;
 use std::error::Error;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use strum_macros::EnumString;
 use tokio::sync::RwLock;
 
@@ -84,6 +89,63 @@ pub struct PostIntentInfo {
     pub outputs: OperationOutput,
 }
 
+impl PostIntentInfo {
+    /// Deadline of the intent, a unix timestamp in seconds like the escrows
+    /// compare it to. Anything past year 2286 isn't seconds and is refused.
+    pub fn timeout(&self) -> Option<u64> {
+        let OperationInput::SwapTransfer(input) = &self.inputs else {
+            return None;
+        };
+
+        input
+            .timeout
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|timeout| *timeout < 10_000_000_000)
+    }
+
+    /// Errors when the intent expires before `extra_secs` + the dst_chain
+    /// TIMEOUT_MARGIN_* from now, i.e. we could not safely fill it in time.
+    pub fn check_timeout(&self, extra_secs: u64) -> Result<(), String> {
+        let timeout = self
+            .timeout()
+            .ok_or_else(|| "intent has no valid timeout (unix seconds)".to_string())?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let needed = timeout_margin_secs(&self.dst_chain) + extra_secs;
+
+        if now >= timeout {
+            Err(format!("intent expired {}s ago", now - timeout))
+        } else if timeout - now < needed {
+            Err(format!(
+                "intent expires in {}s, {}s needed on {}",
+                timeout - now,
+                needed,
+                self.dst_chain
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Seconds we need on `chain` between winning an intent and the fill landing
+/// on-chain, configurable with TIMEOUT_MARGIN_ETHEREUM / _SOLANA / _MANTIS.
+pub fn timeout_margin_secs(chain: &str) -> u64 {
+    let default = match chain {
+        "ethereum" => 120,
+        _ => 30,
+    };
+
+    env::var(format!("TIMEOUT_MARGIN_{}", chain.to_uppercase()))
+        .ok()
+        .and_then(|margin| margin.parse::<u64>().ok())
+        .unwrap_or(default)
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, EnumString, Serialize, Deserialize)]
 #[strum(serialize_all = "lowercase")]
 enum Blockchain {
@@ -95,6 +157,7 @@ enum Blockchain {
 #[strum(serialize_all = "UPPERCASE")]
 enum Token {
     USDT,
+    USDC,
 }
 
 #[derive(Debug)]
@@ -129,11 +192,65 @@ lazy_static! {
             },
         );
 
+        let mut usdc_addresses = HashMap::new();
+        usdc_addresses.insert(
+            Blockchain::Ethereum,
+            "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+        );
+        usdc_addresses.insert(
+            Blockchain::Solana,
+            "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+        );
+        m.insert(
+            Token::USDC,
+            TokenInfo {
+                address: usdc_addresses,
+                decimals: 6,
+            },
+        );
+
         m
     };
+    // tokens held as inventory on every chain, each one is quoted per intent
+    pub static ref BRIDGE_TOKENS: Vec<String> = {
+        let tokens: Vec<String> = env::var("BRIDGE_TOKEN")
+            .unwrap_or_else(|_| String::from("USDT"))
+            .split(',')
+            .map(|token| token.trim().to_uppercase())
+            .filter(|token| !token.is_empty())
+            .collect();
+        for token in &tokens {
+            for chain in ["ethereum", "solana"] {
+                if get_token_info(token, chain).is_none() {
+                    panic!("BRIDGE_TOKEN {token} is not known on {chain}");
+                }
+            }
+        }
+        if tokens.is_empty() {
+            panic!("BRIDGE_TOKEN must name at least one token");
+        }
+        tokens
+    };
     pub static ref SOLVER_ID: String = env::var("SOLVER_ID").unwrap_or_else(|_| String::from(""));
-    pub static ref SOLVER_PRIVATE_KEY: String =
-        env::var("ETHEREUM_PKEY").unwrap_or_else(|_| String::from(""));
+    // address of the key the auctioneer signs its code 1 / code 4 messages with
+    pub static ref AUCTIONEER_ADDRESS: Option<Address> = env::var("AUCTIONEER_ADDRESS")
+        .ok()
+        .filter(|address| !address.is_empty())
+        .map(|address| Address::from_str(&address).expect("AUCTIONEER_ADDRESS must be an ethereum address"));
+    // time the auctioneer takes to pick a winner, on top of the chain margin when bidding
+    pub static ref AUCTION_DURATION_SECS: u64 = env::var("AUCTION_DURATION_SECS")
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .unwrap_or(30);
+}
+
+/// Address on `chain` of the first BRIDGE_TOKEN, the bridge token of fills
+/// that didn't record one.
+pub fn default_bridge_token(chain: &str) -> String {
+    let chain = if chain == "mantis" { "solana" } else { chain };
+    get_token_info(&BRIDGE_TOKENS[0], chain)
+        .map(|(address, _)| address.to_string())
+        .unwrap_or_default()
 }
 
 pub fn get_token_info(token: &str, blockchain: &str) -> Option<(&'static str, u32)> {
@@ -146,7 +263,7 @@ pub fn get_token_info(token: &str, blockchain: &str) -> Option<(&'static str, u3
 
 pub async fn create_keccak256_signature(
     json_data: &mut Value,
-    private_key: String,
+    signer: &EthereumSigner,
 ) -> Result<(), Box<dyn Error>> {
     let json_str = json_data.to_string();
     let json_bytes = json_str.as_bytes();
@@ -154,10 +271,8 @@ pub async fn create_keccak256_signature(
     let hash = keccak256(json_bytes);
     let hash_hex = hex::encode(hash);
 
-    let wallet: LocalWallet = private_key.parse().unwrap();
-    let eth_message_hash = hash_message(hash);
-
-    let signature: Signature = wallet.sign_hash(H256::from(eth_message_hash)).unwrap();
+    // signs hash_message(hash), i.e. the hash with the EIP-191 prefix
+    let signature: Signature = signer.sign_message(hash).await?;
     let signature_hex = signature.to_string();
 
     if let Some(msg) = json_data.get_mut("msg") {
@@ -167,3 +282,318 @@ pub async fn create_keccak256_signature(
 
     Ok(())
 }
+
+/// Code 1 message registering the solver. Bids are signed by the identity key,
+/// which holds no funds: each chain key of SOLVER_ADDRESSES signs that it belongs
+/// to that identity. While rotating, IDENTITY_PREVIOUS signs the handover too.
+pub async fn registration_message() -> Result<Value, String> {
+    let identity = identity_signer()?;
+    let ethereum = ethereum_signer()?;
+    let solana = solana_signer()?;
+
+    if Address::from_str(SOLVER_ADDRESSES[0]).ok() != Some(ethereum.address()) {
+        return Err(format!(
+            "Ethereum key {:?} is not SOLVER_ADDRESSES {}",
+            ethereum.address(),
+            SOLVER_ADDRESSES[0]
+        ));
+    }
+    if Pubkey::from_str(SOLVER_ADDRESSES[1]).ok() != Some(solana.pubkey()) {
+        return Err(format!(
+            "Solana key {} is not SOLVER_ADDRESSES {}",
+            solana.pubkey(),
+            SOLVER_ADDRESSES[1]
+        ));
+    }
+
+    let binding = format!("Mantis solver {} identity {:?}", *SOLVER_ID, identity.address());
+    let ethereum_signature = ethereum
+        .sign_message(&binding)
+        .await
+        .map_err(|e| format!("Ethereum key failed to sign the binding: {e}"))?;
+    let solana_signature = solana
+        .try_sign_message(binding.as_bytes())
+        .map_err(|e| format!("Solana key failed to sign the binding: {e}"))?;
+
+    let rotation = match previous_identity_signer()? {
+        Some(previous) => {
+            let handover = format!(
+                "Mantis solver {} rotates identity {:?} to {:?}",
+                *SOLVER_ID,
+                previous.address(),
+                identity.address()
+            );
+            let signature = previous
+                .sign_message(&handover)
+                .await
+                .map_err(|e| format!("Previous identity failed to sign the rotation: {e}"))?;
+            json!({
+                "previous_identity": format!("{:?}", previous.address()),
+                "signature": signature.to_string(),
+            })
+        }
+        None => Value::Null,
+    };
+
+    Ok(json!({
+        "code": 1,
+        "msg": {
+            "solver_id": SOLVER_ID.to_string(),
+            "solver_addresses": SOLVER_ADDRESSES,
+            "identity": format!("{:?}", identity.address()),
+            "bindings": [
+                {
+                    "chain": "ethereum",
+                    "address": SOLVER_ADDRESSES[0],
+                    "signature": ethereum_signature.to_string(),
+                },
+                {
+                    "chain": "solana",
+                    "address": SOLVER_ADDRESSES[1],
+                    "signature": solana_signature.to_string(),
+                },
+            ],
+            "rotation": rotation,
+        }
+    }))
+}
+
+/// Reverse of `create_keccak256_signature`: strips `hash` and `signature` from
+/// `msg`, re-hashes the message and checks it was signed by `signer`.
+pub fn verify_keccak256_signature(json_data: &Value, signer: Address) -> Result<(), String> {
+    let mut unsigned = json_data.clone();
+    let msg = unsigned
+        .get_mut("msg")
+        .and_then(Value::as_object_mut)
+        .ok_or("message has no msg object")?;
+
+    let hash_hex = msg
+        .remove("hash")
+        .and_then(|hash| hash.as_str().map(String::from))
+        .ok_or("message is not hashed")?;
+    let signature_hex = msg
+        .remove("signature")
+        .and_then(|signature| signature.as_str().map(String::from))
+        .ok_or("message is not signed")?;
+
+    let hash = keccak256(unsigned.to_string().as_bytes());
+    if hex::encode(hash) != hash_hex.trim_start_matches("0x") {
+        return Err("hash does not match message".to_string());
+    }
+
+    let signature =
+        Signature::from_str(&signature_hex).map_err(|e| format!("invalid signature: {}", e))?;
+    signature
+        .verify(hash_message(hash), signer)
+        .map_err(|e| format!("bad signature: {}", e))
+}
+
+/// Without AUCTIONEER_ADDRESS anyone reaching the websocket could make us bid
+/// and fill, so it is required unless ALLOW_UNSIGNED_AUCTIONEER=1 (e.g. against
+/// an unsigned mock auctioneer).
+pub fn check_auctioneer_address() -> Result<(), String> {
+    if AUCTIONEER_ADDRESS.is_some() || env::var("ALLOW_UNSIGNED_AUCTIONEER").as_deref() == Ok("1") {
+        Ok(())
+    } else {
+        Err(String::from(
+            "AUCTIONEER_ADDRESS is not set, set ALLOW_UNSIGNED_AUCTIONEER=1 to accept unsigned auctioneer messages",
+        ))
+    }
+}
+
+/// Checks an inbound auctioneer message against AUCTIONEER_ADDRESS. Messages
+/// only pass unchecked when ALLOW_UNSIGNED_AUCTIONEER=1 and no address is set.
+pub fn verify_auctioneer_message(json_data: &Value) -> Result<(), String> {
+    match *AUCTIONEER_ADDRESS {
+        Some(auctioneer) => verify_keccak256_signature(json_data, auctioneer),
+        None => Ok(()),
+    }
+}
+
+// chains whose bridge_escrow program holds an `intent` account for this intent
+fn solana_escrow_chains(intent: &PostIntentInfo) -> Vec<&str> {
+    let mut chains = Vec::new();
+    for chain in [intent.src_chain.as_str(), intent.dst_chain.as_str()] {
+        if (chain == "solana" || chain == "mantis") && !chains.contains(&chain) {
+            chains.push(chain);
+        }
+    }
+    chains
+}
+
+/// Cross-checks the intent we are about to fill against the escrow on its
+/// source chain, where the user locked token_in, and against the Solana
+/// `intent` account `send_funds_to_user` consumes, before any funds are sent.
+pub async fn verify_intent_on_chain(
+    intent_id: &str,
+    intent: &PostIntentInfo,
+    amount: &str,
+) -> Result<(), String> {
+    if intent.src_chain == "ethereum" {
+        ethereum::ethereum_chain::ethereum_verify_intent(intent_id, intent, amount).await?;
+    }
+
+    for chain in solana_escrow_chains(intent) {
+        solana::solana_chain::solana_verify_intent(chain, intent_id, intent, amount).await?;
+    }
+
+    Ok(())
+}
+
+/// Fills a won intent on its dst_chain, once it checks out against the escrow.
+/// `bridge_token` is the dst_chain address of the bridge token we bid with.
+pub async fn execute_intent(
+    intent_id: &str,
+    intent: &PostIntentInfo,
+    amount: &str,
+    bridge_token: &str,
+) -> Result<(), String> {
+    intent.check_timeout(0)?;
+    verify_intent_on_chain(intent_id, intent, amount).await?;
+
+    if intent.dst_chain == "solana" {
+        solana::solana_chain::handle_solana_execution(intent, intent_id, amount, bridge_token).await
+    } else if intent.dst_chain == "ethereum" {
+        let intent_id = U256::from_dec_str(intent_id).map_err(|e| e.to_string())?;
+        let single_domain = intent.src_chain == intent.dst_chain;
+        ethereum::ethereum_chain::handle_ethereum_execution(
+            intent,
+            intent_id,
+            amount,
+            single_domain,
+            bridge_token,
+        )
+        .await
+    } else if intent.dst_chain == "mantis" {
+        mantis::mantis_chain::handle_mantis_execution(intent, intent_id).await
+    } else {
+        Err(format!("chain {} not supported", intent.dst_chain))
+    }
+}
+
+/// Whether a won intent (e.g. loaded from the journal after a restart) still
+/// has to be filled by us.
+pub async fn intent_needs_fill(
+    intent_id: &str,
+    intent: &PostIntentInfo,
+    amount: &str,
+) -> Result<bool, String> {
+    if intent.check_timeout(0).is_err() {
+        return Ok(false);
+    }
+
+    let solana_chains = solana_escrow_chains(intent);
+    if solana_chains.is_empty() {
+        return Ok(
+            ethereum::ethereum_chain::ethereum_verify_intent(intent_id, intent, amount)
+                .await
+                .is_ok(),
+        );
+    }
+
+    for chain in solana_chains {
+        if !solana::solana_chain::solana_intent_needs_fill(chain, intent_id, intent, amount).await? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_info_has_the_known_mints() {
+        for (token, chain, address) in [
+            ("USDT", "ethereum", "0xdAC17F958D2ee523a2206206994597C13D831ec7"),
+            ("USDT", "solana", "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB"),
+            ("USDC", "ethereum", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+            ("USDC", "solana", "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
+        ] {
+            assert_eq!(get_token_info(token, chain), Some((address, 6)), "{token} on {chain}");
+        }
+
+        // and every entry is a valid address on its chain
+        for info in TOKEN_INFO.values() {
+            for (chain, address) in &info.address {
+                match chain {
+                    Blockchain::Ethereum => assert!(Address::from_str(address).is_ok(), "{address}"),
+                    Blockchain::Solana => assert!(Pubkey::from_str(address).is_ok(), "{address}"),
+                }
+            }
+        }
+    }
+
+    fn wallet(key: &str) -> LocalWallet {
+        key.parse().unwrap()
+    }
+
+    async fn signed_bid(wallet: &LocalWallet) -> Value {
+        let mut bid = json!({
+            "code": 2,
+            "msg": {
+                "solver_id": "solver",
+                "intent_id": "1",
+                "amount": "1000000",
+            }
+        });
+        create_keccak256_signature(&mut bid, &EthereumSigner::Local(Arc::new(wallet.clone())))
+            .await
+            .unwrap();
+        bid
+    }
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const OTHER_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    #[tokio::test]
+    async fn signed_message_verifies() {
+        let wallet = wallet(KEY);
+        let bid = signed_bid(&wallet).await;
+
+        assert!(bid["msg"]["hash"].is_string());
+        assert_eq!(verify_keccak256_signature(&bid, wallet.address()), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn changed_field_is_rejected() {
+        let wallet = wallet(KEY);
+        let mut bid = signed_bid(&wallet).await;
+        bid["msg"]["amount"] = json!("2000000");
+
+        assert_eq!(
+            verify_keccak256_signature(&bid, wallet.address()),
+            Err("hash does not match message".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn other_signer_is_rejected() {
+        let bid = signed_bid(&wallet(OTHER_KEY)).await;
+
+        let err = verify_keccak256_signature(&bid, wallet(KEY).address()).unwrap_err();
+        assert!(err.starts_with("bad signature"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn missing_hash_or_signature_is_rejected() {
+        let wallet = wallet(KEY);
+        let bid = signed_bid(&wallet).await;
+
+        let mut unhashed = bid.clone();
+        unhashed["msg"].as_object_mut().unwrap().remove("hash");
+        assert_eq!(
+            verify_keccak256_signature(&unhashed, wallet.address()),
+            Err("message is not hashed".to_string())
+        );
+
+        let mut unsigned = bid;
+        unsigned["msg"].as_object_mut().unwrap().remove("signature");
+        assert_eq!(
+            verify_keccak256_signature(&unsigned, wallet.address()),
+            Err("message is not signed".to_string())
+        );
+    }
+}
@@ -1,23 +1,33 @@
 pub mod solana_chain {
     use crate::chains::*;
+    use crate::inventory::{fills_from_inventory, keeps_token_in, reserved_amount};
+    use crate::risk::record_fill_pnl;
+    use crate::rpc::{svm_broadcast, svm_client_for, svm_pool, RpcFailure, MANTIS, SOLANA};
+    use crate::signer::{solana_signer, SolanaSigner};
     use crate::routers::jupiter::create_token_account;
     use crate::routers::jupiter::jupiter_swap;
     use crate::routers::jupiter::quote;
+    use crate::routers::jupiter::swap as jupiter_swap_transaction;
+    use crate::routers::jupiter::SwapRequest;
     use crate::routers::jupiter::Memo as Jup_Memo;
     use crate::routers::jupiter::QuoteConfig;
     use crate::routers::jupiter::SwapMode;
+    use crate::routers::solana_amm::solana_amm_router::{amm_swap, quote as amm_quote};
     use crate::PostIntentInfo;
     use anchor_client::Cluster;
+    use anchor_lang::AccountDeserialize;
     use num_bigint::BigInt;
+    use num_traits::ToPrimitive;
     use serde::{Deserialize, Serialize};
     use serde_json::json;
     use solana_client::nonblocking::rpc_client::RpcClient;
-    use solana_client::rpc_config::RpcSendTransactionConfig;
+    use solana_client::rpc_config::{RpcSendTransactionConfig, RpcSimulateTransactionConfig};
     use solana_sdk::commitment_config::CommitmentConfig;
     use solana_sdk::compute_budget::ComputeBudgetInstruction;
+    use solana_sdk::message::Message;
     use solana_sdk::pubkey::Pubkey;
-    use solana_sdk::signature::{Keypair, Signer};
-    use solana_sdk::transaction::Transaction;
+    use solana_sdk::signature::Signer;
+    use solana_sdk::transaction::{Transaction, VersionedTransaction};
     use spl_associated_token_account::get_associated_token_address;
     use spl_token::instruction::transfer;
     use std::env;
@@ -47,28 +57,29 @@ pub mod solana_chain {
         intent: &PostIntentInfo,
         intent_id: &str,
         amount: &str,
+        bridge_token: &str,
     ) -> Result<(), String> {
-        let from_keypair = Keypair::from_base58_string(
-            env::var("SOLANA_KEYPAIR")
-                .expect("SOLANA_KEYPAIR must be set")
-                .as_str(),
-        );
-        let rpc_url = env::var("SOLANA_RPC").expect("SOLANA_RPC must be set");
-        let client = RpcClient::new_with_commitment(rpc_url.clone(), CommitmentConfig::confirmed());
-
-        let usdt_contract_address = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+        let from_keypair = solana_signer()?;
+        let rpc_url = SOLANA.url()?;
+        let client = SOLANA.client_for(&rpc_url)?;
 
-        let usdt_token_account = get_associated_token_address(
+        let bridge_token_account = get_associated_token_address(
             &from_keypair.pubkey(),
-            &Pubkey::from_str(usdt_contract_address).unwrap(),
+            &Pubkey::from_str(bridge_token)
+                .map_err(|e| format!("Invalid bridge token {bridge_token}: {}", e))?,
         );
 
-        let balance_ant = client
-            .get_token_account_balance(&usdt_token_account)
-            .await
-            .map_err(|e| format!("Failed to get token account balance: {}", e))?
-            .ui_amount
-            .unwrap();
+        let read_balance = || {
+            SOLANA.with_failover(move |client| async move {
+                client
+                    .get_token_account_balance(&bridge_token_account)
+                    .await
+                    .map_err(|e| RpcFailure::svm("Failed to get bridge token balance", &e))
+            })
+        };
+        let balance = read_balance().await?;
+        let bridge_decimals = balance.decimals;
+        let balance_ant = BigInt::from_str(&balance.amount).map_err(|e| e.to_string())?;
 
         let mut user_account = String::default();
         let mut token_in = String::default();
@@ -84,11 +95,30 @@ pub mod solana_chain {
             amount_in = transfer_input.amount_in.clone();
         }
 
-        // swap USDT -> token_out
-        if !token_out.eq_ignore_ascii_case(usdt_contract_address) {
-            if let Err(e) = solana_transfer_swap(intent.clone(), amount).await {
+        let single_domain = intent.src_chain == intent.dst_chain;
+        let direct = !token_out.eq_ignore_ascii_case(bridge_token)
+            && fills_from_inventory(
+                Some(intent_id),
+                "solana",
+                &token_out,
+                &BigInt::from_str(amount).map_err(|e| format!("Invalid amount {amount}: {}", e))?,
+            )
+            .await;
+        let keep_token_in = single_domain && keeps_token_in("solana", &token_in).await;
+
+        // verifying may have eaten the time we had left. The margin covers the swap
+        // and the send, once we hold token_out we go through with the fill
+        intent
+            .check_timeout(0)
+            .map_err(|e| format!("Aborting intent {intent_id} before swapping: {e}"))?;
+
+        // swap bridge token -> token_out, unless the inventory holds enough token_out
+        if direct {
+            println!("Filling intent {intent_id} with {token_out} from inventory");
+        } else if !token_out.eq_ignore_ascii_case(bridge_token) {
+            if let Err(e) = solana_transfer_swap(intent_id, intent.clone(), amount, bridge_token).await {
                 return Err(format!(
-                    "Error occurred on Solana swap USDT -> token_out (manual swap required): {}",
+                    "Error occurred on Solana swap {bridge_token} -> token_out (manual swap required): {}",
                     e
                 ));
             }
@@ -109,7 +139,7 @@ pub mod solana_chain {
             &token_out,
             &user_account,
             solver_out.to_string(),
-            intent.src_chain == intent.dst_chain,
+            single_domain,
             rpc_url,
             Pubkey::from_str(&bridge_escrow::ID.to_string()).unwrap(),
         )
@@ -119,72 +149,295 @@ pub mod solana_chain {
                 "Error occurred on send token_out -> user & user sends token_in -> solver: {}",
                 e
             ));
-        // swap token_in -> USDT
-        } else if intent.src_chain == intent.dst_chain
-            && !token_in.eq_ignore_ascii_case(usdt_contract_address)
-        {
+        } else if keep_token_in {
+            println!("Keeping {amount_in} {token_in} of intent {intent_id} as inventory");
+        // swap token_in -> bridge token
+        } else if single_domain && !token_in.eq_ignore_ascii_case(bridge_token) {
             let memo = format!(
                 r#"{{"user_account": "{}","token_in": "{}","token_out": "{}","amount": {},"slippage_bps": {}}}"#,
                 SOLVER_ADDRESSES.get(1).unwrap(),
                 token_in,
-                usdt_contract_address,
+                bridge_token,
                 amount_in,
                 100
             );
 
             if let Err(e) = jupiter_swap(&memo, &client, &from_keypair, SwapMode::ExactIn).await {
-                return Err(format!("Error on Solana swap token_in -> USDT: {e}"));
+                println!("Error on Jupiter swap token_in -> {bridge_token}: {e}, swapping on AMM pools instead");
+                amm_swap(
+                    &client,
+                    &from_keypair,
+                    &Pubkey::from_str(&token_in).map_err(|e| e.to_string())?,
+                    &Pubkey::from_str(bridge_token).unwrap(),
+                    amount_in.parse::<u64>().map_err(|e| e.to_string())?,
+                    0,
+                )
+                .await
+                .map_err(|e| format!("Error on Solana swap token_in -> {bridge_token}: {e}"))?;
             }
         } else {
             println!("You sent token_out to user for intent_id {intent_id}. You will receive token_in from user on src_chain");
         }
 
-        if intent.src_chain == intent.dst_chain {
-            let mut balance_post = client
-                .get_token_account_balance(&usdt_token_account)
-                .await
-                .unwrap()
-                .ui_amount
-                .unwrap();
-
-            let balance = if balance_post > balance_ant {
-                balance_post - balance_ant
-            } else if balance_post < balance_ant {
-                balance_ant - balance_post
-            } else {
-                std::thread::sleep(Duration::from_secs(5));
-                balance_post = client
-                    .get_token_account_balance(&usdt_token_account)
-                    .await
-                    .unwrap()
-                    .ui_amount
-                    .unwrap();
-
-                balance_post - balance_ant
-            };
+        let mut balance_post =
+            BigInt::from_str(&read_balance().await?.amount).map_err(|e| e.to_string())?;
+        if balance_post == balance_ant && !direct {
+            // the RPC may not have caught up with the fill yet
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            balance_post =
+                BigInt::from_str(&read_balance().await?.amount).map_err(|e| e.to_string())?;
+        }
 
+        let pnl = record_fill_pnl(
+            intent_id,
+            balance_post - balance_ant,
+            direct,
+            !single_domain || keep_token_in,
+        )
+        .await;
+        if let Some(pnl) = pnl {
             println!(
-                "You have {} {} USDT on intent {intent_id}",
-                if balance_post >= balance_ant {
-                    "won"
-                } else {
-                    "lost"
-                },
-                balance
+                "You have {} {} {bridge_token} on intent {intent_id}",
+                if pnl >= BigInt::from(0) { "won" } else { "lost" },
+                pnl.magnitude().to_f64().unwrap_or_default() / 10f64.powi(bridge_decimals as i32)
             );
         }
 
         Ok(())
     }
 
-    pub async fn solana_transfer_swap(intent: PostIntentInfo, amount: &str) -> Result<(), String> {
-        let rpc_url = env::var("SOLANA_RPC").map_err(|_| "SOLANA_RPC must be set".to_string())?;
+    // (rpc_url, bridge_escrow program id) of the chain the intent was posted on
+    fn escrow_program(chain: &str) -> Result<(String, Pubkey), String> {
+        if chain == "mantis" {
+            Ok((
+                MANTIS.url()?,
+                Pubkey::from_str("61beRZG1h3SvPgGYh9tXhx42jABkMjbMQWpgqUqXw2hw").unwrap(),
+            ))
+        } else {
+            Ok((
+                SOLANA.url()?,
+                Pubkey::from_str(&bridge_escrow::ID.to_string()).unwrap(),
+            ))
+        }
+    }
+
+    /// Fetches and deserializes the bridge_escrow `intent` PDA of `intent_id` on
+    /// `chain`. Ok(None) means the account doesn't exist (never posted or closed).
+    pub async fn solana_fetch_intent(
+        chain: &str,
+        intent_id: &str,
+    ) -> Result<Option<bridge_escrow::Intent>, String> {
+        let (_, program_id) = escrow_program(chain)?;
 
-        let from_keypair_str =
-            env::var("SOLANA_KEYPAIR").map_err(|_| "SOLANA_KEYPAIR must be set".to_string())?;
-        let from_keypair = Keypair::from_base58_string(&from_keypair_str);
+        let intent_state =
+            Pubkey::find_program_address(&[b"intent", intent_id.as_bytes()], &program_id).0;
 
-        let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+        let account = match svm_pool(chain)
+            .with_failover(|client| async move {
+                client
+                    .get_account_with_commitment(&intent_state, CommitmentConfig::confirmed())
+                    .await
+                    .map_err(|e| RpcFailure::svm(&format!("Failed to fetch intent {intent_id}"), &e))
+            })
+            .await?
+            .value
+        {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+
+        if account.owner != program_id {
+            return Err(format!(
+                "intent {intent_id} account is owned by {}, not {program_id}",
+                account.owner
+            ));
+        }
+
+        bridge_escrow::Intent::try_deserialize(&mut account.data.as_slice())
+            .map(Some)
+            .map_err(|e| format!("Failed to deserialize intent {intent_id}: {}", e))
+    }
+
+    // differences between the escrowed intent and the one the auctioneer sent us
+    fn intent_mismatches(
+        escrowed: &bridge_escrow::Intent,
+        intent: &PostIntentInfo,
+        amount: &str,
+    ) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        // pubkeys are base58, case matters
+        let mut check = |field: &str, ours: &str, escrowed: String| {
+            if ours != escrowed {
+                mismatches.push(format!("{field} {ours} != {escrowed}"));
+            }
+        };
+
+        if let OperationInput::SwapTransfer(input) = &intent.inputs {
+            check("token_in", &input.token_in, escrowed.token_in.to_string());
+            check("amount_in", &input.amount_in, escrowed.amount_in.to_string());
+            // an ethereum src_chain_user has no pubkey to compare with
+            if intent.src_chain != "ethereum" {
+                check("src_chain_user", &input.src_chain_user, escrowed.user_in.to_string());
+            }
+        }
+        if let OperationOutput::SwapTransfer(output) = &intent.outputs {
+            check("token_out", &output.token_out, escrowed.token_out.to_string());
+            check("dst_chain_user", &output.dst_chain_user, escrowed.user_out.to_string());
+        }
+        check("amount_out", amount, escrowed.amount_out.to_string());
+
+        let winner = escrowed.winner_solver.to_string();
+        if !SOLVER_ADDRESSES
+            .iter()
+            .any(|solver| solver.eq_ignore_ascii_case(&winner))
+        {
+            mismatches.push(format!("winner {winner} is not us"));
+        }
+
+        mismatches
+    }
+
+    /// Compares the bridge_escrow intent account on `chain` with the intent the
+    /// auctioneer told us we won, refusing to fill on any mismatch.
+    pub async fn solana_verify_intent(
+        chain: &str,
+        intent_id: &str,
+        intent: &PostIntentInfo,
+        amount: &str,
+    ) -> Result<(), String> {
+        let escrowed = solana_fetch_intent(chain, intent_id)
+            .await?
+            .ok_or_else(|| format!("intent {intent_id} not found in {chain} escrow"))?;
+
+        let mismatches = intent_mismatches(&escrowed, intent, amount);
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "intent {intent_id} does not match {chain} escrow: {}",
+                mismatches.join(", ")
+            ))
+        }
+    }
+
+    /// "Do I still need to fill this?" after a restart: true while the intent
+    /// account is still open on `chain` and names us as the winner for `amount`.
+    pub async fn solana_intent_needs_fill(
+        chain: &str,
+        intent_id: &str,
+        intent: &PostIntentInfo,
+        amount: &str,
+    ) -> Result<bool, String> {
+        match solana_fetch_intent(chain, intent_id).await? {
+            // the escrow closes the account once the intent is filled or cancelled
+            None => Ok(false),
+            Some(escrowed) => Ok(intent_mismatches(&escrowed, intent, amount).is_empty()),
+        }
+    }
+
+    /// Dry run of a fill through simulate_transaction, unsigned and never sent:
+    /// the bridge token -> token_out Jupiter swap, or a transfer of `amount`
+    /// bridge token to ourselves when no swap is needed (which needs the balance).
+    /// Returns the token_out `bridge_amount` buys, itself when no swap is needed.
+    pub async fn solana_simulate_fill(
+        intent: &PostIntentInfo,
+        amount: &str,
+        bridge_token: &str,
+        bridge_amount: &BigInt,
+    ) -> Result<BigInt, String> {
+        let solver = solana_signer()?.pubkey();
+        let client = SOLANA.client()?;
+
+        let OperationOutput::SwapTransfer(output) = &intent.outputs else {
+            return Err("only swap transfers can be simulated".to_string());
+        };
+        let token_out = Pubkey::from_str(&output.token_out)
+            .map_err(|e| format!("Invalid token_out {}: {}", output.token_out, e))?;
+        let bridge_mint = Pubkey::from_str(bridge_token)
+            .map_err(|e| format!("Invalid bridge token {bridge_token}: {}", e))?;
+        let amount = amount
+            .parse::<u64>()
+            .map_err(|e| format!("Invalid amount {amount}: {}", e))?;
+
+        let (transaction, amount_out) = if token_out == bridge_mint {
+            let account = get_associated_token_address(&solver, &bridge_mint);
+            let instruction =
+                transfer(&spl_token::id(), &account, &account, &solver, &[], amount)
+                    .map_err(|e| format!("Failed to build transfer: {}", e))?;
+
+            let transaction = VersionedTransaction::from(Transaction::new_unsigned(
+                Message::new(&[instruction], Some(&solver)),
+            ));
+            (transaction, bridge_amount.clone())
+        } else {
+            let bridge_amount = u64::try_from(bridge_amount)
+                .map_err(|e| format!("Invalid bridge amount {bridge_amount}: {}", e))?;
+            let quote = quote(
+                bridge_mint,
+                token_out,
+                bridge_amount,
+                QuoteConfig {
+                    swap_mode: Some(SwapMode::ExactIn),
+                    slippage_bps: Some(100),
+                    ..QuoteConfig::default()
+                },
+            )
+            .await
+            .map_err(|e| format!("Failed to get Jupiter quote: {}", e))?;
+
+            if quote.out_amount < amount {
+                return Err(format!(
+                    "swap returns {} token_out, {amount} promised",
+                    quote.out_amount
+                ));
+            }
+
+            // no destination account, Jupiter creates our token_out account if needed
+            let amount_out = BigInt::from(quote.out_amount);
+            let mut request =
+                SwapRequest::new(solver, quote, get_associated_token_address(&solver, &token_out));
+            request.destination_token_account = None;
+
+            let transaction = jupiter_swap_transaction(request)
+                .await
+                .map_err(|e| format!("Failed to build swap: {}", e))?
+                .swap_transaction;
+            (transaction, amount_out)
+        };
+
+        let simulation = client
+            .simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )
+            .await
+            .map_err(|e| format!("Failed to simulate fill: {}", e))?
+            .value;
+
+        match simulation.err {
+            Some(e) => Err(format!(
+                "Simulation failed: {e}, logs: {:?}",
+                simulation.logs.unwrap_or_default()
+            )),
+            None => Ok(amount_out),
+        }
+    }
+
+    pub async fn solana_transfer_swap(
+        intent_id: &str,
+        intent: PostIntentInfo,
+        amount: &str,
+        bridge_token: &str,
+    ) -> Result<(), String> {
+
+        let from_keypair = solana_signer()?;
+
+        let client = SOLANA.client()?;
 
         match intent.function_name.as_str() {
             "transfer" => {
@@ -221,16 +474,38 @@ pub mod solana_chain {
                 }
 
                 let memo = format!(
-                    r#"{{"user_account": "{}","token_in": "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB","token_out": "{}","amount": {},"slippage_bps": {}}}"#,
+                    r#"{{"user_account": "{}","token_in": "{}","token_out": "{}","amount": {},"slippage_bps": {}}}"#,
                     SOLVER_ADDRESSES.get(1).unwrap(),
+                    bridge_token,
                     token_out,
                     amount,
                     100
                 );
 
-                jupiter_swap(&memo, &client, &from_keypair, SwapMode::ExactOut)
+                if let Err(err) = jupiter_swap(&memo, &client, &from_keypair, SwapMode::ExactOut).await
+                {
+                    // the pools only do exact-in, sell the bridge amount we bid with
+                    let bridge_amount = reserved_amount(intent_id, bridge_token)
+                        .await
+                        .and_then(|amount| u64::try_from(&amount).ok())
+                        .ok_or_else(|| format!("Swap failed: {}", err))?;
+
+                    println!("Jupiter swap failed: {err}, swapping on AMM pools instead");
+                    amm_swap(
+                        &client,
+                        &from_keypair,
+                        &Pubkey::from_str(bridge_token)
+                            .map_err(|e| format!("Invalid bridge token pubkey: {}", e))?,
+                        &Pubkey::from_str(&token_out)
+                            .map_err(|e| format!("Invalid token_out pubkey: {}", e))?,
+                        bridge_amount,
+                        amount
+                            .parse::<u64>()
+                            .map_err(|e| format!("Invalid amount: {}", e))?,
+                    )
                     .await
                     .map_err(|err| format!("Swap failed: {}", err))?;
+                }
             }
             _ => {
                 return Err("Function not supported".to_string());
@@ -242,7 +517,7 @@ pub mod solana_chain {
 
     async fn transfer_slp20(
         client: &RpcClient,
-        sender_keypair: &Keypair,
+        sender_keypair: &SolanaSigner,
         recipient_wallet_pubkey: &Pubkey,
         token_mint_pubkey: &Pubkey,
         amount: u64,
@@ -304,8 +579,9 @@ pub mod solana_chain {
             return Err("Transaction simulation failed".into());
         }
 
-        let result = client
-            .send_and_confirm_transaction_with_spinner(&transaction)
+        let result = svm_broadcast("solana", &transaction).await?;
+        client
+            .confirm_transaction_with_spinner(&result, &recent_blockhash, client.commitment())
             .await?;
 
         Ok(result.to_string())
@@ -314,7 +590,7 @@ pub mod solana_chain {
     pub async fn _get_solana_token_decimals(
         token_address: &str,
     ) -> Result<u8, Box<dyn std::error::Error>> {
-        let rpc_url = env::var("SOLANA_RPC").expect("SOLANA_RPC must be set");
+        let rpc_url = SOLANA.url()?;
         let client = reqwest::Client::new();
         let request_body = json!({
             "jsonrpc": "2.0",
@@ -340,12 +616,13 @@ pub mod solana_chain {
         }
     }
 
+    // returns (amount_out, price_impact_pct)
     pub async fn solana_simulate_swap(
         dst_chain_user: &str,
         token_in: &str,
         token_out: &str,
         amount_in: u64,
-    ) -> String {
+    ) -> (String, f64) {
         let memo_json = json!({
             "user_account": dst_chain_user,
             "token_in": token_in,
@@ -356,7 +633,7 @@ pub mod solana_chain {
 
         let memo = match Jup_Memo::from_json(&memo_json.to_string()) {
             Ok(memo) => memo,
-            Err(_) => return "0".to_string(),
+            Err(_) => return ("0".to_string(), 0f64),
         };
 
         let quote_config = QuoteConfig {
@@ -366,12 +643,65 @@ pub mod solana_chain {
             ..QuoteConfig::default()
         };
 
-        let quotes = match quote(memo.token_in, memo.token_out, memo.amount, quote_config).await {
-            Ok(quotes) => quotes,
-            Err(_) => return "0".to_string(),
+        let jupiter = quote(memo.token_in, memo.token_out, memo.amount, quote_config).await;
+
+        // same ONCHAIN_QUOTER modes as on Ethereum, with the SOLANA_AMM_POOLS
+        let onchain_quoter =
+            env::var("ONCHAIN_QUOTER").unwrap_or_else(|_| String::from("fallback"));
+        let onchain = match (onchain_quoter.as_str(), &jupiter) {
+            ("off", _) | ("fallback", Ok(_)) => None,
+            _ => {
+                let onchain = match SOLANA.client() {
+                    Ok(client) => {
+                        amm_quote(&client, &memo.token_in, &memo.token_out, memo.amount).await
+                    }
+                    Err(e) => Err(e),
+                };
+                match onchain {
+                    Ok(onchain) => Some(onchain),
+                    Err(e) => {
+                        eprintln!("Failed to get AMM quote: {e}");
+                        None
+                    }
+                }
+            }
         };
 
-        BigInt::from(quotes.out_amount).to_string()
+        match (jupiter, onchain) {
+            (Ok(quotes), Some(onchain)) => {
+                let max_deviation_bps = env::var("ONCHAIN_QUOTE_MAX_DEVIATION_BPS")
+                    .ok()
+                    .and_then(|bps| bps.parse::<u64>().ok())
+                    .unwrap_or(500);
+                let max_amount =
+                    onchain.amount_out as u128 * (10_000 + max_deviation_bps) as u128 / 10_000;
+
+                if quotes.out_amount as u128 > max_amount {
+                    println!(
+                        "Jupiter quote {} is more than {max_deviation_bps} bps above the AMM pools {}, using the pools",
+                        quotes.out_amount, onchain.amount_out
+                    );
+                    return (onchain.amount_out.to_string(), quotes.price_impact_pct);
+                }
+
+                (
+                    BigInt::from(quotes.out_amount).to_string(),
+                    quotes.price_impact_pct,
+                )
+            }
+            (Ok(quotes), None) => (
+                BigInt::from(quotes.out_amount).to_string(),
+                quotes.price_impact_pct,
+            ),
+            (Err(e), Some(onchain)) => {
+                eprintln!("Failed to get Jupiter quote: {e}, using AMM pools quote");
+                (onchain.amount_out.to_string(), 0f64)
+            }
+            (Err(e), None) => {
+                eprintln!("Failed to get Jupiter quote: {e}");
+                ("0".to_string(), 0f64)
+            }
+        }
     }
 
     pub async fn solana_send_funds_to_user(
@@ -384,11 +714,7 @@ pub mod solana_chain {
         rpc_url: String,
         program_id: Pubkey,
     ) -> Result<(), String> {
-        // Load the keypair from environment variable
-        let solana_keypair = env::var("SOLANA_KEYPAIR")
-            .map_err(|e| format!("Failed to read SOLANA_KEYPAIR from environment: {}", e))?;
-
-        let solver = Arc::new(Keypair::from_base58_string(&solana_keypair));
+        let solver = solana_signer()?;
 
         // Clone the necessary variables for the task
         let solver_clone = Arc::clone(&solver);
@@ -397,8 +723,7 @@ pub mod solana_chain {
         let token_out_mint = token_out_mint.to_string();
         let user = user.to_string();
 
-        let rpc_client =
-            RpcClient::new_with_commitment(rpc_url.clone(), CommitmentConfig::confirmed());
+        let rpc_client = svm_client_for(&rpc_url)?;
         let solver_token_in_addr = get_associated_token_address(
             &solver_clone.pubkey(),
             &Pubkey::from_str(&token_in_mint).unwrap(),
@@ -582,4 +907,49 @@ pub mod solana_chain {
         .await
         .map_err(|e| format!("Task failed: {:?}", e))?
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::mock_routers::{Fixtures, MockRouters, ROUTER_ENV};
+
+        const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+        const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+        #[tokio::test]
+        async fn simulate_swap_quotes_through_jupiter() {
+            let _env = ROUTER_ENV.lock().await;
+            let mock = MockRouters::start(Fixtures {
+                price_impact_pct: 1.0,
+                ..Fixtures::default()
+            })
+            .unwrap();
+            env::set_var("QUOTE_API_URL", mock.url());
+            env::set_var("ONCHAIN_QUOTER", "off");
+            mock.set_rate(USDC, USDT, 0.5).await;
+            let user = Pubkey::new_unique().to_string();
+
+            let (amount_out, price_impact_pct) =
+                solana_simulate_swap(&user, USDC, USDT, 1_000_000).await;
+            assert_eq!(amount_out, "500000");
+            assert!((price_impact_pct - 0.01).abs() < 1e-9);
+
+            let requests = mock.requests().await;
+            assert_eq!(requests[0]["query"]["swapMode"], "ExactIn");
+            assert_eq!(requests[0]["query"]["slippageBps"], "100");
+
+            // no quote is no bid, not an error
+            mock.set_response("GET /quote", 200, json!({ "error": "Could not find any route" }))
+                .await;
+            assert_eq!(
+                solana_simulate_swap(&user, USDC, USDT, 1_000_000).await,
+                ("0".to_string(), 0f64)
+            );
+            mock.set_response("GET /quote", 500, json!({})).await;
+            assert_eq!(
+                solana_simulate_swap(&user, USDC, USDT, 1_000_000).await,
+                ("0".to_string(), 0f64)
+            );
+        }
+    }
 }
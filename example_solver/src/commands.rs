@@ -0,0 +1,350 @@
+use crate::chains::ethereum::ethereum_chain::{
+    approve_erc20, erc20_allowance, ESCROW_SC_ETHEREUM, PARASWAP,
+};
+use crate::chains::{
+    check_auctioneer_address, create_keccak256_signature, get_token_info, registration_message,
+    OperationInput, OperationOutput, PostIntentInfo, SwapTransferInput, SwapTransferOutput,
+    AUCTIONEER_ADDRESS, BRIDGE_TOKENS, SOLVER_ADDRESSES, SOLVER_ID,
+};
+use crate::inventory::{get_balance, GAS_TOKEN};
+use crate::routers::get_best_quote;
+use crate::rpc::{check_rpc_health, RpcPool, ETHEREUM, MANTIS, SOLANA};
+use crate::signer::identity_signer;
+use crate::strategy::{price_bid, strategy_from_env, BidInput, BID_STRATEGY};
+use ethers::types::U256;
+use futures::{SinkExt, StreamExt};
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use serde_json::Value;
+use std::env;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const CHAINS: &[&str] = &["ethereum", "solana"];
+
+// "USDT", "USDC" or an address / mint
+fn token_address(token: &str, chain: &str) -> String {
+    get_token_info(token, chain)
+        .map(|(address, _)| address.to_string())
+        .unwrap_or_else(|| token.to_string())
+}
+
+fn solver_address(chain: &str) -> &'static str {
+    if chain == "ethereum" {
+        SOLVER_ADDRESSES[0]
+    } else {
+        SOLVER_ADDRESSES[1]
+    }
+}
+
+// human readable amount of a token with `decimals`
+fn units(amount: &BigInt, decimals: u32) -> f64 {
+    amount.to_f64().unwrap_or_default() / 10f64.powi(decimals as i32)
+}
+
+/// Prices a hypothetical intent, from the solver to itself, the way an
+/// auction would: quote after FLAT_FEES + COMISSION, then BID_STRATEGY.
+pub async fn quote(
+    src_chain: &str,
+    dst_chain: &str,
+    token_in: &str,
+    amount_in: &str,
+    token_out: &str,
+    amount_out_min: &str,
+) -> Result<(), String> {
+    let amount_out_min = BigInt::from_str(amount_out_min)
+        .map_err(|e| format!("Invalid amount_out_min {amount_out_min}: {e}"))?;
+    BigInt::from_str(amount_in).map_err(|e| format!("Invalid amount_in {amount_in}: {e}"))?;
+
+    let timeout = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 3600;
+    let intent = PostIntentInfo {
+        function_name: String::from("transfer"),
+        src_chain: src_chain.to_string(),
+        dst_chain: dst_chain.to_string(),
+        inputs: OperationInput::SwapTransfer(SwapTransferInput {
+            token_in: token_address(token_in, src_chain),
+            amount_in: amount_in.to_string(),
+            src_chain_user: solver_address(src_chain).to_string(),
+            timeout: timeout.to_string(),
+        }),
+        outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+            token_out: token_address(token_out, dst_chain),
+            amount_out: amount_out_min.to_string(),
+            dst_chain_user: solver_address(dst_chain).to_string(),
+        }),
+    };
+
+    let quote = get_best_quote(&intent).await;
+    println!("{:#?}", quote);
+
+    let quote_amount = BigInt::from_str(&quote.amount_out).unwrap_or_default();
+    if quote_amount <= amount_out_min {
+        println!("Would not bid: {quote_amount} token_out is not above {amount_out_min}");
+        return Ok(());
+    }
+
+    let bid = price_bid(
+        BID_STRATEGY.as_ref(),
+        &BidInput {
+            intent: &intent,
+            quote: &quote,
+            quote_amount,
+            amount_out_min,
+        },
+    )
+    .await;
+    println!(
+        "Would bid {} token_out ({} strategy, {} bps margin, inputs {})",
+        bid.amount, bid.margin.strategy, bid.margin.bps, bid.margin.inputs
+    );
+
+    Ok(())
+}
+
+fn report(problems: &mut Vec<String>, what: &str, result: Result<String, String>) {
+    match result {
+        Ok(detail) => println!("ok    {what}: {detail}"),
+        Err(e) => {
+            println!("FAIL  {what}: {e}");
+            problems.push(what.to_string());
+        }
+    }
+}
+
+fn check_pool<C>(problems: &mut Vec<String>, name: &str, pool: &RpcPool<C>, required: bool) {
+    let status = pool.status();
+    if status.is_empty() {
+        if required {
+            report(problems, name, Err(String::from("not set")));
+        }
+        return;
+    }
+
+    for (url, healthy, latency_ms) in status {
+        report(
+            problems,
+            &format!("{name} {url}"),
+            if healthy {
+                Ok(format!("{latency_ms} ms"))
+            } else {
+                Err(String::from("down or lagging"))
+            },
+        );
+    }
+}
+
+/// Validates the configuration, RPC endpoints, balances and Ethereum
+/// approvals the solver needs before it can bid.
+pub async fn check() -> Result<(), String> {
+    let mut problems = Vec::new();
+
+    report(
+        &mut problems,
+        "COMPOSABLE_ENDPOINT",
+        env::var("COMPOSABLE_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty())
+            .ok_or_else(|| String::from("not set")),
+    );
+    report(
+        &mut problems,
+        "SOLVER_ID",
+        if SOLVER_ID.is_empty() {
+            Err(String::from("not set"))
+        } else {
+            Ok(SOLVER_ID.to_string())
+        },
+    );
+    report(
+        &mut problems,
+        "keys",
+        registration_message()
+            .await
+            .map(|_| String::from("identity and chain keys match SOLVER_ADDRESSES")),
+    );
+    report(
+        &mut problems,
+        "AUCTIONEER_ADDRESS",
+        check_auctioneer_address().map(|_| match *AUCTIONEER_ADDRESS {
+            Some(address) => format!("{:?}", address),
+            None => String::from("not set, auctioneer messages are NOT verified"),
+        }),
+    );
+    report(
+        &mut problems,
+        "BID_STRATEGY",
+        strategy_from_env().map(|_| String::from("valid")),
+    );
+
+    check_rpc_health().await;
+    check_pool(&mut problems, "ETHEREUM_RPC", &ETHEREUM, true);
+    check_pool(&mut problems, "SOLANA_RPC", &SOLANA, true);
+    check_pool(&mut problems, "MANTIS_RPC", &MANTIS, false);
+
+    for chain in CHAINS {
+        let mut tokens: Vec<(&str, &str)> = BRIDGE_TOKENS
+            .iter()
+            .map(|symbol| (get_token_info(symbol, chain).unwrap().0, symbol.as_str()))
+            .collect();
+        tokens.push((GAS_TOKEN, GAS_TOKEN));
+        for (token, symbol) in tokens {
+            report(
+                &mut problems,
+                &format!("{chain} {symbol} balance"),
+                get_balance(chain, token).await.and_then(|balance| {
+                    if balance.is_zero() {
+                        Err(String::from("empty"))
+                    } else {
+                        Ok(balance.to_string())
+                    }
+                }),
+            );
+        }
+    }
+
+    for symbol in BRIDGE_TOKENS.iter() {
+        let (token, _) = get_token_info(symbol, "ethereum").unwrap();
+        for (spender, name) in [(ESCROW_SC_ETHEREUM, "Escrow"), (PARASWAP, "Paraswap")] {
+            report(
+                &mut problems,
+                &format!("{symbol} allowance to {name}"),
+                erc20_allowance(token, spender).await.and_then(|allowance| {
+                    if allowance.is_zero() {
+                        Err(String::from("not approved, run `approve`"))
+                    } else {
+                        Ok(allowance.to_string())
+                    }
+                }),
+            );
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} check(s) failed: {}",
+            problems.len(),
+            problems.join(", ")
+        ))
+    }
+}
+
+/// Approves `token` (every BRIDGE_TOKEN by default) on Ethereum to the Escrow
+/// and Paraswap contracts, for `amount`, or the maximum if `unlimited`.
+pub async fn approve(
+    token: Option<&str>,
+    amount: Option<&str>,
+    unlimited: bool,
+) -> Result<(), String> {
+    let tokens: Vec<String> = match token {
+        Some(token) => vec![token_address(token, "ethereum")],
+        None => BRIDGE_TOKENS
+            .iter()
+            .map(|symbol| token_address(symbol, "ethereum"))
+            .collect(),
+    };
+    let amount = match (amount, unlimited) {
+        (Some(amount), false) => {
+            U256::from_dec_str(amount).map_err(|e| format!("Invalid amount {amount}: {e}"))?;
+            amount.to_string()
+        }
+        (None, true) => U256::MAX.to_string(),
+        _ => return Err(String::from("Give either an amount or unlimited")),
+    };
+    let rpc_url = ETHEREUM.url()?;
+
+    for token in tokens {
+        for (spender, name) in [(ESCROW_SC_ETHEREUM, "Escrow"), (PARASWAP, "Paraswap")] {
+            approve_erc20(&rpc_url, &token, spender, &amount)
+                .await
+                .map_err(|e| format!("Failed to approve {token} to {name}: {e}"))?;
+            println!("Approved {amount} {token} to {name} {spender}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the solver's bridge token and gas token balances on every chain.
+pub async fn balances() -> Result<(), String> {
+    for chain in CHAINS {
+        let mut tokens: Vec<(&str, &str, u32)> = BRIDGE_TOKENS
+            .iter()
+            .map(|symbol| {
+                let (token, decimals) = get_token_info(symbol, chain).unwrap();
+                (token, symbol.as_str(), decimals)
+            })
+            .collect();
+        tokens.push((GAS_TOKEN, GAS_TOKEN, if *chain == "ethereum" { 18 } else { 9 }));
+
+        for (token, symbol, decimals) in tokens {
+            match get_balance(chain, token).await {
+                Ok(balance) => println!(
+                    "{chain} {symbol}: {balance} ({})",
+                    units(&balance, decimals)
+                ),
+                Err(e) => println!("{chain} {symbol}: {e}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Connects to COMPOSABLE_ENDPOINT, sends the signed registration and waits
+/// for the auctioneer to accept (code 3) or reject it, without bidding.
+pub async fn register_test() -> Result<(), String> {
+    let server_addr = env::var("COMPOSABLE_ENDPOINT").unwrap_or_else(|_| String::from(""));
+    let solver_signer = identity_signer()?;
+
+    let mut json_data = registration_message().await?;
+    create_keccak256_signature(&mut json_data, &solver_signer)
+        .await
+        .map_err(|e| format!("Failed to sign the registration: {e}"))?;
+
+    let (ws_stream, _) = connect_async(&server_addr)
+        .await
+        .map_err(|e| format!("Failed to connect to {server_addr}: {e}"))?;
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    ws_sender
+        .send(Message::Text(json_data.to_string()))
+        .await
+        .map_err(|e| format!("Failed to send the registration: {e}"))?;
+
+    let answer = tokio::time::timeout(Duration::from_secs(30), async {
+        while let Some(msg) = ws_receiver.next().await {
+            match msg {
+                Ok(Message::Text(text)) => {
+                    let parsed: Value = serde_json::from_str(&text)
+                        .map_err(|e| format!("Invalid message {text}: {e}"))?;
+                    // auctions can come in before the answer
+                    if let Some(code @ (0 | 3)) = parsed.get("code").and_then(Value::as_u64) {
+                        return Ok((code, parsed));
+                    }
+                }
+                Ok(Message::Close(_)) => break,
+                Err(e) => return Err(e.to_string()),
+                _ => {}
+            }
+        }
+        Err(String::from("connection closed"))
+    })
+    .await
+    .map_err(|_| String::from("no answer from the auctioneer within 30s"))??;
+
+    let _ = ws_sender.send(Message::Close(None)).await;
+    println!("{:#?}", answer.1);
+
+    if answer.0 == 3 {
+        Ok(())
+    } else {
+        Err(String::from("registration rejected"))
+    }
+}
@@ -0,0 +1,158 @@
+use crate::chains::ethereum::ethereum_chain::ethereum_simulate_fill;
+use crate::chains::solana::solana_chain::solana_simulate_fill;
+use crate::chains::PostIntentInfo;
+use crate::routers::IntentQuote;
+use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use num_traits::Zero;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+/// DRY_RUN: "log" quotes every intent and logs the bid instead of sending it,
+/// "bid" sends bids to the auctioneer too. In both, fills are simulated with
+/// eth_call / simulate_transaction and never broadcast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DryRun {
+    Off,
+    Log,
+    Bid,
+}
+
+impl DryRun {
+    fn from_env() -> Self {
+        match env::var("DRY_RUN").unwrap_or_default().as_str() {
+            "" | "off" => DryRun::Off,
+            "log" => DryRun::Log,
+            "bid" => DryRun::Bid,
+            other => panic!("Unknown DRY_RUN {other}, expected off, log or bid"),
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref DRY_RUN: DryRun = DryRun::from_env();
+
+    // one line per simulated fill, to compare fee / commission settings later
+    static ref DRY_RUN_RECORD: String =
+        env::var("DRY_RUN_RECORD").unwrap_or_else(|_| String::from("dry_run.jsonl"));
+
+    // <intent_id, quote> bids sent with DRY_RUN=bid, until the auction settles
+    static ref BIDS: Arc<RwLock<HashMap<String, IntentQuote>>> = {
+        let m = HashMap::new();
+        Arc::new(RwLock::new(m))
+    };
+
+    // (simulated fills, their summed PnL in bridge token units)
+    static ref TOTALS: Arc<RwLock<(u64, BigInt)>> = Arc::new(RwLock::new((0, BigInt::zero())));
+}
+
+pub async fn remember_bid(intent_id: &str, quote: &IntentQuote) {
+    BIDS.write()
+        .await
+        .insert(intent_id.to_string(), quote.clone());
+}
+
+pub async fn take_bid(intent_id: &str) -> Option<IntentQuote> {
+    BIDS.write().await.remove(intent_id)
+}
+
+/// Simulates filling `intent` for `amount` token_out as `quote` planned and
+/// records the PnL it would have made: the bridge token token_in is worth on
+/// src_chain minus what the `amount` promised in the bid costs in bridge token
+/// at the simulated rate, so the margin kept and the price moving since the bid
+/// both show. Gas is left out, FLAT_FEES are meant to cover it.
+pub async fn simulate_fill(
+    intent_id: &str,
+    intent: &PostIntentInfo,
+    amount: &str,
+    quote: &IntentQuote,
+) {
+    // a direct fill sends token_out as if it was the bridge token
+    let bridge_token = quote.direct_token.as_ref().unwrap_or(&quote.bridge_token_dst);
+    let simulation = match intent.dst_chain.as_str() {
+        "ethereum" => {
+            ethereum_simulate_fill(
+                intent,
+                amount,
+                bridge_token,
+                &quote.bridge_amount_dst,
+            )
+            .await
+        }
+        "solana" => {
+            solana_simulate_fill(
+                intent,
+                amount,
+                bridge_token,
+                &quote.bridge_amount_dst,
+            )
+            .await
+        }
+        chain => Err(format!("fills on {chain} can't be simulated")),
+    };
+
+    // token_out bridge_amount_dst is worth: what the route gives now, or the
+    // quote itself for a direct fill since nothing is swapped
+    let simulation = simulation.and_then(|amount_out| {
+        let amount_out = match quote.direct_token {
+            Some(_) => BigInt::from_str(&quote.amount_out).unwrap_or_default(),
+            None => amount_out,
+        };
+        let promised =
+            BigInt::from_str(amount).map_err(|e| format!("Invalid amount {amount}: {e}"))?;
+        if amount_out.is_zero() {
+            return Err(String::from("the fill returns no token_out"));
+        }
+        Ok((&quote.bridge_amount_dst * &promised / &amount_out, amount_out))
+    });
+
+    let pnl = simulation
+        .as_ref()
+        .ok()
+        .map(|(bridge_cost, _)| &quote.bridge_amount_src - bridge_cost);
+    let (fills, total) = {
+        let mut totals = TOTALS.write().await;
+        if let Some(pnl) = &pnl {
+            totals.0 += 1;
+            totals.1 += pnl;
+        }
+        totals.clone()
+    };
+
+    match (&simulation, &pnl) {
+        (Ok((bridge_cost, amount_out)), Some(pnl)) => println!(
+            "[dry run] Intent {intent_id}: fill of {amount} token_out simulated, {amount_out} available for {bridge_cost} bridge token, PnL {pnl} ({total} over {fills} fills)"
+        ),
+        (Err(e), _) => println!("[dry run] Intent {intent_id}: fill would fail: {e}"),
+        (Ok(_), None) => {}
+    }
+
+    let record = json!({
+        "timestamp": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        "intent_id": intent_id,
+        "src_chain": intent.src_chain,
+        "dst_chain": intent.dst_chain,
+        "amount": amount,
+        "bridge_amount_src": quote.bridge_amount_src.to_string(),
+        "bridge_amount_dst": quote.bridge_amount_dst.to_string(),
+        "simulated_amount_out": simulation.as_ref().ok().map(|(_, amount_out)| amount_out.to_string()),
+        "bridge_cost": simulation.as_ref().ok().map(|(bridge_cost, _)| bridge_cost.to_string()),
+        "pnl": pnl.map(|pnl| pnl.to_string()),
+        "error": simulation.err(),
+    });
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(DRY_RUN_RECORD.as_str())
+        .and_then(|mut file| writeln!(file, "{record}"));
+    if let Err(e) = written {
+        eprintln!("Failed to write {}: {e}", DRY_RUN_RECORD.as_str());
+    }
+}
@@ -0,0 +1,395 @@
+use crate::chains::ethereum::ethereum_chain::UsdtContract;
+use crate::chains::{get_token_info, AUCTION_DURATION_SECS, SOLVER_ADDRESSES};
+use crate::recorder::{record, replayed_balance, replaying, Event};
+use crate::rpc::{RpcFailure, ETHEREUM, SOLANA};
+use ethers::prelude::*;
+use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use num_traits::Zero;
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+// token key used for the native gas token (ETH / SOL) of a chain
+pub const GAS_TOKEN: &str = "gas";
+
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    pub chain: String,
+    // token the fill spends: the bridge token, or token_out filled from inventory
+    pub token: String,
+    pub amount: BigInt,
+    // bridge token the bid went through, and the fill's value in it
+    pub bridge_token: String,
+    pub notional: BigInt,
+    // bridge token token_in is worth on src_chain, what the fill earns
+    pub value_in: BigInt,
+    // a bid's reservation lapses when no code 4 comes, None once the intent is won
+    pub expires_at: Option<Instant>,
+}
+
+impl Reservation {
+    pub fn is_live(&self) -> bool {
+        self.expires_at.map_or(true, |expires_at| expires_at > Instant::now())
+    }
+}
+
+/// Key a token is cached and compared under: EVM addresses are case
+/// insensitive, Solana mints are not.
+pub fn token_key(token: &str) -> String {
+    if token.starts_with("0x") {
+        token.to_lowercase()
+    } else {
+        token.to_string()
+    }
+}
+
+lazy_static! {
+    // <(chain, token), (balance, fetched_at)>
+    static ref BALANCES: Arc<RwLock<HashMap<(String, String), (BigInt, Instant)>>> = {
+        let m = HashMap::new();
+        Arc::new(RwLock::new(m))
+    };
+
+    // <intent_id, Reservation> funds promised to intents we bid on and not yet filled,
+    // until the auction is over or, once won, until the fill is done
+    pub static ref RESERVATIONS: Arc<RwLock<HashMap<String, Reservation>>> = {
+        let m = HashMap::new();
+        Arc::new(RwLock::new(m))
+    };
+
+    // <(chain, token), delta> applied on top of on-chain balances by the mock bridge
+    static ref MOCK_OFFSETS: Arc<RwLock<HashMap<(String, String), BigInt>>> = {
+        let m = HashMap::new();
+        Arc::new(RwLock::new(m))
+    };
+
+    // <(chain, token), target> tokens besides the bridge tokens held as inventory
+    static ref INVENTORY_TARGETS: HashMap<(String, String), BigInt> = inventory_targets_from_env();
+
+    static ref BALANCE_TTL: Duration = Duration::from_secs(
+        env::var("INVENTORY_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|ttl| ttl.parse::<u64>().ok())
+            .unwrap_or(15),
+    );
+
+    static ref RESERVATION_TTL: Duration = Duration::from_secs(
+        *AUCTION_DURATION_SECS
+            + env::var("RESERVATION_MARGIN_SECS")
+                .ok()
+                .and_then(|margin| margin.parse::<u64>().ok())
+                .unwrap_or(30),
+    );
+}
+
+async fn fetch_balance(chain: &str, token: &str) -> Result<BigInt, String> {
+    match chain {
+        "ethereum" => {
+            let owner = Address::from_str(SOLVER_ADDRESSES.get(0).unwrap())
+                .map_err(|e| format!("Invalid Ethereum solver address: {}", e))?;
+
+            let balance = ETHEREUM
+                .with_failover(|provider| async move {
+                    if token == GAS_TOKEN {
+                        provider
+                            .get_balance(owner, None)
+                            .await
+                            .map_err(|e| RpcFailure::ethereum("Failed to get ETH balance", &e))
+                    } else {
+                        let token_address = Address::from_str(token)
+                            .map_err(|e| format!("Invalid token address {token}: {}", e))?;
+                        UsdtContract::new(token_address, provider)
+                            .balance_of(owner)
+                            .call()
+                            .await
+                            .map_err(|e| {
+                                RpcFailure::contract(&format!("Failed to get {token} balance"), &e)
+                            })
+                    }
+                })
+                .await?;
+
+            BigInt::from_str(&balance.to_string()).map_err(|e| e.to_string())
+        }
+        "solana" | "mantis" => {
+            let owner = Pubkey::from_str(SOLVER_ADDRESSES.get(1).unwrap())
+                .map_err(|e| format!("Invalid Solana solver address: {}", e))?;
+
+            if token == GAS_TOKEN {
+                let lamports = SOLANA
+                    .with_failover(|client| async move {
+                        client
+                            .get_balance(&owner)
+                            .await
+                            .map_err(|e| RpcFailure::svm("Failed to get SOL balance", &e))
+                    })
+                    .await?;
+                Ok(BigInt::from(lamports))
+            } else {
+                let mint =
+                    Pubkey::from_str(token).map_err(|e| format!("Invalid mint {token}: {}", e))?;
+                // a missing token account simply means we hold none of it
+                match SOLANA
+                    .client()?
+                    .get_token_account_balance(&get_associated_token_address(&owner, &mint))
+                    .await
+                {
+                    Ok(balance) => BigInt::from_str(&balance.amount).map_err(|e| e.to_string()),
+                    Err(_) => Ok(BigInt::zero()),
+                }
+            }
+        }
+        _ => Err(format!("chain {chain} not supported")),
+    }
+}
+
+/// Balance of `token` (or `GAS_TOKEN`) held by the solver on `chain`, cached for
+/// INVENTORY_CACHE_TTL_SECS so bidding does not hit the RPC on every intent.
+pub async fn get_balance(chain: &str, token: &str) -> Result<BigInt, String> {
+    if replaying() {
+        return replayed_balance(chain, token).await;
+    }
+
+    let key = (chain.to_string(), token_key(token));
+
+    {
+        let balances = BALANCES.read().await;
+        if let Some((balance, fetched_at)) = balances.get(&key) {
+            if fetched_at.elapsed() < *BALANCE_TTL {
+                return Ok(balance.clone());
+            }
+        }
+    }
+
+    let mut balance = fetch_balance(chain, token).await?;
+    if let Some(offset) = MOCK_OFFSETS.read().await.get(&key) {
+        balance += offset;
+    }
+
+    let mut balances = BALANCES.write().await;
+    balances.insert(key, (balance.clone(), Instant::now()));
+    drop(balances);
+
+    record(Event::Balance {
+        chain: chain.to_string(),
+        token: token.to_string(),
+        balance: balance.to_string(),
+    })
+    .await;
+
+    Ok(balance)
+}
+
+/// Drops every cached balance, call it after funds moved (fills, swaps, bridging).
+pub async fn invalidate_balances() {
+    let mut balances = BALANCES.write().await;
+    balances.clear();
+}
+
+/// Caches `balance` as just fetched, so tests can quote without an RPC.
+#[cfg(test)]
+pub(crate) async fn set_balance(chain: &str, token: &str, balance: BigInt) {
+    let mut balances = BALANCES.write().await;
+    balances.insert((chain.to_string(), token_key(token)), (balance, Instant::now()));
+}
+
+/// Pretends `delta` of `token` moved in or out of the solver on `chain`, used by
+/// the mock bridge so rebalancing can be exercised without moving funds.
+pub async fn apply_mock_offset(chain: &str, token: &str, delta: BigInt) {
+    let key = (chain.to_string(), token_key(token));
+
+    let mut offsets = MOCK_OFFSETS.write().await;
+    *offsets.entry(key.clone()).or_insert_with(BigInt::zero) += &delta;
+    drop(offsets);
+
+    let mut balances = BALANCES.write().await;
+    balances.remove(&key);
+}
+
+pub async fn reserved(chain: &str, token: &str) -> BigInt {
+    let reservations = RESERVATIONS.read().await;
+    reservations
+        .values()
+        .filter(|r| r.is_live() && r.chain == chain && token_key(&r.token) == token_key(token))
+        .fold(BigInt::zero(), |acc, r| acc + &r.amount)
+}
+
+/// Balance minus the amounts promised to open bids and in-flight wins.
+pub async fn available(chain: &str, token: &str) -> Result<BigInt, String> {
+    let balance = get_balance(chain, token).await?;
+    let available = balance - reserved(chain, token).await;
+
+    Ok(if available < BigInt::zero() {
+        BigInt::zero()
+    } else {
+        available
+    })
+}
+
+/// When the reservation of a bid made now lapses, RESERVATION_MARGIN_SECS after
+/// the auction should have ended.
+pub fn reservation_deadline() -> Instant {
+    Instant::now() + *RESERVATION_TTL
+}
+
+pub async fn reserve(intent_id: &str, reservation: Reservation) {
+    let mut reservations = RESERVATIONS.write().await;
+    // auctions that ended without a code 4 for us
+    reservations.retain(|_, reservation| reservation.is_live());
+    reservations.insert(intent_id.to_string(), reservation);
+}
+
+/// Keeps the reservation of a won intent until it is released after the fill.
+pub async fn hold(intent_id: &str) {
+    if let Some(reservation) = RESERVATIONS.write().await.get_mut(intent_id) {
+        reservation.expires_at = None;
+    }
+}
+
+/// Bridge token the bid on `intent_id` went through.
+pub async fn reserved_token(intent_id: &str) -> Option<String> {
+    RESERVATIONS
+        .read()
+        .await
+        .get(intent_id)
+        .map(|reservation| reservation.bridge_token.clone())
+}
+
+/// (notional, value_in) of the bid on `intent_id`.
+pub async fn reserved_valuation(intent_id: &str) -> Option<(BigInt, BigInt)> {
+    RESERVATIONS
+        .read()
+        .await
+        .get(intent_id)
+        .map(|reservation| (reservation.notional.clone(), reservation.value_in.clone()))
+}
+
+/// Amount of `token` reserved for `intent_id`, none when its bid reserved
+/// another token.
+pub async fn reserved_amount(intent_id: &str, token: &str) -> Option<BigInt> {
+    RESERVATIONS
+        .read()
+        .await
+        .get(intent_id)
+        .filter(|reservation| token_key(&reservation.token) == token_key(token))
+        .map(|reservation| reservation.amount.clone())
+}
+
+pub async fn release(intent_id: &str) {
+    let mut reservations = RESERVATIONS.write().await;
+    reservations.remove(intent_id);
+}
+
+fn min_gas_balance(chain: &str) -> BigInt {
+    let (var, default) = match chain {
+        "ethereum" => ("MIN_GAS_BALANCE_ETHEREUM", 10_000_000_000_000_000u64), // 0.01 ETH
+        _ => ("MIN_GAS_BALANCE_SOLANA", 10_000_000u64),                      // 0.01 SOL
+    };
+
+    env::var(var)
+        .ok()
+        .and_then(|min| BigInt::from_str(&min).ok())
+        .unwrap_or_else(|| BigInt::from(default))
+}
+
+async fn check_gas(chain: &str) -> Result<(), String> {
+    let gas = get_balance(chain, GAS_TOKEN).await?;
+    if gas < min_gas_balance(chain) {
+        return Err(format!("not enough gas on {chain}: {gas}"));
+    }
+
+    Ok(())
+}
+
+/// Checks that the solver can fund `amount` of `bridge_token` on `chain` and
+/// returns the amount it can actually spend. With INVENTORY_MODE="cap" the bid is
+/// capped to the available inventory, otherwise (default "skip") it is refused.
+pub async fn check_funding(chain: &str, bridge_token: &str, amount: &BigInt) -> Result<BigInt, String> {
+    check_gas(chain).await?;
+
+    let available = available(chain, bridge_token).await?;
+    if &available >= amount {
+        return Ok(amount.clone());
+    }
+
+    let mode = env::var("INVENTORY_MODE").unwrap_or_else(|_| String::from("skip"));
+    if mode == "cap" && !available.is_zero() {
+        println!("Capping bid on {chain} to {available} {bridge_token} (wanted {amount})");
+        Ok(available)
+    } else {
+        Err(format!(
+            "not enough {bridge_token} on {chain}: {available} available, {amount} needed"
+        ))
+    }
+}
+
+/// Parses INVENTORY_TARGETS, "chain:TOKEN:target,..." with TOKEN a known symbol
+/// or an address / mint and target in its smallest unit.
+fn inventory_targets_from_env() -> HashMap<(String, String), BigInt> {
+    env::var("INVENTORY_TARGETS")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let parts: Vec<&str> = entry.trim().split(':').collect();
+            if parts.len() != 3 {
+                eprintln!("Invalid INVENTORY_TARGETS entry: {entry}");
+                return None;
+            }
+
+            let target = BigInt::from_str(parts[2]).ok()?;
+            let token = get_token_info(&parts[1].to_uppercase(), parts[0])
+                .map(|(address, _)| address.to_string())
+                .unwrap_or_else(|| parts[1].to_string());
+            Some(((parts[0].to_string(), token_key(&token)), target))
+        })
+        .collect()
+}
+
+pub fn inventory_target(chain: &str, token: &str) -> Option<&'static BigInt> {
+    INVENTORY_TARGETS.get(&(chain.to_string(), token_key(token)))
+}
+
+/// Whether `amount` of `token_out` can go to the user straight from inventory on
+/// `chain` instead of being bought with the bridge token. Only INVENTORY_TARGETS
+/// tokens are spent, and not what other intents reserved (the reservation of
+/// `intent_id` itself counts as available).
+pub async fn fills_from_inventory(
+    intent_id: Option<&str>,
+    chain: &str,
+    token_out: &str,
+    amount: &BigInt,
+) -> bool {
+    if inventory_target(chain, token_out).is_none() || check_gas(chain).await.is_err() {
+        return false;
+    }
+
+    let own = match intent_id {
+        Some(intent_id) => reserved_amount(intent_id, token_out)
+            .await
+            .unwrap_or_default(),
+        None => BigInt::zero(),
+    };
+    match available(chain, token_out).await {
+        Ok(available) => available + own >= *amount,
+        Err(_) => false,
+    }
+}
+
+/// Whether single-domain token_in received on `chain` is kept as inventory
+/// instead of swapped to the bridge token: while its balance is below target.
+pub async fn keeps_token_in(chain: &str, token_in: &str) -> bool {
+    match inventory_target(chain, token_in) {
+        Some(target) => match get_balance(chain, token_in).await {
+            Ok(balance) => balance < *target,
+            Err(_) => false,
+        },
+        None => false,
+    }
+}
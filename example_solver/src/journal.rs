@@ -0,0 +1,182 @@
+use crate::chains::{default_bridge_token, PostIntentInfo};
+use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub intent: PostIntentInfo,
+    pub amount: String,
+    // dst_chain address of the bridge token we bid with, unset in older journals
+    #[serde(default)]
+    pub bridge_token: Option<String>,
+    // the bid's valuation in bridge token, as in its Reservation, for the PnL
+    // of fills done after the reservation is gone; unset in older journals
+    #[serde(default)]
+    pub notional: Option<String>,
+    #[serde(default)]
+    pub value_in: Option<String>,
+}
+
+impl JournalEntry {
+    pub fn bridge_token(&self) -> String {
+        self.bridge_token
+            .clone()
+            .unwrap_or_else(|| default_bridge_token(&self.intent.dst_chain))
+    }
+
+    /// (notional, value_in) of the bid, when the journal has them.
+    pub fn valuation(&self) -> Option<(BigInt, BigInt)> {
+        Some((
+            BigInt::from_str(self.notional.as_ref()?).ok()?,
+            BigInt::from_str(self.value_in.as_ref()?).ok()?,
+        ))
+    }
+}
+
+lazy_static! {
+    // won intents that are not filled yet, persisted so they survive restarts
+    static ref JOURNAL_PATH: String =
+        env::var("INTENT_JOURNAL").unwrap_or_else(|_| String::from("intents_journal.json"));
+    static ref JOURNAL_LOCK: Arc<Mutex<()>> = Arc::new(Mutex::new(()));
+}
+
+// a journal that doesn't parse is moved to `<path>.corrupt` instead of being
+// overwritten by the next write, its intents have to be recovered by hand
+fn read_journal(path: &str) -> Result<HashMap<String, JournalEntry>, String> {
+    let journal = match fs::read_to_string(path) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(format!("Failed to read intent journal {path}: {e}")),
+    };
+
+    serde_json::from_str(&journal).map_err(|e| {
+        let corrupt = format!("{path}.corrupt");
+        match fs::rename(path, &corrupt) {
+            Ok(()) => format!("Intent journal {path} is corrupt ({e}), moved to {corrupt}"),
+            Err(rename) => format!("Intent journal {path} is corrupt ({e}), failed to move it: {rename}"),
+        }
+    })
+}
+
+// written next to the journal then renamed over it, a crash mid-write leaves
+// the previous journal intact
+fn write_journal(path: &str, journal: &HashMap<String, JournalEntry>) {
+    let journal = serde_json::to_string_pretty(journal).unwrap();
+    let tmp_path = format!("{path}.tmp");
+    let written = fs::File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(journal.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if let Err(e) = written {
+        eprintln!("Failed to write intent journal {path}: {e}");
+    }
+}
+
+// reads and fsyncs off the runtime threads, callers hold JOURNAL_LOCK
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f)
+        .await
+        .expect("intent journal task panicked")
+}
+
+/// Journals a won intent, with the (notional, value_in) of its bid when known.
+pub async fn record_won(
+    intent_id: &str,
+    intent: &PostIntentInfo,
+    amount: &str,
+    bridge_token: &str,
+    valuation: Option<(BigInt, BigInt)>,
+) {
+    let _lock = JOURNAL_LOCK.lock().await;
+    let intent_id = intent_id.to_string();
+    let entry = JournalEntry {
+        intent: intent.clone(),
+        amount: amount.to_string(),
+        bridge_token: Some(bridge_token.to_string()),
+        notional: valuation.as_ref().map(|(notional, _)| notional.to_string()),
+        value_in: valuation.as_ref().map(|(_, value_in)| value_in.to_string()),
+    };
+    blocking(move || {
+        let path = JOURNAL_PATH.as_str();
+        let mut journal = match read_journal(path) {
+            Ok(journal) => journal,
+            // moved aside, intents won from now on go to a new journal
+            Err(e) if !Path::new(path).exists() => {
+                eprintln!("{e}");
+                HashMap::new()
+            }
+            Err(e) => {
+                eprintln!("{e}, not journaling intent {intent_id}");
+                return;
+            }
+        };
+        journal.insert(intent_id, entry);
+        write_journal(path, &journal);
+    })
+    .await
+}
+
+pub async fn mark_done(intent_id: &str) {
+    let _lock = JOURNAL_LOCK.lock().await;
+    let intent_id = intent_id.to_string();
+    blocking(move || match read_journal(JOURNAL_PATH.as_str()) {
+        Ok(mut journal) => {
+            if journal.remove(&intent_id).is_some() {
+                write_journal(JOURNAL_PATH.as_str(), &journal);
+            }
+        }
+        Err(e) => eprintln!("{e}, intent {intent_id} not marked done"),
+    })
+    .await
+}
+
+/// Won intents not filled yet. Errs when the journal can't be read or is
+/// corrupt, the latter then kept as `<INTENT_JOURNAL>.corrupt`.
+pub async fn pending() -> Result<HashMap<String, JournalEntry>, String> {
+    let _lock = JOURNAL_LOCK.lock().await;
+    blocking(|| read_journal(JOURNAL_PATH.as_str())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("example_solver_{name}_{}.json", std::process::id()));
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn missing_journal_is_empty() {
+        assert!(read_journal(&path("journal_missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn corrupt_journal_is_kept_aside() {
+        let path = path("journal_corrupt");
+        fs::write(&path, "{\"1\": {\"intent\"").unwrap();
+
+        let err = read_journal(&path).unwrap_err();
+        assert!(err.contains("is corrupt"), "{err}");
+        assert!(!Path::new(&path).exists());
+        let corrupt = format!("{path}.corrupt");
+        assert_eq!(fs::read_to_string(&corrupt).unwrap(), "{\"1\": {\"intent\"");
+
+        // and the next write starts a new journal
+        write_journal(&path, &HashMap::new());
+        assert!(read_journal(&path).unwrap().is_empty());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&corrupt).unwrap();
+    }
+}
@@ -0,0 +1,23 @@
+pub mod admin;
+pub mod backtest;
+pub mod chains;
+pub mod commands;
+pub mod dry_run;
+pub mod inventory;
+pub mod journal;
+pub mod listeners;
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock_routers;
+pub mod rebalancer;
+pub mod recorder;
+pub mod risk;
+pub mod routers;
+pub mod rpc;
+pub mod signer;
+pub mod strategy;
+
+// the chain modules reach these through the crate root
+use chains::{OperationInput, PostIntentInfo, SOLVER_ADDRESSES, SOLVER_ID};
+use serde_json::json;
+use spl_associated_token_account::get_associated_token_address;
+use std::env;
@@ -0,0 +1,447 @@
+use crate::chains::ethereum::ethereum_chain::ESCROW_SC_ETHEREUM;
+use crate::chains::solana::solana_chain::solana_fetch_intent;
+use crate::chains::{
+    OperationInput, OperationOutput, PostIntentInfo, SwapTransferInput, SwapTransferOutput,
+};
+use crate::inventory::token_key;
+use crate::routers::{get_best_quote, IntentQuote};
+use crate::rpc::{ETHEREUM, SOLANA};
+use base64::Engine;
+use ethers::prelude::*;
+use ethers::providers::{Provider, Ws};
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+abigen!(
+    EscrowEvents,
+    r#"[
+        event IntentPosted(uint256 indexed intentId, address tokenIn, uint256 amountIn, address srcUser, string dstUser, string tokenOut, string amountOut, string dstChain, uint256 timeout)
+    ]"#
+);
+
+#[derive(Debug, Clone)]
+pub struct OnchainIntent {
+    // decoded from the event, what `quote` was computed from
+    pub intent: PostIntentInfo,
+    pub src_chain: String,
+    pub seen_at: Instant,
+    pub quote: Option<IntentQuote>,
+    // set once the auctioneer broadcast the same intent_id
+    pub broadcast: bool,
+    // set once the audit warned it was withheld
+    pub warned: bool,
+}
+
+lazy_static! {
+    // <intent_id, OnchainIntent> intents seen in escrow events
+    pub static ref ONCHAIN_INTENTS: Arc<RwLock<HashMap<String, OnchainIntent>>> = {
+        let m = HashMap::new();
+        Arc::new(RwLock::new(m))
+    };
+
+    // how long a quote computed from the on-chain event can be bid as is
+    static ref PRECOMPUTED_QUOTE_TTL: Duration = Duration::from_secs(
+        env::var("PRECOMPUTED_QUOTE_TTL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .unwrap_or(10),
+    );
+}
+
+/// Registers an intent seen on-chain and pre-computes its quote so the bid is
+/// ready by the time the auctioneer broadcasts it.
+async fn on_intent_posted(intent_id: String, intent: PostIntentInfo) {
+    {
+        let mut intents = ONCHAIN_INTENTS.write().await;
+        if intents.contains_key(&intent_id) {
+            return;
+        }
+        intents.insert(
+            intent_id.clone(),
+            OnchainIntent {
+                intent: intent.clone(),
+                src_chain: intent.src_chain.clone(),
+                seen_at: Instant::now(),
+                quote: None,
+                broadcast: false,
+                warned: false,
+            },
+        );
+    }
+
+    println!("Intent {intent_id} posted on {}", intent.src_chain);
+
+    tokio::spawn(async move {
+        let quote = get_best_quote(&intent).await;
+
+        if let Some(onchain) = ONCHAIN_INTENTS.write().await.get_mut(&intent_id) {
+            onchain.quote = Some(quote);
+        }
+    });
+}
+
+// what the quote of an intent depends on, with addresses compared the way
+// tokens are; function_name is left out, the auctioneer names it differently
+fn quoted_fields(intent: &PostIntentInfo) -> Option<Vec<String>> {
+    let (OperationInput::SwapTransfer(input), OperationOutput::SwapTransfer(output)) =
+        (&intent.inputs, &intent.outputs)
+    else {
+        return None;
+    };
+
+    Some(vec![
+        intent.src_chain.to_lowercase(),
+        intent.dst_chain.to_lowercase(),
+        token_key(&input.token_in),
+        input.amount_in.clone(),
+        token_key(&input.src_chain_user),
+        input.timeout.clone(),
+        token_key(&output.token_out),
+        output.amount_out.clone(),
+        token_key(&output.dst_chain_user),
+    ])
+}
+
+/// Marks `intent_id` as broadcast by the auctioneer and hands back the quote
+/// pre-computed from the on-chain event, if it is still fresh and the event
+/// decoded to the same `intent` the auctioneer sent.
+pub async fn take_precomputed_quote(
+    intent_id: &str,
+    intent: &PostIntentInfo,
+) -> Option<IntentQuote> {
+    let mut intents = ONCHAIN_INTENTS.write().await;
+    let onchain = intents.get_mut(intent_id)?;
+    onchain.broadcast = true;
+
+    if onchain.seen_at.elapsed() >= *PRECOMPUTED_QUOTE_TTL {
+        return None;
+    }
+    let quote = onchain.quote.take()?;
+    let quoted = quoted_fields(&onchain.intent);
+    if quoted.is_none() || quoted != quoted_fields(intent) {
+        println!("Intent {intent_id} differs from its on-chain event, quoting it again");
+        return None;
+    }
+
+    Some(quote)
+}
+
+/// Warns about intents seen on-chain that the auctioneer did not broadcast
+/// within AUDIT_WITHHOLD_SECS, and forgets intents older than an hour.
+pub async fn run_withholding_audit() {
+    let withhold = Duration::from_secs(
+        env::var("AUDIT_WITHHOLD_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .unwrap_or(60),
+    );
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(10)).await;
+
+        let mut intents = ONCHAIN_INTENTS.write().await;
+        intents.retain(|intent_id, onchain| {
+            let age = onchain.seen_at.elapsed();
+            if !onchain.broadcast && !onchain.warned && age >= withhold {
+                onchain.warned = true;
+                println!(
+                    "AUDIT: intent {intent_id} posted on {} {}s ago was never broadcast by the auctioneer",
+                    onchain.src_chain,
+                    age.as_secs()
+                );
+            }
+            age < Duration::from_secs(3600)
+        });
+    }
+}
+
+fn ethereum_intent(event: IntentPostedFilter) -> (String, PostIntentInfo) {
+    let intent = PostIntentInfo {
+        function_name: "swap".to_string(),
+        src_chain: "ethereum".to_string(),
+        dst_chain: event.dst_chain.to_lowercase(),
+        inputs: OperationInput::SwapTransfer(SwapTransferInput {
+            token_in: format!("{:?}", event.token_in),
+            amount_in: event.amount_in.to_string(),
+            src_chain_user: format!("{:?}", event.src_user),
+            timeout: event.timeout.to_string(),
+        }),
+        outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+            token_out: event.token_out,
+            amount_out: event.amount_out,
+            dst_chain_user: event.dst_user,
+        }),
+    };
+
+    (event.intent_id.to_string(), intent)
+}
+
+/// Follows IntentPosted on the Ethereum escrow, over eth_subscribe when
+/// ETHEREUM_WS_RPC is set and by polling eth_getLogs on ETHEREUM_RPC otherwise.
+pub async fn listen_ethereum_intents() -> Result<(), String> {
+    let escrow_address = Address::from_str(ESCROW_SC_ETHEREUM).unwrap();
+
+    if let Some(ws_url) = env::var("ETHEREUM_WS_RPC").ok().filter(|url| !url.is_empty()) {
+        let provider = Provider::<Ws>::connect(ws_url.as_str())
+            .await
+            .map_err(|e| format!("Failed to connect to ETHEREUM_WS_RPC: {}", e))?;
+        let escrow = EscrowEvents::new(escrow_address, Arc::new(provider));
+        let events = escrow.intent_posted_filter();
+        let mut stream = events
+            .subscribe()
+            .await
+            .map_err(|e| format!("Failed to subscribe to escrow events: {}", e))?;
+
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(event) => {
+                    let (intent_id, intent) = ethereum_intent(event);
+                    on_intent_posted(intent_id, intent).await;
+                }
+                Err(e) => eprintln!("Failed to decode escrow event: {e}"),
+            }
+        }
+
+        return Err("Ethereum escrow subscription closed".to_string());
+    }
+
+    let provider = ETHEREUM.client()?;
+    let escrow = EscrowEvents::new(escrow_address, provider.clone());
+
+    let mut from_block = provider
+        .get_block_number()
+        .await
+        .map_err(|e| format!("Failed to get block number: {}", e))?;
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(4)).await;
+
+        let to_block = match provider.get_block_number().await {
+            Ok(block) => block,
+            Err(e) => {
+                eprintln!("Failed to get block number: {e}");
+                continue;
+            }
+        };
+        if to_block < from_block {
+            continue;
+        }
+
+        match escrow
+            .intent_posted_filter()
+            .from_block(from_block)
+            .to_block(to_block)
+            .query()
+            .await
+        {
+            Ok(events) => {
+                for event in events {
+                    let (intent_id, intent) = ethereum_intent(event);
+                    on_intent_posted(intent_id, intent).await;
+                }
+                from_block = to_block + 1;
+            }
+            Err(e) => eprintln!("Failed to query escrow events: {e}"),
+        }
+    }
+}
+
+// anchor events are "Program data: base64(discriminator || borsh(event))" and
+// bridge_escrow events start with the intent_id string
+fn intent_id_from_log(log: &str) -> Option<String> {
+    let data = log.strip_prefix("Program data: ")?;
+    let bytes = base64::engine::general_purpose::STANDARD.decode(data).ok()?;
+    let len = u32::from_le_bytes(bytes.get(8..12)?.try_into().ok()?) as usize;
+    if len == 0 || len > 64 {
+        return None;
+    }
+
+    String::from_utf8(bytes.get(12..12 + len)?.to_vec()).ok()
+}
+
+/// Follows the bridge_escrow program logs with logsSubscribe and loads every
+/// intent they mention from its `intent` account.
+pub async fn listen_solana_intents() -> Result<(), String> {
+    let ws_url = env::var("SOLANA_WS_RPC").ok().filter(|url| !url.is_empty()).unwrap_or_else(|| {
+        SOLANA
+            .url()
+            .unwrap_or_default()
+            .replace("https://", "wss://")
+            .replace("http://", "ws://")
+    });
+
+    let client = PubsubClient::new(&ws_url)
+        .await
+        .map_err(|e| format!("Failed to connect to {ws_url}: {}", e))?;
+    let (mut stream, _unsubscribe) = client
+        .logs_subscribe(
+            RpcTransactionLogsFilter::Mentions(vec![bridge_escrow::ID.to_string()]),
+            RpcTransactionLogsConfig {
+                commitment: Some(CommitmentConfig::confirmed()),
+            },
+        )
+        .await
+        .map_err(|e| format!("Failed to subscribe to bridge_escrow logs: {}", e))?;
+
+    while let Some(response) = stream.next().await {
+        if response.value.err.is_some() {
+            continue;
+        }
+
+        for intent_id in response.value.logs.iter().filter_map(|log| intent_id_from_log(log)) {
+            if ONCHAIN_INTENTS.read().await.contains_key(&intent_id) {
+                continue;
+            }
+
+            let escrowed = match solana_fetch_intent("solana", &intent_id).await {
+                Ok(Some(escrowed)) => escrowed,
+                // not an intent id, or the intent was already closed
+                Ok(None) => continue,
+                Err(e) => {
+                    eprintln!("Failed to load intent {intent_id}: {e}");
+                    continue;
+                }
+            };
+
+            let user_out = escrowed.user_out.to_string();
+            let dst_chain = if escrowed.single_domain {
+                "solana"
+            } else if user_out.starts_with("0x") {
+                "ethereum"
+            } else {
+                "mantis"
+            };
+
+            let intent = PostIntentInfo {
+                function_name: "swap".to_string(),
+                src_chain: "solana".to_string(),
+                dst_chain: dst_chain.to_string(),
+                inputs: OperationInput::SwapTransfer(SwapTransferInput {
+                    token_in: escrowed.token_in.to_string(),
+                    amount_in: escrowed.amount_in.to_string(),
+                    src_chain_user: escrowed.user_in.to_string(),
+                    timeout: escrowed.timeout_timestamp_in_sec.to_string(),
+                }),
+                outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+                    token_out: escrowed.token_out.to_string(),
+                    amount_out: escrowed.amount_out.to_string(),
+                    dst_chain_user: user_out,
+                }),
+            };
+
+            on_intent_posted(intent_id, intent).await;
+        }
+    }
+
+    Err("bridge_escrow log subscription closed".to_string())
+}
+
+/// Runs the listeners named in LISTEN_ONCHAIN_INTENTS ("ethereum,solana"),
+/// restarting them when their connection drops.
+pub fn spawn_intent_listeners() {
+    let chains = env::var("LISTEN_ONCHAIN_INTENTS").unwrap_or_default();
+    if chains.trim().is_empty() {
+        return;
+    }
+
+    for chain in chains.split(',').map(|chain| chain.trim().to_lowercase()) {
+        tokio::spawn(async move {
+            loop {
+                let result = match chain.as_str() {
+                    "ethereum" => listen_ethereum_intents().await,
+                    "solana" => listen_solana_intents().await,
+                    _ => {
+                        eprintln!("No intent listener for chain {chain}");
+                        return;
+                    }
+                };
+
+                if let Err(e) = result {
+                    eprintln!("{chain} intent listener stopped: {e}, restarting");
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+    }
+
+    tokio::spawn(run_withholding_audit());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intent(token_in: &str, amount_in: &str) -> PostIntentInfo {
+        PostIntentInfo {
+            function_name: String::from("swap"),
+            src_chain: String::from("ethereum"),
+            dst_chain: String::from("solana"),
+            inputs: OperationInput::SwapTransfer(SwapTransferInput {
+                token_in: token_in.to_string(),
+                amount_in: amount_in.to_string(),
+                src_chain_user: String::from("0x0000000000000000000000000000000000000001"),
+                timeout: String::from("1700000000"),
+            }),
+            outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+                token_out: String::from("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB"),
+                amount_out: String::from("99000000"),
+                dst_chain_user: String::from("5zCZ3jk8EZnJyG7fhDqD6tmqiYTLZjik5HUpGMnHrZfC"),
+            }),
+        }
+    }
+
+    async fn seen(intent_id: &str, intent: PostIntentInfo) {
+        ONCHAIN_INTENTS.write().await.insert(
+            intent_id.to_string(),
+            OnchainIntent {
+                src_chain: intent.src_chain.clone(),
+                intent,
+                seen_at: Instant::now(),
+                quote: Some(IntentQuote {
+                    amount_out: String::from("99500000"),
+                    ..IntentQuote::default()
+                }),
+                broadcast: false,
+                warned: false,
+            },
+        );
+    }
+
+    const USDT: &str = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+
+    #[tokio::test]
+    async fn same_intent_reuses_the_quote() {
+        seen("listeners-same", intent(USDT, "100000000")).await;
+
+        // the auctioneer names it "transfer" and checksums the address
+        let mut broadcast = intent("0xdAC17F958D2ee523a2206206994597C13D831ec7", "100000000");
+        broadcast.function_name = String::from("transfer");
+        let quote = take_precomputed_quote("listeners-same", &broadcast).await;
+        assert_eq!(quote.map(|quote| quote.amount_out), Some(String::from("99500000")));
+        assert!(ONCHAIN_INTENTS.read().await["listeners-same"].broadcast);
+    }
+
+    #[tokio::test]
+    async fn different_intent_is_quoted_again() {
+        seen("listeners-different", intent(USDT, "100000000")).await;
+
+        let quote = take_precomputed_quote("listeners-different", &intent(USDT, "200000000")).await;
+        assert!(quote.is_none());
+        // still counts as broadcast for the withholding audit
+        assert!(ONCHAIN_INTENTS.read().await["listeners-different"].broadcast);
+    }
+
+    #[tokio::test]
+    async fn unseen_intent_has_no_quote() {
+        assert!(take_precomputed_quote("listeners-unseen", &intent(USDT, "1")).await.is_none());
+    }
+}
@@ -1,44 +1,202 @@
-mod chains;
-mod routers;
-
-use crate::chains::ethereum::ethereum_chain::handle_ethereum_execution;
-use crate::chains::mantis::mantis_chain::handle_mantis_execution;
-use crate::chains::solana::solana_chain::handle_solana_execution;
-use crate::chains::OperationInput;
-use crate::chains::OperationOutput;
-use crate::chains::PostIntentInfo;
-use crate::chains::INTENTS;
-use crate::chains::SOLVER_ADDRESSES;
-use crate::chains::SOLVER_ID;
-use crate::chains::SOLVER_PRIVATE_KEY;
-use crate::routers::get_simulate_swap_intent;
-use chains::create_keccak256_signature;
+use example_solver::admin::run_admin_api;
+use example_solver::chains::OperationOutput;
+use example_solver::chains::PostIntentInfo;
+use example_solver::chains::INTENTS;
+use example_solver::chains::AUCTION_DURATION_SECS;
+use example_solver::chains::SOLVER_ID;
+use example_solver::chains::{create_keccak256_signature, registration_message};
+use example_solver::chains::{check_auctioneer_address, AUCTIONEER_ADDRESS};
+use example_solver::chains::{default_bridge_token, execute_intent, intent_needs_fill, verify_auctioneer_message};
+use example_solver::commands::{approve, balances, check, quote, register_test};
+use example_solver::dry_run::{remember_bid, simulate_fill, take_bid, DryRun, DRY_RUN};
+use example_solver::inventory::{
+    hold, invalidate_balances, release, reservation_deadline, reserve, reserved_token,
+    reserved_valuation, Reservation,
+};
+use example_solver::journal::{mark_done, pending, record_won};
+use example_solver::listeners::{spawn_intent_listeners, take_precomputed_quote};
+use example_solver::rebalancer::{run_rebalancer, targets_from_env, BridgeAdapter};
+use example_solver::recorder::{record, Event};
+use example_solver::routers::cache::run_quote_prewarmer;
+use example_solver::routers::get_best_quote;
+use example_solver::rpc::run_rpc_health_checks;
+use example_solver::signer::{identity_signer, load_signers};
+use example_solver::strategy::{price_bid, BidInput, BID_STRATEGY};
+use clap::{Parser, Subcommand};
 use ethers::types::U256;
 use futures::{SinkExt, StreamExt};
+use num_bigint::BigInt;
 use serde_json::json;
 use serde_json::Value;
-use spl_associated_token_account::get_associated_token_address;
 use std::env;
+use std::str::FromStr;
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::protocol::Message;
 
+// finish the intents we won before a restart and that are still open on-chain
+async fn recover_pending_intents() {
+    if *DRY_RUN != DryRun::Off {
+        println!("[dry run] Not recovering the intents in the journal");
+        return;
+    }
+
+    let pending = match pending().await {
+        Ok(pending) => pending,
+        Err(e) => {
+            eprintln!("Not recovering the journal: {e}");
+            return;
+        }
+    };
+
+    for (intent_id, entry) in pending {
+        match intent_needs_fill(&intent_id, &entry.intent, &entry.amount).await {
+            Ok(true) => {
+                println!("Recovering intent {intent_id}");
+                match execute_intent(&intent_id, &entry.intent, &entry.amount, &entry.bridge_token()).await {
+                    Ok(()) => mark_done(&intent_id).await,
+                    Err(e) => println!("Failed to recover intent {intent_id}: {e}"),
+                }
+            }
+            Ok(false) => {
+                println!("Intent {intent_id} no longer needs a fill");
+                mark_done(&intent_id).await;
+            }
+            Err(e) => println!("Could not check intent {intent_id}: {e}"),
+        }
+    }
+}
+
+#[derive(Parser)]
+#[clap(about = "Mantis example solver")]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Connect to the auctioneer and bid (default)
+    Run,
+    /// Price one hypothetical intent, without the auctioneer
+    Quote {
+        #[clap(long)]
+        src_chain: String,
+        #[clap(long)]
+        dst_chain: String,
+        /// "USDT" or a token address / mint
+        #[clap(long)]
+        token_in: String,
+        #[clap(long)]
+        amount_in: String,
+        /// "USDT" or a token address / mint
+        #[clap(long)]
+        token_out: String,
+        /// Minimum token_out the user would accept
+        #[clap(long, default_value = "0")]
+        amount_out_min: String,
+    },
+    /// Validate config, RPC health, balances and allowances
+    Check,
+    /// Approve a token to the Ethereum Escrow and Paraswap contracts
+    Approve {
+        /// Defaults to every BRIDGE_TOKEN
+        #[clap(long)]
+        token: Option<String>,
+        /// In the token's smallest unit
+        #[clap(long, required_unless_present = "unlimited")]
+        amount: Option<String>,
+        /// Approve the maximum amount instead
+        #[clap(long, conflicts_with = "amount")]
+        unlimited: bool,
+    },
+    /// Print the solver's balances
+    Balances,
+    /// Finish the won intents left in the journal, then exit
+    Recover,
+    /// Do a signed registration handshake only
+    RegisterTest,
+}
+
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
+
+    let result = match Cli::parse().command.unwrap_or(Command::Run) {
+        Command::Run => {
+            run().await;
+            Ok(())
+        }
+        Command::Quote {
+            src_chain,
+            dst_chain,
+            token_in,
+            amount_in,
+            token_out,
+            amount_out_min,
+        } => {
+            quote(
+                &src_chain,
+                &dst_chain,
+                &token_in,
+                &amount_in,
+                &token_out,
+                &amount_out_min,
+            )
+            .await
+        }
+        Command::Check => check().await,
+        Command::Approve {
+            token,
+            amount,
+            unlimited,
+        } => approve(token.as_deref(), amount.as_deref(), unlimited).await,
+        Command::Balances => balances().await,
+        Command::Recover => {
+            recover_pending_intents().await;
+            Ok(())
+        }
+        Command::RegisterTest => register_test().await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+async fn run() {
     let server_addr = env::var("COMPOSABLE_ENDPOINT").unwrap_or_else(|_| String::from(""));
+    let solver_signer = identity_signer().expect("Failed to load the identity signer");
+    load_signers().expect("Failed to load the signers");
+
+    check_auctioneer_address().expect("Refusing to start");
+    if AUCTIONEER_ADDRESS.is_none() {
+        eprintln!("**********************************************************************");
+        eprintln!("WARNING: ALLOW_UNSIGNED_AUCTIONEER=1, auctioneer messages are NOT verified");
+        eprintln!("whoever can inject messages into the auctioneer connection can make this solver bid and fill");
+        eprintln!("**********************************************************************");
+    }
+
+    tokio::spawn(run_rpc_health_checks());
+    recover_pending_intents().await;
+    spawn_intent_listeners();
+    tokio::spawn(run_quote_prewarmer());
+    tokio::spawn(run_admin_api());
+
+    if *DRY_RUN != DryRun::Off {
+        println!("[dry run] {:?} mode: fills are simulated, nothing is broadcast", *DRY_RUN);
+    } else if let Some(adapter) = BridgeAdapter::from_env() {
+        tokio::spawn(run_rebalancer(adapter, targets_from_env()));
+    }
 
     let (ws_stream, _) = connect_async(server_addr).await.expect("Failed to connect");
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
-    let mut json_data = json!({
-        "code": 1,
-        "msg": {
-            "solver_id": SOLVER_ID.to_string(),
-            "solver_addresses": SOLVER_ADDRESSES,
-        }
-    });
+    let mut json_data = registration_message()
+        .await
+        .expect("Failed to build the registration message");
 
-    create_keccak256_signature(&mut json_data, SOLVER_PRIVATE_KEY.to_string())
+    create_keccak256_signature(&mut json_data, &solver_signer)
         .await
         .unwrap();
 
@@ -55,11 +213,23 @@ async fn main() {
     while let Some(msg) = ws_receiver.next().await {
         match msg {
             Ok(Message::Text(text)) => {
+                record(Event::Received {
+                    message: text.clone(),
+                })
+                .await;
+
                 let parsed: Value = serde_json::from_str(&text).unwrap();
                 let code = parsed.get("code").unwrap().as_u64().unwrap();
 
                 println!("{:#?}", parsed);
 
+                if code == 1 || code == 4 {
+                    if let Err(e) = verify_auctioneer_message(&parsed) {
+                        println!("Dropping message not signed by the auctioneer: {e}");
+                        continue;
+                    }
+                }
+
                 if code == 0 {
                     // error
                 } else if code == 1 {
@@ -79,14 +249,16 @@ async fn main() {
                     let intent_value: Value = serde_json::from_str(&intent_str).unwrap();
                     let intent_info: PostIntentInfo = serde_json::from_value(intent_value).unwrap();
 
-                    // calculate best quote
-                    let final_amount = get_simulate_swap_intent(
-                        &intent_info,
-                        &intent_info.src_chain,
-                        &intent_info.dst_chain,
-                        &String::from("USDT"),
-                    )
-                    .await;
+                    if let Err(e) = intent_info.check_timeout(*AUCTION_DURATION_SECS) {
+                        println!("Skipping intent {intent_id}: {e}");
+                        continue;
+                    }
+
+                    // calculate best quote, unless we already did from the on-chain event
+                    let quote = match take_precomputed_quote(intent_id, &intent_info).await {
+                        Some(quote) => quote,
+                        None => get_best_quote(&intent_info).await,
+                    };
 
                     // decide if participate or not
                     let mut amount_out_min = U256::zero();
@@ -94,11 +266,37 @@ async fn main() {
                         amount_out_min = U256::from_dec_str(&transfer_output.amount_out).unwrap();
                     }
 
-                    let final_amount = U256::from_dec_str(&final_amount).unwrap();
+                    let bid = price_bid(
+                        BID_STRATEGY.as_ref(),
+                        &BidInput {
+                            intent: &intent_info,
+                            quote: &quote,
+                            quote_amount: BigInt::from_str(&quote.amount_out).unwrap(),
+                            amount_out_min: BigInt::from_str(&amount_out_min.to_string()).unwrap(),
+                        },
+                    )
+                    .await;
+                    let final_amount = U256::from_dec_str(&bid.amount.to_string()).unwrap();
 
-                    println!("User wants {amount_out_min} token_out, you can provide {final_amount} token_out (after FLAT_FEES + COMISSION)");
+                    println!("User wants {amount_out_min} token_out, you can provide {} token_out through {} (after FLAT_FEES + COMISSION), bidding {final_amount} ({} strategy, {} bps margin)", quote.amount_out, quote.direct_token.as_ref().map_or(quote.bridge_token.as_str(), |_| "inventory"), bid.margin.strategy, bid.margin.bps);
 
                     if final_amount > amount_out_min {
+                        record(Event::Bid {
+                            intent_id: intent_id.to_string(),
+                            strategy: bid.margin.strategy,
+                            margin_bps: bid.margin.bps,
+                            inputs: bid.margin.inputs,
+                            quote_amount: quote.amount_out.clone(),
+                            amount: final_amount.to_string(),
+                        })
+                        .await;
+                    }
+
+                    if final_amount > amount_out_min && *DRY_RUN == DryRun::Log {
+                        println!("[dry run] Would bid {final_amount} on intent {intent_id}");
+                        simulate_fill(intent_id, &intent_info, &final_amount.to_string(), &quote)
+                            .await;
+                    } else if final_amount > amount_out_min {
                         let mut json_data = json!({
                             "code": 2,
                             "msg": {
@@ -108,7 +306,7 @@ async fn main() {
                             }
                         });
 
-                        create_keccak256_signature(&mut json_data, SOLVER_PRIVATE_KEY.to_string())
+                        create_keccak256_signature(&mut json_data, &solver_signer)
                             .await
                             .unwrap();
 
@@ -117,9 +315,38 @@ async fn main() {
                             .await
                             .expect("Failed to send message");
 
+                        record(Event::Sent {
+                            message: json_data.to_string(),
+                        })
+                        .await;
+
                         let mut intents = INTENTS.write().await;
                         intents.insert(intent_id.to_string(), intent_info);
                         drop(intents);
+
+                        if *DRY_RUN == DryRun::Bid {
+                            remember_bid(intent_id, &quote).await;
+                        }
+
+                        // keep the bridge token, or token_out filled from inventory,
+                        // aside until we know if we won
+                        let (token, amount) = match quote.direct_token {
+                            Some(token_out) => (token_out, bid.amount),
+                            None => (quote.bridge_token_dst.clone(), quote.bridge_amount_dst.clone()),
+                        };
+                        reserve(
+                            intent_id,
+                            Reservation {
+                                chain: quote.dst_chain,
+                                token,
+                                amount,
+                                bridge_token: quote.bridge_token_dst,
+                                notional: quote.bridge_amount_dst,
+                                value_in: quote.bridge_amount_src,
+                                expires_at: Some(reservation_deadline()),
+                            },
+                        )
+                        .await;
                     }
                 } else if code == 3 {
                     // solver registered
@@ -145,7 +372,23 @@ async fn main() {
                             .unwrap()
                             .to_string();
 
-                        if msg.contains("won") {
+                        let dry_run_quote = take_bid(intent_id).await;
+
+                        if let Some(intent) = INTENTS.read().await.get(intent_id) {
+                            BID_STRATEGY.record_result(intent, msg.contains("won")).await;
+                        }
+
+                        if msg.contains("won") && *DRY_RUN != DryRun::Off {
+                            let intent = INTENTS.read().await.get(intent_id).cloned();
+                            match (intent, dry_run_quote) {
+                                (Some(intent), Some(quote)) => {
+                                    simulate_fill(intent_id, &intent, amount, &quote).await
+                                }
+                                _ => println!("[dry run] Won intent {intent_id} without its bid, not simulated"),
+                            }
+                        } else if msg.contains("won") {
+                            hold(intent_id).await;
+
                             let intent;
                             {
                                 let intents = INTENTS.read().await;
@@ -153,21 +396,20 @@ async fn main() {
                                 drop(intents);
                             }
 
-                            if intent.dst_chain == "solana" {
-                                handle_solana_execution(&intent, intent_id, amount)
-                                    .await
-                                    .unwrap();
-                            } else if intent.dst_chain == "ethereum" {
-                                handle_ethereum_execution(&intent, U256::from_dec_str(intent_id).unwrap(), amount, intent.src_chain == intent.dst_chain)
-                                    .await
-                                    .unwrap();
-                            } else if intent.dst_chain == "mantis" {
-                                handle_mantis_execution(&intent, intent_id)
-                                    .await
-                                    .unwrap();
+                            let bridge_token = reserved_token(intent_id)
+                                .await
+                                .unwrap_or_else(|| default_bridge_token(&intent.dst_chain));
+                            let valuation = reserved_valuation(intent_id).await;
+                            record_won(intent_id, &intent, amount, &bridge_token, valuation).await;
+
+                            match execute_intent(intent_id, &intent, amount, &bridge_token).await {
+                                Ok(()) => mark_done(intent_id).await,
+                                Err(e) => println!("Failed to execute intent {intent_id}: {e}"),
                             }
 
                             // ws_sender.send(Message::text(msg)).await.expect("Failed to send message");
+
+                            invalidate_balances().await;
                         }
 
                         {
@@ -175,6 +417,7 @@ async fn main() {
                             intents.remove(&intent_id.to_string());
                             drop(intents);
                         }
+                        release(intent_id).await;
                     }
                 }
             }
@@ -0,0 +1,603 @@
+//! Local stand-in for the Paraswap, Jupiter and gas oracle HTTP APIs, so quoting
+//! and its error paths can be exercised offline and deterministically. Unit tests
+//! start one on an ephemeral port with `MockRouters::start`, the `mock_routers`
+//! binary serves one for manual runs.
+//!
+//! Quotes are priced from rates (token_out per token_in, in whole tokens):
+//! `rates["<token_in>:<token_out>"]`, else `default_rate`. Any route can be
+//! replaced by a canned response instead. Both come from the fixtures and can be
+//! changed while running:
+//!
+//! POST   /mock/rates       {"<token_in>:<token_out>": 0.99, ...}
+//! POST   /mock/responses   {"GET /quote": {"status": 500, "body": {"error": "..."}}, ...}
+//! GET    /mock/requests    every request served so far
+//! DELETE /mock             back to the fixtures
+
+use base64::Engine;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_sdk::message::{Message, VersionedMessage};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fs;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+// Augustus v6.2, what Paraswap returns as `to`
+const PARASWAP_ROUTER: &str = "0x6a000f20005980200259b80c5102003040001068";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CannedResponse {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    pub body: Value,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+fn default_rate() -> f64 {
+    1.0
+}
+
+/// What the mock starts from, the MOCK_ROUTERS_FIXTURES file of the binary:
+/// {"default_rate", "price_impact_pct", "rates", "responses"}
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fixtures {
+    #[serde(default = "default_rate")]
+    pub default_rate: f64,
+    // reported by /prices (srcUSD vs destUSD) and /quote (priceImpactPct)
+    #[serde(default)]
+    pub price_impact_pct: f64,
+    // "<token_in>:<token_out>", addresses / mints compared case-insensitively
+    #[serde(default)]
+    pub rates: HashMap<String, f64>,
+    // "<METHOD> <path>" -> response served instead of the mock's own
+    #[serde(default)]
+    pub responses: HashMap<String, CannedResponse>,
+}
+
+impl Default for Fixtures {
+    fn default() -> Self {
+        Fixtures {
+            default_rate: default_rate(),
+            price_impact_pct: 0.0,
+            rates: HashMap::new(),
+            responses: HashMap::new(),
+        }
+    }
+}
+
+impl Fixtures {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let fixtures = fs::read_to_string(path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+        serde_json::from_str(&fixtures).map_err(|e| format!("Invalid fixtures {path}: {e}"))
+    }
+
+    fn rate(&self, token_in: &str, token_out: &str) -> f64 {
+        let key = format!("{token_in}:{token_out}").to_lowercase();
+        self.rates
+            .iter()
+            .find(|(pair, _)| pair.to_lowercase() == key)
+            .map(|(_, rate)| *rate)
+            .unwrap_or(self.default_rate)
+    }
+}
+
+struct State {
+    initial: Fixtures,
+    fixtures: Fixtures,
+    requests: Vec<Value>,
+}
+
+type SharedState = Arc<Mutex<State>>;
+
+fn respond(status: u16, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR))
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn bad_request(error: impl ToString) -> Response<Body> {
+    respond(400, json!({ "error": error.to_string() }))
+}
+
+// raw token units across decimals, at `rate` whole token_out per whole token_in
+fn convert(amount: &str, rate: f64, from_decimals: i32, to_decimals: i32) -> Result<u128, String> {
+    let amount = amount
+        .parse::<f64>()
+        .map_err(|e| format!("Invalid amount {amount}: {e}"))?;
+    Ok((amount * rate * 10f64.powi(to_decimals - from_decimals)).floor() as u128)
+}
+
+fn paraswap_prices(fixtures: &Fixtures, query: &HashMap<String, String>) -> Result<Value, String> {
+    let param = |name: &str| {
+        query
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Missing {name}"))
+    };
+    let decimals = |name: &str| -> Result<i32, String> {
+        param(name)?.parse::<i32>().map_err(|e| format!("Invalid {name}: {e}"))
+    };
+
+    let (src_token, dest_token) = (param("srcToken")?, param("destToken")?);
+    let (src_decimals, dest_decimals) = (decimals("srcDecimals")?, decimals("destDecimals")?);
+    let amount = param("amount")?;
+    let side = param("side").unwrap_or_else(|_| String::from("SELL"));
+    let rate = fixtures.rate(&src_token, &dest_token);
+
+    let (src_amount, dest_amount) = match side.as_str() {
+        "SELL" => (amount.clone(), convert(&amount, rate, src_decimals, dest_decimals)?.to_string()),
+        "BUY" => (convert(&amount, 1.0 / rate, dest_decimals, src_decimals)?.to_string(), amount),
+        _ => return Err(format!("Invalid side {side}")),
+    };
+
+    Ok(json!({
+        "priceRoute": {
+            "srcToken": src_token,
+            "srcDecimals": src_decimals,
+            "destToken": dest_token,
+            "destDecimals": dest_decimals,
+            "srcAmount": src_amount,
+            "destAmount": dest_amount,
+            "side": side,
+            "network": query.get("network").and_then(|network| network.parse::<u64>().ok()).unwrap_or(1),
+            "srcUSD": "100",
+            "destUSD": format!("{}", 100.0 * (1.0 - fixtures.price_impact_pct / 100.0)),
+            "bestRoute": [],
+            "contractAddress": PARASWAP_ROUTER,
+            "version": "6.2",
+        }
+    }))
+}
+
+fn jupiter_quote(fixtures: &Fixtures, query: &HashMap<String, String>) -> Result<Value, String> {
+    let param = |name: &str| {
+        query
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Missing {name}"))
+    };
+
+    let (input_mint, output_mint) = (param("inputMint")?, param("outputMint")?);
+    let amount = param("amount")?;
+    let swap_mode = param("swapMode").unwrap_or_else(|_| String::from("ExactIn"));
+    let slippage_bps = param("slippageBps")
+        .ok()
+        .and_then(|bps| bps.parse::<u64>().ok())
+        .unwrap_or(50);
+    // mints carry no decimals here, rates are taken as raw unit ratios
+    let rate = fixtures.rate(&input_mint, &output_mint);
+
+    let (in_amount, out_amount, threshold) = match swap_mode.as_str() {
+        "ExactIn" => {
+            let out_amount = convert(&amount, rate, 0, 0)?;
+            let threshold = out_amount * (10_000 - slippage_bps as u128) / 10_000;
+            (amount.parse::<u128>().unwrap_or_default(), out_amount, threshold)
+        }
+        "ExactOut" => {
+            let in_amount = convert(&amount, 1.0 / rate, 0, 0)?;
+            let threshold = in_amount * (10_000 + slippage_bps as u128) / 10_000;
+            (in_amount, amount.parse::<u128>().unwrap_or_default(), threshold)
+        }
+        _ => return Err(format!("Invalid swapMode {swap_mode}")),
+    };
+
+    Ok(json!({
+        "inputMint": input_mint,
+        "inAmount": in_amount.to_string(),
+        "outputMint": output_mint,
+        "outAmount": out_amount.to_string(),
+        "otherAmountThreshold": threshold.to_string(),
+        "swapMode": swap_mode,
+        "slippageBps": slippage_bps,
+        "platformFee": null,
+        "priceImpactPct": (fixtures.price_impact_pct / 100.0).to_string(),
+        "routePlan": [{
+            "swapInfo": {
+                "ammKey": Pubkey::default().to_string(),
+                "label": "Mock",
+                "inputMint": input_mint,
+                "outputMint": output_mint,
+                "inAmount": in_amount.to_string(),
+                "outAmount": out_amount.to_string(),
+                "feeAmount": "0",
+                "feeMint": input_mint,
+            },
+            "percent": 100,
+        }],
+        "contextSlot": 1,
+        "timeTaken": 0.0,
+    }))
+}
+
+fn user_public_key(body: &Value) -> Result<Pubkey, String> {
+    body.get("userPublicKey")
+        .and_then(Value::as_str)
+        .ok_or("Missing userPublicKey")
+        .and_then(|key| Pubkey::from_str(key).map_err(|_| "Invalid userPublicKey"))
+        .map_err(String::from)
+}
+
+// an empty transaction paid by the user, the solver only signs and sends it
+fn jupiter_swap(body: &Value) -> Result<Value, String> {
+    let user = user_public_key(body)?;
+    let transaction = VersionedTransaction {
+        signatures: vec![Signature::default()],
+        message: VersionedMessage::Legacy(Message::new(&[], Some(&user))),
+    };
+    let serialized = bincode::serialize(&transaction).map_err(|e| e.to_string())?;
+
+    Ok(json!({
+        "swapTransaction": base64::engine::general_purpose::STANDARD.encode(serialized),
+        "lastValidBlockHeight": 1,
+    }))
+}
+
+fn jupiter_swap_instructions(body: &Value) -> Result<Value, String> {
+    let user = user_public_key(body)?;
+
+    Ok(json!({
+        "tokenLedgerInstruction": null,
+        "computeBudgetInstructions": [],
+        "setupInstructions": [],
+        "swapInstruction": {
+            "programId": "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr",
+            "accounts": [{ "pubkey": user.to_string(), "isSigner": true, "isWritable": false }],
+            "data": base64::engine::general_purpose::STANDARD.encode("mock swap"),
+        },
+        "cleanupInstruction": null,
+        "addressLookupTableAddresses": [],
+        "prioritizationFeeLamports": 0,
+    }))
+}
+
+async fn handle(state: SharedState, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let query: HashMap<String, String> = Url::parse(&format!("http://mock{}", request.uri()))
+        .map(|url| url.query_pairs().into_owned().collect())
+        .unwrap_or_default();
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+        .unwrap_or(Value::Null);
+
+    let mut state = state.lock().await;
+
+    // control routes
+    if path == "/mock" && method == Method::DELETE {
+        state.fixtures = state.initial.clone();
+        state.requests.clear();
+        return Ok(respond(200, json!({})));
+    }
+    if path == "/mock/requests" && method == Method::GET {
+        return Ok(respond(200, Value::Array(state.requests.clone())));
+    }
+    if path == "/mock/rates" && method == Method::POST {
+        return Ok(match serde_json::from_value::<HashMap<String, f64>>(body) {
+            Ok(rates) => {
+                state.fixtures.rates.extend(rates);
+                respond(200, json!({}))
+            }
+            Err(e) => bad_request(e),
+        });
+    }
+    if path == "/mock/responses" && method == Method::POST {
+        return Ok(match serde_json::from_value::<HashMap<String, CannedResponse>>(body) {
+            Ok(responses) => {
+                state.fixtures.responses.extend(responses);
+                respond(200, json!({}))
+            }
+            Err(e) => bad_request(e),
+        });
+    }
+
+    state.requests.push(json!({
+        "method": method.as_str(),
+        "path": path,
+        "query": query,
+        "body": body,
+    }));
+
+    // /transactions/<chain_id> is matched without the chain id
+    let route = if path.starts_with("/transactions/") {
+        "/transactions"
+    } else {
+        path.as_str()
+    };
+    let key = format!("{} {}", method, route);
+    if let Some(canned) = state.fixtures.responses.get(&key) {
+        return Ok(respond(canned.status, canned.body.clone()));
+    }
+
+    let fixtures = &state.fixtures;
+    let result = match (&method, route) {
+        (&Method::GET, "/prices") => paraswap_prices(fixtures, &query),
+        (&Method::POST, "/transactions") => Ok(json!({
+            "from": body.get("userAddress").cloned().unwrap_or(Value::Null),
+            "to": PARASWAP_ROUTER,
+            "value": "0",
+            "data": "0x",
+            "chainId": 1,
+        })),
+        (&Method::GET, "/quote") => jupiter_quote(fixtures, &query),
+        (&Method::POST, "/swap") => jupiter_swap(&body),
+        (&Method::POST, "/swap-instructions") => jupiter_swap_instructions(&body),
+        (&Method::GET, "/api") => Ok(json!({
+            "status": "1",
+            "result": {
+                "SafeGasPrice": "10",
+                "ProposeGasPrice": "10",
+                "FastGasPrice": "10",
+            }
+        })),
+        _ => return Ok(respond(404, json!({ "error": format!("No mock for {key}") }))),
+    };
+
+    Ok(match result {
+        Ok(body) => respond(200, body),
+        Err(e) => bad_request(e),
+    })
+}
+
+/// A running mock, serving until the tokio runtime it was started on shuts down.
+pub struct MockRouters {
+    pub addr: SocketAddr,
+    state: SharedState,
+}
+
+impl MockRouters {
+    /// Serves `fixtures` on an ephemeral port of 127.0.0.1.
+    pub fn start(fixtures: Fixtures) -> Result<Self, String> {
+        Self::bind(SocketAddr::from(([127, 0, 0, 1], 0)), fixtures)
+    }
+
+    /// Serves `fixtures` on `addr`, port 0 picks a free one.
+    pub fn bind(addr: SocketAddr, fixtures: Fixtures) -> Result<Self, String> {
+        let state = Arc::new(Mutex::new(State {
+            initial: fixtures.clone(),
+            fixtures,
+            requests: Vec::new(),
+        }));
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request))) }
+        });
+
+        let server = Server::try_bind(&addr)
+            .map_err(|e| format!("Failed to bind {addr}: {e}"))?
+            .serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("Mock routers stopped: {e}");
+            }
+        });
+
+        Ok(MockRouters { addr, state })
+    }
+
+    /// Base url, for PARASWAP_API_URL and QUOTE_API_URL.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// GAS_ORACLE_URL answering like the Etherscan gas tracker.
+    pub fn gas_oracle_url(&self) -> String {
+        format!("{}/api?module=gastracker&action=gasoracle", self.url())
+    }
+
+    /// Same as POST /mock/rates.
+    pub async fn set_rate(&self, token_in: &str, token_out: &str, rate: f64) {
+        let mut state = self.state.lock().await;
+        state.fixtures.rates.insert(format!("{token_in}:{token_out}"), rate);
+    }
+
+    /// Same as POST /mock/responses, `route` is "<METHOD> <path>".
+    pub async fn set_response(&self, route: &str, status: u16, body: Value) {
+        let mut state = self.state.lock().await;
+        state
+            .fixtures
+            .responses
+            .insert(route.to_string(), CannedResponse { status, body });
+    }
+
+    /// Same as GET /mock/requests.
+    pub async fn requests(&self) -> Vec<Value> {
+        self.state.lock().await.requests.clone()
+    }
+}
+
+#[cfg(test)]
+lazy_static::lazy_static! {
+    // PARASWAP_API_URL / QUOTE_API_URL are process wide, tests pointing them at
+    // their own mock take turns
+    pub(crate) static ref ROUTER_ENV: Mutex<()> = Mutex::new(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn fixtures(rates: &[(&str, f64)]) -> Fixtures {
+        Fixtures {
+            rates: rates.iter().map(|(pair, rate)| (pair.to_string(), *rate)).collect(),
+            ..Fixtures::default()
+        }
+    }
+
+    #[test]
+    fn convert_scales_across_decimals() {
+        assert_eq!(convert("1000000", 2.0, 6, 18).unwrap(), 2_000_000_000_000_000_000);
+        assert_eq!(convert("2000000000000000000", 0.5, 18, 6).unwrap(), 1_000_000);
+        // rounded down, like a router would
+        assert_eq!(convert("3", 0.5, 0, 0).unwrap(), 1);
+        assert!(convert("1e", 1.0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn rates_are_looked_up_case_insensitively() {
+        let fixtures = Fixtures {
+            default_rate: 3.0,
+            ..fixtures(&[("0xAbC:0xDeF", 0.5)])
+        };
+        assert_eq!(fixtures.rate("0xabc", "0xDEF"), 0.5);
+        assert_eq!(fixtures.rate("0xdef", "0xabc"), 3.0);
+    }
+
+    #[test]
+    fn paraswap_prices_sell_and_buy() {
+        let fixtures = fixtures(&[("0xa:0xb", 0.5)]);
+        let params = |side: &str, amount: &str| {
+            query(&[
+                ("srcToken", "0xa"),
+                ("srcDecimals", "6"),
+                ("destToken", "0xb"),
+                ("destDecimals", "18"),
+                ("amount", amount),
+                ("side", side),
+            ])
+        };
+
+        let sell = paraswap_prices(&fixtures, &params("SELL", "1000000")).unwrap();
+        assert_eq!(sell["priceRoute"]["srcAmount"], "1000000");
+        assert_eq!(sell["priceRoute"]["destAmount"], "500000000000000000");
+
+        let buy = paraswap_prices(&fixtures, &params("BUY", "500000000000000000")).unwrap();
+        assert_eq!(buy["priceRoute"]["srcAmount"], "1000000");
+        assert_eq!(buy["priceRoute"]["destAmount"], "500000000000000000");
+
+        assert_eq!(
+            paraswap_prices(&fixtures, &params("SWAP", "1")).unwrap_err(),
+            "Invalid side SWAP"
+        );
+    }
+
+    #[test]
+    fn paraswap_prices_rejects_missing_params() {
+        let fixtures = Fixtures::default();
+        let missing = query(&[("srcToken", "0xa"), ("srcDecimals", "6"), ("destDecimals", "6")]);
+        assert_eq!(paraswap_prices(&fixtures, &missing).unwrap_err(), "Missing destToken");
+
+        let invalid = query(&[
+            ("srcToken", "0xa"),
+            ("srcDecimals", "six"),
+            ("destToken", "0xb"),
+            ("destDecimals", "6"),
+        ]);
+        assert!(paraswap_prices(&fixtures, &invalid)
+            .unwrap_err()
+            .starts_with("Invalid srcDecimals"));
+    }
+
+    #[test]
+    fn jupiter_quote_applies_slippage_to_the_threshold() {
+        let fixtures = fixtures(&[("in:out", 0.5)]);
+        let exact_in = jupiter_quote(
+            &fixtures,
+            &query(&[
+                ("inputMint", "in"),
+                ("outputMint", "out"),
+                ("amount", "1000"),
+                ("slippageBps", "100"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(exact_in["outAmount"], "500");
+        assert_eq!(exact_in["otherAmountThreshold"], "495");
+
+        let exact_out = jupiter_quote(
+            &fixtures,
+            &query(&[
+                ("inputMint", "in"),
+                ("outputMint", "out"),
+                ("amount", "500"),
+                ("swapMode", "ExactOut"),
+                ("slippageBps", "100"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(exact_out["inAmount"], "1000");
+        assert_eq!(exact_out["otherAmountThreshold"], "1010");
+
+        let invalid = query(&[
+            ("inputMint", "in"),
+            ("outputMint", "out"),
+            ("amount", "1"),
+            ("swapMode", "Both"),
+        ]);
+        assert_eq!(jupiter_quote(&fixtures, &invalid).unwrap_err(), "Invalid swapMode Both");
+        assert_eq!(
+            jupiter_quote(&fixtures, &query(&[("inputMint", "in")])).unwrap_err(),
+            "Missing outputMint"
+        );
+    }
+
+    #[test]
+    fn jupiter_swap_needs_a_user() {
+        assert_eq!(jupiter_swap(&json!({})).unwrap_err(), "Missing userPublicKey");
+        assert_eq!(
+            jupiter_swap_instructions(&json!({ "userPublicKey": "nope" })).unwrap_err(),
+            "Invalid userPublicKey"
+        );
+        assert!(jupiter_swap(&json!({ "userPublicKey": Pubkey::new_unique().to_string() })).is_ok());
+    }
+
+    #[tokio::test]
+    async fn serves_canned_responses_and_records_requests() {
+        let mock = MockRouters::start(Fixtures::default()).unwrap();
+        mock.set_response("GET /quote", 500, json!({ "error": "no route" })).await;
+
+        let response = reqwest::get(format!("{}/quote?inputMint=a", mock.url())).await.unwrap();
+        assert_eq!(response.status().as_u16(), 500);
+        assert_eq!(response.json::<Value>().await.unwrap(), json!({ "error": "no route" }));
+
+        let response = reqwest::get(format!("{}/unknown", mock.url())).await.unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+
+        let gas = reqwest::get(mock.gas_oracle_url())
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .unwrap();
+        assert_eq!(gas["result"]["ProposeGasPrice"], "10");
+
+        let requests = mock.requests().await;
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0]["query"]["inputMint"], "a");
+
+        // DELETE /mock drops the canned response again
+        reqwest::Client::new()
+            .delete(format!("{}/mock", mock.url()))
+            .send()
+            .await
+            .unwrap();
+        let response = reqwest::get(format!("{}/quote", mock.url())).await.unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+        assert_eq!(mock.requests().await.len(), 1);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::chains::get_token_info;
+use crate::chains::SOLVER_ADDRESSES;
+use crate::inventory::{apply_mock_offset, get_balance, invalidate_balances};
+use crate::routers::PAUSED_BRIDGE_TOKENS;
+use num_bigint::BigInt;
+use num_traits::Zero;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const PAUSE_REASON: &str = "rebalance";
+
+#[derive(Debug, Clone)]
+pub struct RebalanceTarget {
+    pub chain: String,
+    pub token: String,
+    // balance we want to hold, in the token's smallest unit
+    pub target: BigInt,
+    // how far below target we tolerate before topping the chain up, a surplus
+    // above target is only drawn on to top up other chains
+    pub threshold: BigInt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BridgeAdapter {
+    // only pause bidding with the token running low on the chain
+    Pause,
+    // log the transfers we would do
+    DryRun,
+    // pretend the transfer happened by offsetting the cached inventory
+    Mock,
+    // POST the transfer to an external bridge service
+    Http(String),
+}
+
+impl BridgeAdapter {
+    pub fn from_env() -> Option<Self> {
+        match env::var("REBALANCE_MODE")
+            .unwrap_or_else(|_| String::from("off"))
+            .as_str()
+        {
+            "pause" => Some(BridgeAdapter::Pause),
+            "dry-run" => Some(BridgeAdapter::DryRun),
+            "mock" => Some(BridgeAdapter::Mock),
+            "bridge" => match env::var("REBALANCE_BRIDGE_URL") {
+                Ok(url) => Some(BridgeAdapter::Http(url)),
+                Err(_) => {
+                    eprintln!("REBALANCE_MODE=bridge requires REBALANCE_BRIDGE_URL, rebalancer disabled");
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    async fn transfer(
+        &self,
+        from_chain: &str,
+        to_chain: &str,
+        token: &str,
+        amount: &BigInt,
+    ) -> Result<(), String> {
+        let (token_from, _) = get_token_info(token, from_chain)
+            .ok_or_else(|| format!("{token} is not known on {from_chain}"))?;
+        let (token_to, _) = get_token_info(token, to_chain)
+            .ok_or_else(|| format!("{token} is not known on {to_chain}"))?;
+
+        match self {
+            BridgeAdapter::Pause => Err("bridging disabled (REBALANCE_MODE=pause)".to_string()),
+            BridgeAdapter::DryRun => {
+                println!("[dry-run] would bridge {amount} {token} {from_chain} -> {to_chain}");
+                Ok(())
+            }
+            BridgeAdapter::Mock => {
+                apply_mock_offset(from_chain, token_from, -amount.clone()).await;
+                apply_mock_offset(to_chain, token_to, amount.clone()).await;
+                println!("[mock] bridged {amount} {token} {from_chain} -> {to_chain}");
+                Ok(())
+            }
+            BridgeAdapter::Http(url) => {
+                let recipient = if to_chain == "ethereum" {
+                    SOLVER_ADDRESSES.get(0).unwrap()
+                } else {
+                    SOLVER_ADDRESSES.get(1).unwrap()
+                };
+
+                let body = json!({
+                    "from_chain": from_chain,
+                    "to_chain": to_chain,
+                    "token_from": token_from,
+                    "token_to": token_to,
+                    "amount": amount.to_string(),
+                    "recipient": recipient,
+                });
+
+                reqwest::Client::builder()
+                    .timeout(Duration::from_secs(30))
+                    .build()
+                    .map_err(|e| format!("Failed to build bridge client: {}", e))?
+                    .post(url)
+                    .json(&body)
+                    .send()
+                    .await
+                    .map_err(|e| format!("Failed to reach bridge: {}", e))?
+                    .error_for_status()
+                    .map_err(|e| format!("Bridge refused transfer: {}", e))?;
+
+                invalidate_balances().await;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parses REBALANCE_TARGETS="chain:TOKEN:target:threshold,..." e.g.
+/// "ethereum:USDT:20000000000:5000000000,solana:USDT:20000000000:5000000000"
+pub fn targets_from_env() -> Vec<RebalanceTarget> {
+    let raw = env::var("REBALANCE_TARGETS").unwrap_or_default();
+
+    raw.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let parts: Vec<&str> = entry.trim().split(':').collect();
+            if parts.len() != 4 {
+                eprintln!("Invalid REBALANCE_TARGETS entry: {entry}");
+                return None;
+            }
+
+            Some(RebalanceTarget {
+                chain: parts[0].to_string(),
+                token: parts[1].to_uppercase(),
+                target: BigInt::from_str(parts[2]).ok()?,
+                threshold: BigInt::from_str(parts[3]).ok()?,
+            })
+        })
+        .collect()
+}
+
+// stops (or resumes) quoting through `token` on `chain`, other bridge tokens
+// and pauses set by the admin API are left alone
+async fn set_token_paused(chain: &str, token: &str, paused: bool) {
+    let mut tokens = PAUSED_BRIDGE_TOKENS.write().await;
+    let key = (chain.to_string(), token.to_string());
+
+    if paused {
+        tokens.entry(key).or_insert_with(|| PAUSE_REASON.to_string());
+    } else if tokens.get(&key).map(String::as_str) == Some(PAUSE_REASON) {
+        tokens.remove(&key);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Plan {
+    // within threshold of its target
+    Healthy,
+    // below it, but the last transfer into it may not have landed yet
+    InFlight,
+    // below it, bridge `amount` from the chain with the largest surplus
+    Bridge { from_chain: String, amount: BigInt },
+    // below it and no other chain holds more than its own target
+    NoDonor,
+}
+
+// what to do about `target`, given every target's balance
+fn plan(
+    target: &RebalanceTarget,
+    targets: &[RebalanceTarget],
+    balances: &HashMap<(String, String), BigInt>,
+    cooling_down: bool,
+) -> Option<Plan> {
+    let balance = balances.get(&(target.chain.clone(), target.token.clone()))?;
+    if balance >= &(&target.target - &target.threshold) {
+        return Some(Plan::Healthy);
+    }
+    if cooling_down {
+        return Some(Plan::InFlight);
+    }
+
+    let deficit = &target.target - balance;
+    // the donor is the chain holding the most above its own target
+    let donor = targets
+        .iter()
+        .filter(|t| t.token == target.token && t.chain != target.chain)
+        .filter_map(|t| {
+            let surplus = balances.get(&(t.chain.clone(), t.token.clone()))? - &t.target;
+            (surplus > BigInt::zero()).then_some((t.chain.clone(), surplus))
+        })
+        .max_by(|a, b| a.1.cmp(&b.1));
+
+    Some(match donor {
+        Some((from_chain, surplus)) => Plan::Bridge {
+            from_chain,
+            amount: deficit.min(surplus),
+        },
+        None => Plan::NoDonor,
+    })
+}
+
+/// Watches the bridge token balances against REBALANCE_TARGETS every
+/// REBALANCE_INTERVAL_SECS. A chain running low on a token gets topped up from
+/// the chain with the largest surplus of it, and bidding with that token into
+/// the chain is paused until it recovers.
+pub async fn run_rebalancer(adapter: BridgeAdapter, targets: Vec<RebalanceTarget>) {
+    let interval = Duration::from_secs(
+        env::var("REBALANCE_INTERVAL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .unwrap_or(60),
+    );
+    let cooldown = Duration::from_secs(
+        env::var("REBALANCE_COOLDOWN_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .unwrap_or(600),
+    );
+
+    // <(chain, token), last transfer into it> so we don't re-bridge while funds are in flight
+    let mut last_transfer: HashMap<(String, String), Instant> = HashMap::new();
+
+    println!("Rebalancer running ({adapter:?}) with {} targets", targets.len());
+
+    loop {
+        let mut balances = HashMap::new();
+        for target in &targets {
+            let Some((token_address, _)) = get_token_info(&target.token, &target.chain) else {
+                eprintln!("{} is not known on {}", target.token, target.chain);
+                continue;
+            };
+
+            match get_balance(&target.chain, token_address).await {
+                Ok(balance) => {
+                    balances.insert((target.chain.clone(), target.token.clone()), balance);
+                }
+                Err(e) => eprintln!("Rebalancer failed to read {} balance on {}: {e}", target.token, target.chain),
+            }
+        }
+
+        for target in &targets {
+            let key = (target.chain.clone(), target.token.clone());
+            let cooling_down = last_transfer
+                .get(&key)
+                .map_or(false, |at| at.elapsed() < cooldown);
+            let Some(step) = plan(target, &targets, &balances, cooling_down) else {
+                continue;
+            };
+
+            if step == Plan::Healthy {
+                set_token_paused(&target.chain, &target.token, false).await;
+                continue;
+            }
+            println!(
+                "{} on {} is below target: {} < {} (threshold {})",
+                target.token, target.chain, balances[&key], target.target, target.threshold
+            );
+
+            let transferred = match step {
+                Plan::Bridge { from_chain, amount } if adapter != BridgeAdapter::Pause => {
+                    match adapter
+                        .transfer(&from_chain, &target.chain, &target.token, &amount)
+                        .await
+                    {
+                        Ok(()) => {
+                            last_transfer.insert(key.clone(), Instant::now());
+                            true
+                        }
+                        Err(e) => {
+                            eprintln!("Rebalance {from_chain} -> {} failed: {e}", target.chain);
+                            false
+                        }
+                    }
+                }
+                _ => false,
+            };
+
+            // dry-run doesn't move funds, so keep bidding as if nothing happened.
+            // Funds in flight keep bidding paused until they land
+            if !transferred && adapter != BridgeAdapter::DryRun {
+                println!(
+                    "Pausing bids with {} into {} until it recovers",
+                    target.token, target.chain
+                );
+                set_token_paused(&target.chain, &target.token, true).await;
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(chain: &str, token: &str, target: u64, threshold: u64) -> RebalanceTarget {
+        RebalanceTarget {
+            chain: chain.to_string(),
+            token: token.to_string(),
+            target: BigInt::from(target),
+            threshold: BigInt::from(threshold),
+        }
+    }
+
+    fn balances(entries: &[(&str, &str, u64)]) -> HashMap<(String, String), BigInt> {
+        entries
+            .iter()
+            .map(|(chain, token, balance)| ((chain.to_string(), token.to_string()), BigInt::from(*balance)))
+            .collect()
+    }
+
+    #[test]
+    fn within_threshold_is_healthy() {
+        let targets = [target("ethereum", "USDT", 1000, 200), target("solana", "USDT", 1000, 200)];
+        let held = balances(&[("ethereum", "USDT", 800), ("solana", "USDT", 799)]);
+
+        assert_eq!(plan(&targets[0], &targets, &held, false), Some(Plan::Healthy));
+        assert_ne!(plan(&targets[1], &targets, &held, false), Some(Plan::Healthy));
+        // a balance we couldn't read is left alone
+        let unread = target("mantis", "USDT", 1000, 200);
+        assert_eq!(plan(&unread, &targets, &held, false), None);
+    }
+
+    #[test]
+    fn donor_is_the_largest_surplus_of_the_same_token() {
+        let targets = [
+            target("ethereum", "USDT", 1000, 200),
+            target("solana", "USDT", 1000, 200),
+            target("mantis", "USDT", 500, 100),
+            target("solana", "USDC", 1000, 200),
+        ];
+        let held = balances(&[
+            ("ethereum", "USDT", 100),
+            ("solana", "USDT", 1300),
+            ("mantis", "USDT", 1000),
+            ("solana", "USDC", 5000),
+        ]);
+
+        // mantis holds 500 above its target, solana 300, USDC doesn't count
+        assert_eq!(
+            plan(&targets[0], &targets, &held, false),
+            Some(Plan::Bridge {
+                from_chain: String::from("mantis"),
+                amount: BigInt::from(500),
+            })
+        );
+
+        // the deficit is all that is bridged when the surplus is larger
+        let held = balances(&[("ethereum", "USDT", 700), ("mantis", "USDT", 1000)]);
+        assert_eq!(
+            plan(&targets[0], &targets, &held, false),
+            Some(Plan::Bridge {
+                from_chain: String::from("mantis"),
+                amount: BigInt::from(300),
+            })
+        );
+    }
+
+    #[test]
+    fn no_surplus_means_no_donor() {
+        let targets = [target("ethereum", "USDT", 1000, 200), target("solana", "USDT", 1000, 200)];
+        let held = balances(&[("ethereum", "USDT", 100), ("solana", "USDT", 1000)]);
+
+        assert_eq!(plan(&targets[0], &targets, &held, false), Some(Plan::NoDonor));
+    }
+
+    #[test]
+    fn cooldown_waits_for_funds_in_flight() {
+        let targets = [target("ethereum", "USDT", 1000, 200), target("solana", "USDT", 1000, 200)];
+        let low = balances(&[("ethereum", "USDT", 100), ("solana", "USDT", 5000)]);
+        assert_eq!(plan(&targets[0], &targets, &low, true), Some(Plan::InFlight));
+
+        // landed: healthy again even within the cooldown
+        let landed = balances(&[("ethereum", "USDT", 1000), ("solana", "USDT", 4100)]);
+        assert_eq!(plan(&targets[0], &targets, &landed, true), Some(Plan::Healthy));
+    }
+
+    #[tokio::test]
+    async fn pauses_are_per_token() {
+        set_token_paused("ethereum", "USDT", true).await;
+        set_token_paused("ethereum", "USDC", false).await;
+        assert!(PAUSED_BRIDGE_TOKENS
+            .read()
+            .await
+            .contains_key(&(String::from("ethereum"), String::from("USDT"))));
+
+        set_token_paused("ethereum", "USDT", false).await;
+        assert!(!PAUSED_BRIDGE_TOKENS
+            .read()
+            .await
+            .contains_key(&(String::from("ethereum"), String::from("USDT"))));
+    }
+}
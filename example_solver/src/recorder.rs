@@ -0,0 +1,202 @@
+use crate::inventory::token_key;
+use crate::routers::cache::interpolate_quote;
+use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, RwLock};
+
+/// What a session recording is made of, one JSON per line in SESSION_RECORD.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    // raw websocket text from / to the auctioneer
+    Received {
+        message: String,
+    },
+    Sent {
+        message: String,
+    },
+    // router quote used while bidding, amounts in token units
+    Quote {
+        chain: String,
+        token_in: String,
+        token_out: String,
+        amount_in: String,
+        amount_out: String,
+        price_impact_pct: f64,
+    },
+    // solver balance fetched while bidding
+    Balance {
+        chain: String,
+        token: String,
+        balance: String,
+    },
+    // how a bid was priced from the quote
+    Bid {
+        intent_id: String,
+        strategy: String,
+        margin_bps: u32,
+        inputs: Value,
+        quote_amount: String,
+        amount: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ReplayState {
+    // <(chain, token_in, token_out), [(amount_in, amount_out, price_impact_pct)]>
+    quotes: HashMap<(String, String, String), Vec<(BigInt, BigInt, f64)>>,
+    // <(chain, token), balance>
+    balances: HashMap<(String, String), BigInt>,
+}
+
+// set while a backtest replays a session, routers and balances then come from it
+static REPLAYING: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref SESSION_RECORD: Option<String> = env::var("SESSION_RECORD")
+        .ok()
+        .filter(|path| !path.is_empty());
+    static ref RECORD_LOCK: Arc<Mutex<()>> = Arc::new(Mutex::new(()));
+    static ref REPLAY: Arc<RwLock<ReplayState>> = Arc::new(RwLock::new(ReplayState::default()));
+}
+
+/// Appends `event` to SESSION_RECORD, when recording.
+pub async fn record(event: Event) {
+    let Some(path) = SESSION_RECORD.as_ref() else {
+        return;
+    };
+    if replaying() {
+        return;
+    }
+
+    let record = Record {
+        timestamp_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64,
+        event,
+    };
+
+    let _lock = RECORD_LOCK.lock().await;
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(&record).unwrap()));
+    if let Err(e) = written {
+        eprintln!("Failed to write session record {path}: {e}");
+    }
+}
+
+pub fn read_session(path: &str) -> Result<Vec<Record>, String> {
+    fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {path}: {e}"))?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| format!("{path}:{}: {e}", i + 1)))
+        .collect()
+}
+
+pub fn replaying() -> bool {
+    REPLAYING.load(Ordering::Relaxed)
+}
+
+/// Starts serving quotes and balances from replayed events, forgetting earlier ones.
+pub async fn start_replay() {
+    *REPLAY.write().await = ReplayState::default();
+    REPLAYING.store(true, Ordering::Relaxed);
+}
+
+/// Back to live routers and balances.
+pub fn stop_replay() {
+    REPLAYING.store(false, Ordering::Relaxed);
+}
+
+/// Makes a recorded quote or balance available to the replay, later events of
+/// the same pair and size replace earlier ones.
+pub async fn replay(event: &Event) {
+    let mut state = REPLAY.write().await;
+    match event {
+        Event::Quote {
+            chain,
+            token_in,
+            token_out,
+            amount_in,
+            amount_out,
+            price_impact_pct,
+        } => {
+            let (Ok(amount_in), Ok(amount_out)) =
+                (BigInt::from_str(amount_in), BigInt::from_str(amount_out))
+            else {
+                return;
+            };
+            let quotes = state
+                .quotes
+                .entry((
+                    chain.clone(),
+                    token_key(token_in),
+                    token_key(token_out),
+                ))
+                .or_default();
+            quotes.retain(|(recorded_in, _, _)| *recorded_in != amount_in);
+            quotes.push((amount_in, amount_out, *price_impact_pct));
+        }
+        Event::Balance {
+            chain,
+            token,
+            balance,
+        } => {
+            if let Ok(balance) = BigInt::from_str(balance) {
+                state
+                    .balances
+                    .insert((chain.clone(), token_key(token)), balance);
+            }
+        }
+        Event::Received { .. } | Event::Sent { .. } | Event::Bid { .. } => {}
+    }
+}
+
+/// Replayed quote for `amount_in`, interpolate_quote over the sizes recorded
+/// for the pair. (0, 0) when the pair was never quoted.
+pub async fn replayed_quote(
+    chain: &str,
+    token_in: &str,
+    token_out: &str,
+    amount_in: &BigInt,
+) -> (BigInt, f64) {
+    let state = REPLAY.read().await;
+    state
+        .quotes
+        .get(&(chain.to_string(), token_key(token_in), token_key(token_out)))
+        .and_then(|quotes| interpolate_quote(quotes, amount_in))
+        .unwrap_or_else(|| (BigInt::zero(), 0f64))
+}
+
+pub async fn replayed_balance(chain: &str, token: &str) -> Result<BigInt, String> {
+    REPLAY
+        .read()
+        .await
+        .balances
+        .get(&(chain.to_string(), token_key(token)))
+        .cloned()
+        .ok_or_else(|| format!("no recorded {token} balance on {chain}"))
+}
@@ -0,0 +1,259 @@
+use crate::inventory::{reserved_valuation, RESERVATIONS};
+use crate::journal::{pending, JournalEntry};
+use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use num_traits::Zero;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+// amounts are in the bridge token smallest unit (USDT / USDC, 6 decimals)
+#[derive(Debug, Clone, Default)]
+pub struct RiskLimits {
+    pub max_notional_per_intent: Option<BigInt>,
+    // <chain, max bridge token promised to unfilled intents>
+    pub max_open_exposure: HashMap<String, BigInt>,
+    pub max_daily_loss: Option<BigInt>,
+    // empty allowlist means every token not in the denylist is allowed
+    pub token_allowlist: HashSet<String>,
+    pub token_denylist: HashSet<String>,
+    pub max_price_impact_pct: Option<f64>,
+}
+
+fn env_bigint(var: &str) -> Option<BigInt> {
+    env::var(var)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .and_then(|value| BigInt::from_str(&value).ok())
+}
+
+fn env_token_set(var: &str) -> HashSet<String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(|token| token.trim().to_lowercase())
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+impl RiskLimits {
+    pub fn from_env() -> Self {
+        let mut max_open_exposure = HashMap::new();
+        for chain in ["ethereum", "solana"] {
+            if let Some(max) = env_bigint(&format!("RISK_MAX_EXPOSURE_{}", chain.to_uppercase())) {
+                max_open_exposure.insert(chain.to_string(), max);
+            }
+        }
+
+        RiskLimits {
+            max_notional_per_intent: env_bigint("RISK_MAX_NOTIONAL"),
+            max_open_exposure,
+            max_daily_loss: env_bigint("RISK_MAX_DAILY_LOSS"),
+            token_allowlist: env_token_set("RISK_TOKEN_ALLOWLIST"),
+            token_denylist: env_token_set("RISK_TOKEN_DENYLIST"),
+            max_price_impact_pct: env::var("RISK_MAX_PRICE_IMPACT_PCT")
+                .ok()
+                .and_then(|pct| pct.parse::<f64>().ok()),
+        }
+    }
+}
+
+pub static KILL_SWITCH: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    pub static ref RISK_LIMITS: Arc<RwLock<RiskLimits>> = Arc::new(RwLock::new(RiskLimits::from_env()));
+
+    // (day since epoch, realized pnl of that day)
+    static ref DAILY_PNL: Arc<RwLock<(u64, BigInt)>> = Arc::new(RwLock::new((0, BigInt::zero())));
+
+    // touching this file stops new bids without restarting the solver
+    static ref KILL_SWITCH_FILE: String =
+        env::var("KILL_SWITCH_FILE").unwrap_or_else(|_| String::from("KILL_SWITCH"));
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        / 86_400
+}
+
+pub fn set_kill_switch(engaged: bool) {
+    KILL_SWITCH.store(engaged, Ordering::SeqCst);
+    println!("Kill switch {}", if engaged { "engaged" } else { "released" });
+}
+
+pub fn kill_switch_engaged() -> bool {
+    KILL_SWITCH.load(Ordering::SeqCst) || Path::new(KILL_SWITCH_FILE.as_str()).exists()
+}
+
+/// Adds the result of a fill to today's pnl, positive means we made money.
+pub async fn record_pnl(pnl: BigInt) {
+    let mut daily = DAILY_PNL.write().await;
+    if daily.0 != today() {
+        *daily = (today(), BigInt::zero());
+    }
+    daily.1 += pnl;
+}
+
+/// Records the PnL of a fill on intent_id in bridge token units and returns it.
+/// `bridge_delta` is how our bridge token balance on dst_chain moved during the
+/// fill. What it doesn't see is taken at the bid's valuation: token_out sent from
+/// inventory (`direct`) and token_in not received there as the bridge token
+/// (`token_in_elsewhere`: cross domain, or kept as inventory). Fills without a
+/// reservation, e.g. recovered after a restart, take it from the journal, and
+/// record nothing when it isn't there either.
+pub async fn record_fill_pnl(
+    intent_id: &str,
+    bridge_delta: BigInt,
+    direct: bool,
+    token_in_elsewhere: bool,
+) -> Option<BigInt> {
+    let valuation = match reserved_valuation(intent_id).await {
+        Some(valuation) => Some(valuation),
+        None => pending()
+            .await
+            .ok()
+            .and_then(|journal| journal.get(intent_id).and_then(JournalEntry::valuation)),
+    };
+
+    let mut pnl = bridge_delta;
+    if direct || token_in_elsewhere {
+        let Some((notional, value_in)) = valuation else {
+            println!("Not recording the PnL of intent {intent_id}: the value of its bid is unknown");
+            return None;
+        };
+        if direct {
+            pnl -= notional;
+        }
+        if token_in_elsewhere {
+            pnl += value_in;
+        }
+    }
+
+    record_pnl(pnl.clone()).await;
+    Some(pnl)
+}
+
+/// Kill switch and daily loss, the checks that don't depend on the intent.
+pub async fn check_trading_allowed() -> Result<(), String> {
+    if kill_switch_engaged() {
+        return Err("kill switch engaged".to_string());
+    }
+
+    if let Some(max_loss) = &RISK_LIMITS.read().await.max_daily_loss {
+        let daily = DAILY_PNL.read().await;
+        if daily.0 == today() && -&daily.1 >= *max_loss {
+            return Err(format!("daily loss limit reached ({})", daily.1));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn check_tokens(token_in: &str, token_out: &str) -> Result<(), String> {
+    let limits = RISK_LIMITS.read().await;
+
+    for token in [token_in, token_out] {
+        let token = token.to_lowercase();
+        if limits.token_denylist.contains(&token) {
+            return Err(format!("token {token} is denylisted"));
+        }
+        if !limits.token_allowlist.is_empty() && !limits.token_allowlist.contains(&token) {
+            return Err(format!("token {token} is not allowlisted"));
+        }
+    }
+
+    Ok(())
+}
+
+/// `notional` is the bridge token value of the intent, `chain` where we spend it.
+pub async fn check_exposure(chain: &str, notional: &BigInt) -> Result<(), String> {
+    let limits = RISK_LIMITS.read().await;
+
+    if let Some(max_notional) = &limits.max_notional_per_intent {
+        if notional > max_notional {
+            return Err(format!("notional {notional} above max {max_notional}"));
+        }
+    }
+
+    if let Some(max_exposure) = limits.max_open_exposure.get(chain) {
+        let open = RESERVATIONS
+            .read()
+            .await
+            .values()
+            .filter(|r| r.is_live() && r.chain == chain)
+            .fold(BigInt::zero(), |acc, r| acc + &r.notional);
+
+        if &(open.clone() + notional) > max_exposure {
+            return Err(format!(
+                "open exposure on {chain} would be {} (max {max_exposure})",
+                open + notional
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn check_price_impact(price_impact_pct: f64) -> Result<(), String> {
+    match RISK_LIMITS.read().await.max_price_impact_pct {
+        Some(max) if price_impact_pct > max => Err(format!(
+            "price impact {price_impact_pct}% above max {max}%"
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::{reserve, Reservation};
+
+    #[tokio::test]
+    async fn fill_pnl_uses_the_bid_valuation() {
+        reserve(
+            "risk-reserved",
+            Reservation {
+                chain: String::from("solana"),
+                token: String::from("token_out"),
+                amount: BigInt::from(990),
+                bridge_token: String::from("USDT"),
+                notional: BigInt::from(1000),
+                value_in: BigInt::from(1010),
+                expires_at: None,
+            },
+        )
+        .await;
+
+        // token_out from inventory, token_in received on the other chain
+        assert_eq!(
+            record_fill_pnl("risk-reserved", BigInt::zero(), true, true).await,
+            Some(BigInt::from(10))
+        );
+        assert_eq!(
+            record_fill_pnl("risk-reserved", BigInt::from(-1000), false, true).await,
+            Some(BigInt::from(10))
+        );
+    }
+
+    #[tokio::test]
+    async fn fill_pnl_without_valuation_is_not_recorded() {
+        // the bridge token delta is all there is to it
+        assert_eq!(
+            record_fill_pnl("risk-unknown", BigInt::from(5), false, false).await,
+            Some(BigInt::from(5))
+        );
+        // a cross domain fill would look like losing the whole bridge amount
+        assert_eq!(
+            record_fill_pnl("risk-unknown", BigInt::from(-1000), false, true).await,
+            None
+        );
+    }
+}
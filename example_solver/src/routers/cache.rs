@@ -0,0 +1,360 @@
+use crate::chains::ethereum::ethereum_chain::ethereum_simulate_swap;
+use crate::chains::solana::solana_chain::solana_simulate_swap;
+use crate::chains::SOLVER_ADDRESSES;
+use crate::inventory::token_key;
+use crate::recorder::{record, replayed_quote, replaying, Event};
+use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone)]
+struct CachedQuote {
+    amount_in: BigInt,
+    amount_out: BigInt,
+    price_impact_pct: f64,
+    fetched_at: Instant,
+}
+
+lazy_static! {
+    // <(chain, token_in, token_out, size bucket), CachedQuote>
+    static ref QUOTES: Arc<RwLock<HashMap<(String, String, String, u64), CachedQuote>>> = {
+        let m = HashMap::new();
+        Arc::new(RwLock::new(m))
+    };
+
+    static ref QUOTE_TTL: Duration = Duration::from_millis(
+        env::var("QUOTE_CACHE_TTL_MS")
+            .ok()
+            .and_then(|ttl| ttl.parse::<u64>().ok())
+            .unwrap_or(3000),
+    );
+
+    static ref QUOTE_CACHE_TOLERANCE_BPS: u64 = env::var("QUOTE_CACHE_TOLERANCE_BPS")
+        .ok()
+        .and_then(|bps| bps.parse::<u64>().ok())
+        .unwrap_or(100);
+}
+
+// amounts within the same power of two share a bucket
+fn size_bucket(amount: &BigInt) -> u64 {
+    amount.bits()
+}
+
+fn cache_key(chain: &str, token_in: &str, token_out: &str, bucket: u64) -> (String, String, String, u64) {
+    (chain.to_string(), token_key(token_in), token_key(token_out), bucket)
+}
+
+fn scaled(quote: &CachedQuote, amount_in: &BigInt) -> (BigInt, f64) {
+    (&quote.amount_out * amount_in / &quote.amount_in, quote.price_impact_pct)
+}
+
+/// Quote for `amount_in` from (amount_in, amount_out, price_impact_pct) quotes of
+/// the pair at other sizes, never above what the routers would likely give since
+/// price impact grows with size: a quote of that size as is, the rate
+/// interpolated between the closest smaller and larger sizes, below the smallest
+/// size its rate, past the largest the rate falling on as it did between the two
+/// largest (never rising). None without quotes.
+pub(crate) fn interpolate_quote(
+    quotes: &[(BigInt, BigInt, f64)],
+    amount_in: &BigInt,
+) -> Option<(BigInt, f64)> {
+    let mut quotes: Vec<&(BigInt, BigInt, f64)> = quotes
+        .iter()
+        .filter(|(quoted_in, _, _)| !quoted_in.is_zero())
+        .collect();
+    quotes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let upper = quotes.iter().position(|(quoted_in, _, _)| quoted_in >= amount_in);
+    let (lower, upper) = match upper {
+        Some(upper) if &quotes[upper].0 == amount_in => {
+            let (_, quoted_out, price_impact_pct) = quotes[upper];
+            return Some((quoted_out.clone(), *price_impact_pct));
+        }
+        // smaller than anything quoted, the rate of a larger size is a floor
+        Some(0) => {
+            let (quoted_in, quoted_out, price_impact_pct) = quotes[0];
+            return Some((quoted_out * amount_in / quoted_in, *price_impact_pct));
+        }
+        Some(upper) => (quotes[upper - 1], quotes[upper]),
+        None if quotes.len() >= 2 => (quotes[quotes.len() - 2], quotes[quotes.len() - 1]),
+        None => {
+            let (quoted_in, quoted_out, price_impact_pct) = quotes.last()?;
+            return Some((quoted_out * amount_in / quoted_in, *price_impact_pct));
+        }
+    };
+
+    let rate = |(quoted_in, quoted_out, _): &(BigInt, BigInt, f64)| {
+        quoted_out.to_f64().unwrap_or_default() / quoted_in.to_f64().unwrap_or(1f64)
+    };
+    let (lower_in, upper_in) = (lower.0.to_f64()?, upper.0.to_f64()?);
+    let weight = (amount_in.to_f64()? - lower_in) / (upper_in - lower_in);
+    let rate = (rate(lower) + (rate(upper) - rate(lower)) * weight)
+        .min(rate(lower).max(rate(upper)))
+        .max(0f64);
+    let price_impact_pct = lower.2 + (upper.2 - lower.2) * weight;
+
+    let amount_out = BigInt::from_str(&format!("{:.0}", (amount_in.to_f64()? * rate).floor())).ok()?;
+    Some((amount_out, price_impact_pct))
+}
+
+/// Quote for `amount_in` from fresh cached quotes of the pair: a quote within
+/// QUOTE_CACHE_TOLERANCE_BPS of the size is scaled linearly, otherwise
+/// interpolate_quote between the closest smaller and larger quotes, or from a
+/// quote up to twice the size. None when nothing usable is cached, sizes past
+/// the largest fresh one included.
+async fn cached_quote(
+    chain: &str,
+    token_in: &str,
+    token_out: &str,
+    amount_in: &BigInt,
+) -> Option<(BigInt, f64)> {
+    let bucket = size_bucket(amount_in);
+    let quotes = QUOTES.read().await;
+    // neighbours a few buckets away at most
+    let fresh: Vec<&CachedQuote> = (bucket.saturating_sub(4)..=bucket + 4)
+        .filter_map(|bucket| quotes.get(&cache_key(chain, token_in, token_out, bucket)))
+        .filter(|quote| quote.fetched_at.elapsed() < *QUOTE_TTL)
+        .collect();
+
+    let tolerance = amount_in * *QUOTE_CACHE_TOLERANCE_BPS / 10_000u64;
+    if let Some(quote) = fresh
+        .iter()
+        .filter(|quote| (&quote.amount_in - amount_in).magnitude() <= tolerance.magnitude())
+        .min_by_key(|quote| (&quote.amount_in - amount_in).magnitude().clone())
+    {
+        return Some(scaled(quote, amount_in));
+    }
+
+    let smallest = fresh.iter().map(|quote| &quote.amount_in).min()?;
+    let largest = fresh.iter().map(|quote| &quote.amount_in).max()?;
+    if amount_in > largest || smallest > &(amount_in * 2u32) {
+        return None;
+    }
+
+    let sizes: Vec<(BigInt, BigInt, f64)> = fresh
+        .iter()
+        .map(|quote| (quote.amount_in.clone(), quote.amount_out.clone(), quote.price_impact_pct))
+        .collect();
+    interpolate_quote(&sizes, amount_in)
+}
+
+async fn store_quote(
+    chain: &str,
+    token_in: &str,
+    token_out: &str,
+    amount_in: &BigInt,
+    amount_out: &BigInt,
+    price_impact_pct: f64,
+) {
+    if amount_in.is_zero() || amount_out.is_zero() {
+        return;
+    }
+
+    let mut quotes = QUOTES.write().await;
+    quotes.insert(
+        cache_key(chain, token_in, token_out, size_bucket(amount_in)),
+        CachedQuote {
+            amount_in: amount_in.clone(),
+            amount_out: amount_out.clone(),
+            price_impact_pct,
+            fetched_at: Instant::now(),
+        },
+    );
+}
+
+async fn fresh_quote(
+    chain: &str,
+    user: &str,
+    token_in: &str,
+    token_out: &str,
+    amount_in: &BigInt,
+) -> (BigInt, f64) {
+    let (amount_out, price_impact_pct) = if chain == "ethereum" {
+        ethereum_simulate_swap(token_in, &amount_in.to_string(), token_out).await
+    } else {
+        let (amount_out, price_impact_pct) =
+            solana_simulate_swap(user, token_in, token_out, amount_in.to_u64().unwrap_or_default())
+                .await;
+        (BigInt::from_str(&amount_out).unwrap_or_default(), price_impact_pct)
+    };
+
+    store_quote(chain, token_in, token_out, amount_in, &amount_out, price_impact_pct).await;
+    (amount_out, price_impact_pct)
+}
+
+/// (amount_out, price_impact_pct) of swapping `amount_in` token_in -> token_out
+/// on `chain`, served from the quote cache when possible. Only meant for
+/// bidding, execution always asks the routers for a fresh quote. Quotes are
+/// recorded to SESSION_RECORD, and come from it while a backtest replays.
+///
+/// A cache hit asks no router, so no Paraswap route is kept for the bid
+/// (remember_bid_quote) and its fill quotes a fresh route at execution, like a
+/// bid whose route expired. The cached route was for another size anyway.
+pub async fn simulate_swap(
+    chain: &str,
+    user: &str,
+    token_in: &str,
+    token_out: &str,
+    amount_in: &BigInt,
+) -> (BigInt, f64) {
+    if replaying() {
+        return replayed_quote(chain, token_in, token_out, amount_in).await;
+    }
+
+    let (amount_out, price_impact_pct) =
+        match cached_quote(chain, token_in, token_out, amount_in).await {
+            Some(quote) => quote,
+            None => fresh_quote(chain, user, token_in, token_out, amount_in).await,
+        };
+
+    record(Event::Quote {
+        chain: chain.to_string(),
+        token_in: token_in.to_string(),
+        token_out: token_out.to_string(),
+        amount_in: amount_in.to_string(),
+        amount_out: amount_out.to_string(),
+        price_impact_pct,
+    })
+    .await;
+
+    (amount_out, price_impact_pct)
+}
+
+/// Keeps the cache warm for QUOTE_PREWARM_PAIRS, a comma separated list of
+/// "chain:token_in:token_out:size1;size2;..." with sizes in token_in units.
+pub async fn run_quote_prewarmer() {
+    let pairs: Vec<(String, String, String, Vec<BigInt>)> = env::var("QUOTE_PREWARM_PAIRS")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .filter_map(|pair| {
+            let parts: Vec<&str> = pair.trim().split(':').collect();
+            if parts.len() != 4 {
+                eprintln!("Invalid QUOTE_PREWARM_PAIRS entry: {pair}");
+                return None;
+            }
+            let sizes = parts[3]
+                .split(';')
+                .filter_map(|size| BigInt::from_str(size.trim()).ok())
+                .collect();
+            Some((parts[0].to_string(), parts[1].to_string(), parts[2].to_string(), sizes))
+        })
+        .collect();
+
+    if pairs.is_empty() {
+        return;
+    }
+
+    // refresh a bit before the cached quotes expire
+    let interval = *QUOTE_TTL * 2 / 3;
+
+    loop {
+        for (chain, token_in, token_out, sizes) in &pairs {
+            let user = if chain == "ethereum" {
+                SOLVER_ADDRESSES.get(0).unwrap()
+            } else {
+                SOLVER_ADDRESSES.get(1).unwrap()
+            };
+
+            for size in sizes {
+                fresh_quote(chain, user, token_in, token_out, size).await;
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2 token_out per token_in at 1000, 1.5 at 3000
+    fn quotes() -> Vec<(BigInt, BigInt, f64)> {
+        vec![
+            (BigInt::from(3000), BigInt::from(4500), 0.3),
+            (BigInt::from(1000), BigInt::from(2000), 0.1),
+        ]
+    }
+
+    fn assert_quote(quote: Option<(BigInt, f64)>, amount_out: u64, price_impact_pct: f64) {
+        let (out, impact) = quote.unwrap();
+        assert_eq!(out, BigInt::from(amount_out));
+        assert!((impact - price_impact_pct).abs() < 1e-9, "{impact}");
+    }
+
+    #[test]
+    fn interpolates_between_quoted_sizes() {
+        // a quoted size as is
+        assert_quote(interpolate_quote(&quotes(), &BigInt::from(1000)), 2000, 0.1);
+        // below the smallest at its rate
+        assert_quote(interpolate_quote(&quotes(), &BigInt::from(500)), 1000, 0.1);
+        // halfway, 1.75
+        assert_quote(interpolate_quote(&quotes(), &BigInt::from(2000)), 3500, 0.2);
+        // the rate keeps falling 0.25 per 1000 past the largest, 1.0 at 5000
+        assert_quote(interpolate_quote(&quotes(), &BigInt::from(5000)), 5000, 0.5);
+    }
+
+    #[test]
+    fn past_the_largest_the_rate_never_rises() {
+        // 1.5 at 1000, 2 at 3000
+        let quotes = vec![
+            (BigInt::from(1000), BigInt::from(1500), 0.1),
+            (BigInt::from(3000), BigInt::from(6000), 0.1),
+        ];
+        assert_quote(interpolate_quote(&quotes, &BigInt::from(5000)), 10_000, 0.1);
+
+        // a single size is scaled either way
+        let quotes = &quotes[..1];
+        assert_quote(interpolate_quote(quotes, &BigInt::from(4000)), 6000, 0.1);
+        assert_quote(interpolate_quote(quotes, &BigInt::from(100)), 150, 0.1);
+        assert_eq!(interpolate_quote(&[], &BigInt::from(100)), None);
+    }
+
+    #[test]
+    fn sizes_within_a_power_of_two_share_a_bucket() {
+        assert_eq!(size_bucket(&BigInt::from(512)), size_bucket(&BigInt::from(1023)));
+        assert_ne!(size_bucket(&BigInt::from(1023)), size_bucket(&BigInt::from(1024)));
+        assert_eq!(size_bucket(&BigInt::zero()), 0);
+    }
+
+    async fn quote(amount_in: u64) -> Option<(BigInt, f64)> {
+        cached_quote("solana", "cache_in", "cache_out", &BigInt::from(amount_in)).await
+    }
+
+    #[tokio::test]
+    async fn cached_quotes_are_scaled_or_interpolated() {
+        for (amount_in, amount_out, price_impact_pct) in [(1000u64, 2000u64, 0.1), (4000, 6000, 0.3)] {
+            store_quote(
+                "solana",
+                "cache_in",
+                "cache_out",
+                &BigInt::from(amount_in),
+                &BigInt::from(amount_out),
+                price_impact_pct,
+            )
+            .await;
+        }
+
+        // within QUOTE_CACHE_TOLERANCE_BPS of 1000, scaled
+        assert_quote(quote(1005).await, 2010, 0.1);
+        // between the sizes, 1.75
+        assert_quote(quote(2500).await, 4375, 0.2);
+        // at most half the smallest size, at its rate
+        assert_quote(quote(500).await, 1000, 0.1);
+        assert_eq!(quote(400).await, None);
+        // nothing to tell how much worse larger sizes get
+        assert_eq!(quote(5000).await, None);
+        // other pairs ask the routers
+        assert_eq!(
+            cached_quote("solana", "cache_out", "cache_in", &BigInt::from(1000)).await,
+            None
+        );
+    }
+}
@@ -22,7 +22,7 @@ use {
 use crate::get_associated_token_address;
 use serde_json::Value;
 use solana_sdk::pubkey;
-use solana_sdk::signer::keypair::Keypair;
+use crate::signer::SolanaSigner;
 use spl_associated_token_account::instruction;
 
 /// A `Result` alias where the `Err` case is `jup_ag::Error`.
@@ -463,7 +463,7 @@ impl Memo {
 pub async fn jupiter_swap(
     _memo: &str,
     rpc_client: &RpcClient,
-    keypair: &Keypair,
+    keypair: &SolanaSigner,
     swap_mode: SwapMode,
 ) -> core::result::Result<(), String> {
     // Parse the memo JSON
@@ -537,7 +537,7 @@ pub async fn jupiter_swap(
 pub async fn create_token_account(
     owner: &Pubkey,
     mint: &Pubkey,
-    fee_payer: &Keypair,
+    fee_payer: &SolanaSigner,
     rpc_client: &RpcClient,
 ) -> Result<()> {
     let create_account_ix = instruction::create_associated_token_account(
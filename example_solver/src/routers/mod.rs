@@ -1,16 +1,20 @@
+pub mod cache;
 pub mod jupiter;
 pub mod paraswap;
+pub mod solana_amm;
+pub mod uniswap;
 
 // use ethers::providers::Middleware;
 // use serde_json::Value;
 use crate::chains::*;
+use crate::inventory::{check_funding, fills_from_inventory, inventory_target};
+use cache::simulate_swap;
+use crate::risk::{check_exposure, check_price_impact, check_tokens, check_trading_allowed};
 use crate::PostIntentInfo;
-use ethereum::ethereum_chain::ethereum_simulate_swap;
+use futures::future::join_all;
 use lazy_static::lazy_static;
 use num_bigint::BigInt;
-use num_traits::ToPrimitive;
 use num_traits::Zero;
-use solana::solana_chain::solana_simulate_swap;
 use std::collections::HashMap;
 use std::env;
 use std::str::FromStr;
@@ -18,7 +22,7 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 
 lazy_static! {
-    // <(src_chain, dst_chain), (src_chain_cost, dst_chain_cost)> // cost in USDT
+    // <(src_chain, dst_chain), (src_chain_cost, dst_chain_cost)> // cost in bridge token units
     pub static ref FLAT_FEES: Arc<RwLock<HashMap<(String, String), (u32, u32)>>> = {
         let mut m = HashMap::new();
         m.insert(("ethereum".to_string(), "ethereum".to_string()), (0, 0));       // 0$ 3$
@@ -35,6 +39,85 @@ lazy_static! {
         m.insert("9fJw9rQdMi8QEJnBsybVKU7XTXBUTXVKpinDaYMsVSUS".to_string(), "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB".to_string()); // USDT (test, not the IBC-USDT)
         Arc::new(RwLock::new(m))
     };
+
+    // COMISSION, the solver cut of every intent, kept here so it can change at runtime
+    pub static ref COMISSION: Arc<RwLock<u32>> = Arc::new(RwLock::new(
+        env::var("COMISSION")
+            .expect("COMISSION must be set")
+            .parse::<u32>()
+            .unwrap(),
+    ));
+
+    // <(src_chain, dst_chain), reason> routes we don't bid on for now
+    pub static ref PAUSED_ROUTES: Arc<RwLock<HashMap<(String, String), String>>> = {
+        let m = HashMap::new();
+        Arc::new(RwLock::new(m))
+    };
+
+    // <(dst_chain, bridge token), reason> bridge tokens we don't fill with on
+    // dst_chain for now, the other BRIDGE_TOKENS still quote
+    pub static ref PAUSED_BRIDGE_TOKENS: Arc<RwLock<HashMap<(String, String), String>>> = {
+        let m = HashMap::new();
+        Arc::new(RwLock::new(m))
+    };
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IntentQuote {
+    // token_out the solver can provide (after FLAT_FEES + COMISSION)
+    pub amount_out: String,
+    // bridge token token_in is worth on src_chain, what the solver ends up with
+    pub bridge_amount_src: BigInt,
+    // bridge token the solver spends on dst_chain to fill the intent, or what the
+    // token_out it fills from inventory is worth
+    pub bridge_amount_dst: BigInt,
+    pub dst_chain: String,
+    // BRIDGE_TOKENS entry the quote goes through, and its address on dst_chain
+    pub bridge_token: String,
+    pub bridge_token_dst: String,
+    // token_out address on dst_chain when the inventory holds enough of it to
+    // fill without the bridge token -> token_out swap
+    pub direct_token: Option<String>,
+    // summed over the token_in -> bridge and bridge -> token_out legs
+    pub price_impact_pct: f64,
+}
+
+impl IntentQuote {
+    fn zero() -> Self {
+        IntentQuote {
+            amount_out: String::from("0"),
+            ..IntentQuote::default()
+        }
+    }
+}
+
+/// Quotes the intent through every BRIDGE_TOKENS entry and keeps the one
+/// giving the user the most token_out.
+pub async fn get_best_quote(intent_info: &PostIntentInfo) -> IntentQuote {
+    best_quote(intent_info, &BRIDGE_TOKENS).await
+}
+
+async fn best_quote(intent_info: &PostIntentInfo, bridge_tokens: &[String]) -> IntentQuote {
+    let quotes = join_all(bridge_tokens.iter().map(|bridge_token| {
+        get_simulate_swap_intent(
+            intent_info,
+            &intent_info.src_chain,
+            &intent_info.dst_chain,
+            bridge_token,
+        )
+    }))
+    .await;
+
+    if quotes.len() > 1 {
+        for quote in &quotes {
+            println!("Through {}: {} token_out", quote.bridge_token, quote.amount_out);
+        }
+    }
+
+    quotes
+        .into_iter()
+        .max_by_key(|quote| BigInt::from_str(&quote.amount_out).unwrap_or_default())
+        .unwrap_or_else(IntentQuote::zero)
 }
 
 pub async fn get_simulate_swap_intent(
@@ -42,7 +125,7 @@ pub async fn get_simulate_swap_intent(
     mut src_chain: &str,
     mut dst_chain: &str,
     bridge_token: &String,
-) -> String {
+) -> IntentQuote {
     // Extracting values from OperationInput
     let (mut token_in, amount_in, src_chain_user) = match &intent_info.inputs {
         OperationInput::SwapTransfer(input) => (
@@ -88,14 +171,51 @@ pub async fn get_simulate_swap_intent(
         dst_chain = "solana";
     }
 
+    if let Some(reason) = PAUSED_ROUTES
+        .read()
+        .await
+        .get(&(src_chain.to_string(), dst_chain.to_string()))
+    {
+        println!("Route {src_chain} -> {dst_chain} is paused: {reason}");
+        return IntentQuote::zero();
+    }
+
+    if let Some(reason) = PAUSED_BRIDGE_TOKENS
+        .read()
+        .await
+        .get(&(dst_chain.to_string(), bridge_token.clone()))
+    {
+        println!("{bridge_token} on {dst_chain} is paused: {reason}");
+        return IntentQuote::zero();
+    }
+
+    if let Err(e) = check_trading_allowed().await {
+        println!("Not bidding: {e}");
+        return IntentQuote::zero();
+    }
+
+    if let Err(e) = check_tokens(&token_in, &token_out).await {
+        println!("Skipping intent: {e}");
+        return IntentQuote::zero();
+    }
+
     let (bridge_token_address_src, _) = get_token_info(bridge_token, src_chain).unwrap();
     let mut amount_out_src_chain = BigInt::from_str(&amount_in).unwrap();
+    let mut price_impact_pct = 0f64;
 
     if !bridge_token_address_src.eq_ignore_ascii_case(&token_in) {
-        // simulate token_in -> USDT
+        // simulate token_in -> bridge token
         if src_chain == "ethereum" {
-            amount_out_src_chain =
-                ethereum_simulate_swap(&token_in, &amount_in, bridge_token_address_src).await;
+            let (amount_out, impact) = simulate_swap(
+                src_chain,
+                &src_chain_user,
+                &token_in,
+                bridge_token_address_src,
+                &amount_out_src_chain,
+            )
+            .await;
+            amount_out_src_chain = amount_out;
+            price_impact_pct += impact;
         } else if src_chain == "solana" || src_chain == "mantis" {
             if src_chain == "mantis" {
                 let tokens = MANTIS_TOKENS.read().await;
@@ -112,22 +232,27 @@ pub async fn get_simulate_swap_intent(
             }
 
             if !amount_out_src_chain.is_zero() {
-                amount_out_src_chain = BigInt::from_str(
-                    &solana_simulate_swap(
-                        &src_chain_user,
-                        &token_in,
-                        &bridge_token_address_src,
-                        BigInt::from_str(&amount_in).unwrap().to_u64().unwrap(),
-                    )
-                    .await,
+                let (amount_out, impact) = simulate_swap(
+                    src_chain,
+                    &src_chain_user,
+                    &token_in,
+                    bridge_token_address_src,
+                    &BigInt::from_str(&amount_in).unwrap(),
                 )
-                .unwrap();
+                .await;
+                amount_out_src_chain = amount_out;
+                price_impact_pct += impact;
             }
         }
     }
 
     let (bridge_token_address_dst, _) = get_token_info(bridge_token, dst_chain).unwrap();
 
+    if let Err(e) = check_exposure(dst_chain, &amount_out_src_chain).await {
+        println!("Skipping intent: {e}");
+        return IntentQuote::zero();
+    }
+
     // get flat fees
     let flat_fees;
     {
@@ -140,31 +265,53 @@ pub async fn get_simulate_swap_intent(
     }
 
     // get comission
-    let comission = env::var("COMISSION")
-        .expect("COMISSION must be set")
-        .parse::<u32>()
-        .unwrap();
+    let comission = *COMISSION.read().await;
 
-    if amount_out_src_chain < BigInt::from(flat_fees.0 + flat_fees.1 + (&amount_out_src_chain * comission) / 100) {
-        return String::from("0");
+    // nothing left once the fees and the comission deducted below are paid
+    if amount_out_src_chain
+        < BigInt::from(flat_fees.0)
+            + BigInt::from(flat_fees.1)
+            + &amount_out_src_chain * BigInt::from(comission) / BigInt::from(100_000)
+    {
+        return IntentQuote::zero();
     }
 
+    let bridge_amount_src = amount_out_src_chain.clone();
+
     // we substract the flat fees and the solver comission in USD
-    let amount_in_dst_chain = amount_out_src_chain.clone()
+    let mut amount_in_dst_chain = amount_out_src_chain.clone()
         - (BigInt::from(flat_fees.0)
             + BigInt::from(flat_fees.1)
             + (amount_out_src_chain * BigInt::from(comission) / BigInt::from(100_000)));
 
+    // make sure we can actually fund the fill on dst_chain before bidding, token_out
+    // held as inventory may still fund it when the bridge token can't
+    let mut unfunded = None;
+    match check_funding(dst_chain, bridge_token_address_dst, &amount_in_dst_chain).await {
+        Ok(funded_amount) => amount_in_dst_chain = funded_amount,
+        Err(e) if inventory_target(dst_chain, &token_out).is_some() => unfunded = Some(e),
+        Err(e) => {
+            println!("Skipping intent: {e}");
+            return IntentQuote::zero();
+        }
+    }
+
     let mut final_amount_out = amount_in_dst_chain.to_string();
 
     if !amount_in_dst_chain.is_zero() && !bridge_token_address_dst.eq_ignore_ascii_case(&token_out)
     {
-        // simulate USDT -> token_out
+        // simulate bridge token -> token_out
         if dst_chain == "ethereum" {
-            final_amount_out =
-                ethereum_simulate_swap(bridge_token_address_dst, &final_amount_out, &token_out)
-                    .await
-                    .to_string();
+            let (amount_out, impact) = simulate_swap(
+                dst_chain,
+                &dst_chain_user,
+                bridge_token_address_dst,
+                &token_out,
+                &amount_in_dst_chain,
+            )
+            .await;
+            final_amount_out = amount_out.to_string();
+            price_impact_pct += impact;
         } else if dst_chain == "solana" || dst_chain == "mantis" {
             if src_chain == "mantis" {
                 let tokens = MANTIS_TOKENS.read().await;
@@ -181,18 +328,50 @@ pub async fn get_simulate_swap_intent(
             }
 
             if final_amount_out != "0" {
-                final_amount_out = solana_simulate_swap(
+                let (amount_out, impact) = simulate_swap(
+                    dst_chain,
                     &dst_chain_user,
                     bridge_token_address_dst,
                     &token_out,
-                    amount_in_dst_chain.to_u64().unwrap(),
+                    &amount_in_dst_chain,
                 )
                 .await;
+                final_amount_out = amount_out.to_string();
+                price_impact_pct += impact;
             }
         }
     }
 
-    final_amount_out
+    // token_out is still priced at the router rate, filling it from inventory
+    // saves the swap fees and slippage
+    let direct = !bridge_token_address_dst.eq_ignore_ascii_case(&token_out)
+        && fills_from_inventory(
+            None,
+            dst_chain,
+            &token_out,
+            &BigInt::from_str(&final_amount_out).unwrap_or_default(),
+        )
+        .await;
+    if let (Some(e), false) = (unfunded, direct) {
+        println!("Skipping intent: {e}");
+        return IntentQuote::zero();
+    }
+
+    if let Err(e) = check_price_impact(price_impact_pct).await {
+        println!("Skipping intent: {e}");
+        return IntentQuote::zero();
+    }
+
+    IntentQuote {
+        amount_out: final_amount_out,
+        bridge_amount_src,
+        bridge_amount_dst: amount_in_dst_chain,
+        dst_chain: dst_chain.to_string(),
+        bridge_token: bridge_token.to_string(),
+        bridge_token_dst: bridge_token_address_dst.to_string(),
+        direct_token: if direct { Some(token_out) } else { None },
+        price_impact_pct,
+    }
 }
 
 // Calculation ethereum gas fees
@@ -205,3 +384,148 @@ pub async fn get_simulate_swap_intent(
 
 // let profit = (amount_out_src_chain.to_f64().unwrap() / 10f64.powi(bridge_token_dec_src as i32))
 //     - (amount_in_dst_chain.to_f64().unwrap() / 10f64.powi(bridge_token_dec_dst as i32));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::{set_balance, GAS_TOKEN};
+    use crate::mock_routers::{Fixtures, MockRouters, ROUTER_ENV};
+    use serde_json::json;
+    use solana_sdk::pubkey::Pubkey;
+
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+    fn intent(token_in: &str, amount_in: &str, token_out: &str) -> PostIntentInfo {
+        PostIntentInfo {
+            function_name: String::from("swap"),
+            src_chain: String::from("solana"),
+            dst_chain: String::from("solana"),
+            inputs: OperationInput::SwapTransfer(SwapTransferInput {
+                token_in: token_in.to_string(),
+                amount_in: amount_in.to_string(),
+                src_chain_user: Pubkey::new_unique().to_string(),
+                timeout: String::from("1700000000"),
+            }),
+            outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+                token_out: token_out.to_string(),
+                amount_out: String::from("0"),
+                dst_chain_user: Pubkey::new_unique().to_string(),
+            }),
+        }
+    }
+
+    // quotes against `mock`, solana -> solana costing 1000 + 2000 + 0.1%, with
+    // `bridge_balance` of USDT and USDC on solana. Every test quotes a new
+    // token_in mint so the quote cache never answers for the mock.
+    async fn setup(mock: &MockRouters, bridge_balance: u64) {
+        env::set_var("QUOTE_API_URL", mock.url());
+        env::set_var("ONCHAIN_QUOTER", "off");
+        env::set_var("COMISSION", "100");
+        *COMISSION.write().await = 100;
+        FLAT_FEES
+            .write()
+            .await
+            .insert(("solana".to_string(), "solana".to_string()), (1000, 2000));
+
+        set_balance("solana", GAS_TOKEN, BigInt::from(1_000_000_000u64)).await;
+        for token in [USDT, USDC] {
+            set_balance("solana", token, BigInt::from(bridge_balance)).await;
+        }
+    }
+
+    async fn quote_usdt(intent_info: &PostIntentInfo) -> IntentQuote {
+        get_simulate_swap_intent(intent_info, "solana", "solana", &String::from("USDT")).await
+    }
+
+    #[tokio::test]
+    async fn deducts_flat_fees_and_comission() {
+        let _env = ROUTER_ENV.lock().await;
+        let mock = MockRouters::start(Fixtures::default()).unwrap();
+        setup(&mock, 1_000_000_000).await;
+        let token_in = Pubkey::new_unique().to_string();
+        mock.set_rate(&token_in, USDT, 2.0).await;
+
+        let quote = quote_usdt(&intent(&token_in, "1000000", USDT)).await;
+        assert_eq!(quote.bridge_amount_src, BigInt::from(2_000_000));
+        // 2_000_000 - 1000 - 2000 - 0.1%
+        assert_eq!(quote.bridge_amount_dst, BigInt::from(1_995_000));
+        assert_eq!(quote.amount_out, "1995000");
+        assert_eq!(quote.bridge_token_dst, USDT);
+        assert_eq!(quote.direct_token, None);
+
+        // 2000, less than the flat fees
+        let quote = quote_usdt(&intent(&token_in, "1000", USDT)).await;
+        assert_eq!(quote.amount_out, "0");
+    }
+
+    #[tokio::test]
+    async fn paused_route_quotes_zero() {
+        let _env = ROUTER_ENV.lock().await;
+        let mock = MockRouters::start(Fixtures::default()).unwrap();
+        setup(&mock, 1_000_000_000).await;
+        let route = ("solana".to_string(), "solana".to_string());
+        PAUSED_ROUTES.write().await.insert(route.clone(), String::from("test"));
+
+        let quote = quote_usdt(&intent(&Pubkey::new_unique().to_string(), "1000000", USDT)).await;
+        PAUSED_ROUTES.write().await.remove(&route);
+        assert_eq!(quote.amount_out, "0");
+        assert!(mock.requests().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn router_error_quotes_zero() {
+        let _env = ROUTER_ENV.lock().await;
+        let mock = MockRouters::start(Fixtures::default()).unwrap();
+        setup(&mock, 1_000_000_000).await;
+        mock.set_response("GET /quote", 500, json!({ "error": "internal" })).await;
+
+        let quote = quote_usdt(&intent(&Pubkey::new_unique().to_string(), "1000000", USDT)).await;
+        assert_eq!(quote.amount_out, "0");
+        assert!(!mock.requests().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unfunded_quotes_zero() {
+        let _env = ROUTER_ENV.lock().await;
+        let mock = MockRouters::start(Fixtures::default()).unwrap();
+        // 1_995_000 USDT needed on solana
+        setup(&mock, 1_000_000).await;
+        let token_in = Pubkey::new_unique().to_string();
+        mock.set_rate(&token_in, USDT, 2.0).await;
+
+        let quote = quote_usdt(&intent(&token_in, "1000000", USDT)).await;
+        assert_eq!(quote.amount_out, "0");
+
+        // enough USDT but no SOL for the fill
+        setup(&mock, 1_000_000_000).await;
+        set_balance("solana", GAS_TOKEN, BigInt::from(1_000)).await;
+        let quote = quote_usdt(&intent(&token_in, "1000000", USDT)).await;
+        assert_eq!(quote.amount_out, "0");
+    }
+
+    #[tokio::test]
+    async fn picks_the_best_bridge_token() {
+        let _env = ROUTER_ENV.lock().await;
+        let mock = MockRouters::start(Fixtures::default()).unwrap();
+        setup(&mock, 1_000_000_000).await;
+        let (token_in, token_out) = (Pubkey::new_unique().to_string(), Pubkey::new_unique().to_string());
+        mock.set_rate(USDT, &token_out, 2.0).await;
+        mock.set_rate(USDC, &token_out, 3.0).await;
+        let intent_info = intent(&token_in, "1000000", &token_out);
+        let bridge_tokens = [String::from("USDT"), String::from("USDC")];
+
+        // 1_000_000 - 1000 - 2000 - 0.1% through either, USDC buys more token_out
+        let quote = best_quote(&intent_info, &bridge_tokens).await;
+        assert_eq!(quote.bridge_token, "USDC");
+        assert_eq!(quote.bridge_token_dst, USDC);
+        assert_eq!(quote.amount_out, "2988000");
+
+        let paused = ("solana".to_string(), "USDC".to_string());
+        PAUSED_BRIDGE_TOKENS.write().await.insert(paused.clone(), String::from("test"));
+        let quote = best_quote(&intent_info, &bridge_tokens).await;
+        PAUSED_BRIDGE_TOKENS.write().await.remove(&paused);
+        assert_eq!(quote.bridge_token, "USDT");
+        assert_eq!(quote.amount_out, "1992000");
+    }
+}
@@ -1,14 +1,59 @@
 pub mod paraswap_router {
+    use crate::routers::jupiter::field_as_string;
     use ethers::prelude::Address;
+    use lazy_static::lazy_static;
     use num_bigint::BigInt;
     use reqwest::Client;
+    use serde::{Deserialize, Serialize};
     use serde_json::Value;
-    use std::str::FromStr;
+    use std::collections::HashMap;
+    use std::env;
+    use std::fmt;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::sync::RwLock;
+
+    /// A `Result` alias where the `Err` case is `paraswap_router::Error`.
+    pub type Result<T> = std::result::Result<T, Error>;
+
+    // Reference: https://developers.paraswap.network/api/paraswap-api
+    fn paraswap_api_url() -> String {
+        env::var("PARASWAP_API_URL").unwrap_or_else(|_| "https://api.paraswap.io".to_string())
+    }
+
+    #[derive(thiserror::Error, Debug)]
+    pub enum Error {
+        #[error("reqwest: {0}")]
+        Reqwest(#[from] reqwest::Error),
+
+        #[error("serde_json: {0}")]
+        SerdeJson(#[from] serde_json::Error),
+
+        #[error("Paraswap API: {0}")]
+        ParaswapApi(String),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "UPPERCASE")]
+    pub enum Side {
+        Sell,
+        Buy,
+    }
+
+    impl fmt::Display for Side {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Side::Sell => write!(f, "SELL"),
+                Side::Buy => write!(f, "BUY"),
+            }
+        }
+    }
 
     #[derive(Debug)]
     pub struct ParaswapParams {
-        pub side: String,
+        pub side: Side,
         pub chain_id: u16,
+        // amount of token_in on SELL, of token_out on BUY
         pub amount_in: BigInt,
         pub token_in: Address,
         pub token_out: Address,
@@ -16,108 +61,402 @@ pub mod paraswap_router {
         pub token1_decimals: u32,
         pub wallet_address: Address,
         pub receiver_address: Address,
-        pub client_aggregator: Client,
     }
 
+    /// Partner and slippage settings sent along every request.
+    #[derive(Debug, Clone)]
+    pub struct ParaswapConfig {
+        pub partner: String,
+        pub partner_address: Option<Address>,
+        pub partner_fee_bps: Option<u16>,
+        pub slippage_bps: u16,
+    }
+
+    impl ParaswapConfig {
+        fn from_env() -> Self {
+            ParaswapConfig {
+                partner: env::var("PARASWAP_PARTNER")
+                    .unwrap_or_else(|_| String::from("composable_solver")),
+                partner_address: env::var("PARASWAP_PARTNER_ADDRESS")
+                    .ok()
+                    .and_then(|address| address.parse().ok()),
+                partner_fee_bps: env::var("PARASWAP_PARTNER_FEE_BPS")
+                    .ok()
+                    .and_then(|bps| bps.parse().ok()),
+                slippage_bps: env::var("PARASWAP_SLIPPAGE_BPS")
+                    .ok()
+                    .and_then(|bps| bps.parse().ok())
+                    .unwrap_or(100),
+            }
+        }
+    }
+
+    /// The `priceRoute` of `/prices`. Fields we don't use are kept in `other`
+    /// since `/transactions` wants the route back untouched.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct PriceRoute {
+        #[serde(with = "field_as_string")]
+        pub src_amount: BigInt,
+        #[serde(with = "field_as_string")]
+        pub dest_amount: BigInt,
+        pub side: Side,
+        #[serde(rename = "srcUSD", default, skip_serializing_if = "Option::is_none")]
+        pub src_usd: Option<String>,
+        #[serde(rename = "destUSD", default, skip_serializing_if = "Option::is_none")]
+        pub dest_usd: Option<String>,
+        #[serde(flatten)]
+        pub other: HashMap<String, Value>,
+    }
+
+    impl PriceRoute {
+        // USD value lost between srcUSD and destUSD, in %
+        fn price_impact_pct(&self) -> f64 {
+            let usd = |usd: &Option<String>| {
+                usd.as_deref()
+                    .and_then(|usd| usd.parse::<f64>().ok())
+                    .unwrap_or_default()
+            };
+
+            let (src_usd, dest_usd) = (usd(&self.src_usd), usd(&self.dest_usd));
+            if src_usd <= 0f64 {
+                return 0f64;
+            }
+
+            ((src_usd - dest_usd) / src_usd * 100f64).max(0f64)
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct PricesResponse {
+        price_route: PriceRoute,
+    }
+
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct TransactionRequest<'a> {
+        src_token: String,
+        src_decimals: u32,
+        dest_token: String,
+        dest_decimals: u32,
+        #[serde(with = "field_as_string")]
+        src_amount: BigInt,
+        #[serde(with = "field_as_string")]
+        dest_amount: BigInt,
+        price_route: &'a PriceRoute,
+        user_address: String,
+        tx_origin: String,
+        partner: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        partner_address: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        partner_fee_bps: Option<u16>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct TransactionResponse {
+        to: Address,
+        data: String,
+    }
+
+    /// Result of the `/prices` step, enough to build the transaction later.
+    #[derive(Debug, Clone)]
+    pub struct ParaswapQuote {
+        pub side: Side,
+        // destAmount on SELL, srcAmount on BUY
+        pub amount: BigInt,
+        pub price_route: PriceRoute,
+        pub price_impact_pct: f64,
+        pub quoted_at: Instant,
+    }
+
+    impl ParaswapQuote {
+        /// Least token_out a SELL may return, or most token_in a BUY may spend,
+        /// within PARASWAP_SLIPPAGE_BPS of the quote.
+        pub fn limit_with_slippage(&self) -> BigInt {
+            let bps = BigInt::from(CONFIG.slippage_bps);
+            match self.side {
+                Side::Sell => &self.amount * (BigInt::from(10_000) - bps) / 10_000,
+                Side::Buy => &self.amount * (BigInt::from(10_000) + bps) / 10_000,
+            }
+        }
+    }
+
+    lazy_static! {
+        static ref CONFIG: ParaswapConfig = ParaswapConfig::from_env();
+
+        static ref CLIENT: Client = Client::builder()
+            .connect_timeout(Duration::from_secs(2))
+            .timeout(Duration::from_secs(
+                env::var("PARASWAP_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|secs| secs.parse::<u64>().ok())
+                    .unwrap_or(5),
+            ))
+            .build()
+            .expect("Failed to build Paraswap client");
+
+        // <(token_in, token_out, amount_in), quote> SELL quotes we bid with, so the
+        // swap at execution follows the same route
+        static ref BID_QUOTES: Arc<RwLock<HashMap<(Address, Address, BigInt), ParaswapQuote>>> = {
+            let m = HashMap::new();
+            Arc::new(RwLock::new(m))
+        };
+    }
+
+    // Paraswap refuses to build transactions for stale price routes
+    const QUOTE_MAX_AGE: Duration = Duration::from_secs(60);
+
+    // Paraswap answers errors as {"error": "..."}, sometimes with a 200
+    async fn paraswap_response<T: for<'de> Deserialize<'de>>(
+        response: reqwest::Response,
+    ) -> Result<T> {
+        let status = response.status();
+        let body = response.json::<Value>().await?;
+
+        if let Some(error) = body.get("error") {
+            return Err(Error::ParaswapApi(
+                error.as_str().map(String::from).unwrap_or_else(|| error.to_string()),
+            ));
+        }
+        if !status.is_success() {
+            return Err(Error::ParaswapApi(format!("{status}: {body}")));
+        }
+
+        Ok(serde_json::from_value(body)?)
+    }
+
+    /// `/prices` only, this is all bidding needs.
+    pub async fn price(params: &ParaswapParams) -> Result<ParaswapQuote> {
+        let url = format!("{}/prices", paraswap_api_url());
+
+        let mut query = vec![
+            ("srcToken", format!("{:?}", params.token_in)),
+            ("srcDecimals", params.token0_decimals.to_string()),
+            ("destToken", format!("{:?}", params.token_out)),
+            ("destDecimals", params.token1_decimals.to_string()),
+            ("amount", params.amount_in.to_string()),
+            ("side", params.side.to_string()),
+            ("network", params.chain_id.to_string()),
+            ("version", "6.2".to_string()),
+            ("userAddress", format!("{:?}", params.wallet_address)),
+            ("partner", CONFIG.partner.clone()),
+        ];
+        if let Some(partner_address) = CONFIG.partner_address {
+            query.push(("partnerAddress", format!("{:?}", partner_address)));
+        }
+        if let Some(partner_fee_bps) = CONFIG.partner_fee_bps {
+            query.push(("partnerFeeBps", partner_fee_bps.to_string()));
+        }
+
+        let response = CLIENT.get(url).query(&query).send().await?;
+        let price_route = paraswap_response::<PricesResponse>(response)
+            .await?
+            .price_route;
+
+        let amount = match params.side {
+            Side::Sell => price_route.dest_amount.clone(),
+            Side::Buy => price_route.src_amount.clone(),
+        };
+
+        Ok(ParaswapQuote {
+            side: params.side,
+            amount,
+            price_impact_pct: price_route.price_impact_pct(),
+            price_route,
+            quoted_at: Instant::now(),
+        })
+    }
+
+    /// `/transactions` for a quote from `price`. `limit` is the least token_out
+    /// a SELL must return, or the most token_in a BUY may spend.
+    pub async fn build_transaction(
+        params: &ParaswapParams,
+        quote: &ParaswapQuote,
+        limit: &BigInt,
+    ) -> Result<(String, Address)> {
+        let url = format!(
+            "{}/transactions/{}?ignoreChecks=true&ignoreGasEstimate=true",
+            paraswap_api_url(),
+            params.chain_id
+        );
+
+        let (src_amount, dest_amount) = match quote.side {
+            Side::Sell => (quote.price_route.src_amount.clone(), limit.clone()),
+            Side::Buy => (limit.clone(), quote.price_route.dest_amount.clone()),
+        };
+
+        let request = TransactionRequest {
+            src_token: format!("{:?}", params.token_in),
+            src_decimals: params.token0_decimals,
+            dest_token: format!("{:?}", params.token_out),
+            dest_decimals: params.token1_decimals,
+            src_amount,
+            dest_amount,
+            price_route: &quote.price_route,
+            user_address: format!("{:?}", params.wallet_address),
+            tx_origin: format!("{:?}", params.receiver_address),
+            partner: &CONFIG.partner,
+            partner_address: CONFIG.partner_address.map(|address| format!("{:?}", address)),
+            partner_fee_bps: CONFIG.partner_fee_bps,
+        };
+
+        let response = CLIENT.post(url).json(&request).send().await?;
+        let transaction = paraswap_response::<TransactionResponse>(response).await?;
+
+        Ok((transaction.data, transaction.to))
+    }
+
+    /// Quote and calldata of a swap, within PARASWAP_SLIPPAGE_BPS of the quote.
     pub async fn simulate_swap_paraswap(
         params: ParaswapParams,
-    ) -> Result<(BigInt, String, Address), String> {
-        let mut res_amount = BigInt::from(0);
-        let mut res_data = String::from("None");
-        let mut res_to = Address::zero();
-
-        let url = format!("https://apiv5.paraswap.io/prices?srcToken=0x{:x}&srcDecimals={}&destToken=0x{:x}&destDecimals={}&amount={}&side={}&network={}&maxImpact=10",
-            params.token_in, params.token0_decimals, params.token_out, params.token1_decimals, params.amount_in, params.side, params.chain_id);
-
-        let res = params
-            .client_aggregator
-            .get(url)
-            .send()
-            .await
-            .map_err(|err| err.to_string())?;
-
-        let body = res.text().await.map_err(|err| err.to_string())?;
-        let json_value = serde_json::from_str::<Value>(&body).map_err(|err| err.to_string())?;
-
-        match json_value.get("priceRoute") {
-            Some(json) => {
-                let (amount_in, amount_out, mode) = if params.side == "SELL".to_string() {
-                    (params.amount_in.clone(), BigInt::from(1), "destAmount")
-                } else {
-                    let agg_amount = json
-                        .get("srcAmount")
-                        .ok_or("Failed to get destination srcAmount")?
-                        .to_string()
-                        .trim_matches('"')
-                        .to_string();
-                    (
-                        BigInt::from_str(&agg_amount).unwrap() * BigInt::from(2),
-                        params.amount_in.clone(),
-                        "srcAmount",
-                    )
-                };
-
-                let dest_amount = json.get(mode).ok_or("Failed to get destination amount")?;
-
-                res_amount =
-                    BigInt::from_str(&format!("{}", dest_amount.to_string().trim_matches('"')))
-                        .map_err(|err| err.to_string())?;
-
-                let url = format!(
-                    "https://apiv5.paraswap.io/transactions/{}?gasPrice=50000000000&ignoreChecks=true&ignoreGasEstimate=true&onlyParams=false", params.chain_id
-                );
-
-                let body_0 = serde_json::json!({
-                    "srcToken": format!("0x{:x}", params.token_in),
-                    "destToken": format!("0x{:x}", params.token_out),
-                    "srcAmount": format!("{}", amount_in),
-                    "destAmount": format!("{}", amount_out),
-                    "priceRoute": json,
-                    "userAddress": format!("0x{:x}", params.wallet_address),
-                    "txOrigin": format!("0x{:x}", params.receiver_address),
-                    //"receiver": format!("0x{:x}", *MY_SC),
-                    "partner": "paraswap.io",
-                    "srcDecimals": params.token0_decimals,
-                    "destDecimals": params.token1_decimals
-                });
-
-                let res = params
-                    .client_aggregator
-                    .post(url)
-                    .json(&body_0)
-                    .send()
-                    .await
-                    .map_err(|err| err.to_string())?;
-
-                let body = res.text().await.map_err(|err| err.to_string())?;
-
-                let json_value = serde_json::from_str::<serde_json::Value>(&body)
-                    .map_err(|err| err.to_string())?;
-
-                match json_value.get("to") {
-                    Some(address) => {
-                        res_to =
-                            Address::from_str(address.as_str().ok_or("Failed to get address")?)
-                                .map_err(|err| err.to_string())?;
-                        let data = json_value.get("data").ok_or("Failed to get data")?;
-                        res_data = format!("{}", data.to_string().trim_matches('"'));
-                    }
-                    None => {
-                        println!(
-                            "Failed getting calldata in Paraswap (weird): {:#}",
-                            json_value
-                        );
-                    }
-                }
-            }
-            None => {
-                println!(
-                    "Failed getting price in Paraswap (maybe token doesn't exist): {:#}",
-                    body
-                );
+    ) -> Result<(BigInt, String, Address, f64)> {
+        let quote = price(&params).await?;
+        let (res_data, res_to) =
+            build_transaction(&params, &quote, &quote.limit_with_slippage()).await?;
+
+        Ok((quote.amount, res_data, res_to, quote.price_impact_pct))
+    }
+
+    /// Keeps the SELL quote a bid was based on.
+    pub async fn remember_bid_quote(params: &ParaswapParams, quote: &ParaswapQuote) {
+        let mut quotes = BID_QUOTES.write().await;
+        quotes.retain(|_, quote| quote.quoted_at.elapsed() < QUOTE_MAX_AGE);
+        quotes.insert(
+            (params.token_in, params.token_out, params.amount_in.clone()),
+            quote.clone(),
+        );
+    }
+
+    /// The quote we bid with for this swap, if it is still recent enough to build.
+    pub async fn take_bid_quote(
+        token_in: Address,
+        token_out: Address,
+        amount_in: &BigInt,
+    ) -> Option<ParaswapQuote> {
+        let mut quotes = BID_QUOTES.write().await;
+        quotes
+            .remove(&(token_in, token_out, amount_in.clone()))
+            .filter(|quote| quote.quoted_at.elapsed() < QUOTE_MAX_AGE)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::mock_routers::{Fixtures, MockRouters, ROUTER_ENV};
+        use serde_json::json;
+
+        const USDT: &str = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+        const DAI: &str = "0x6b175474e89094c44da98b954eedeac495271d0f";
+
+        fn params(amount_in: u64) -> ParaswapParams {
+            ParaswapParams {
+                side: Side::Sell,
+                chain_id: 1,
+                amount_in: BigInt::from(amount_in),
+                token_in: USDT.parse().unwrap(),
+                token_out: DAI.parse().unwrap(),
+                token0_decimals: 6,
+                token1_decimals: 18,
+                wallet_address: Address::zero(),
+                receiver_address: Address::zero(),
             }
         }
 
-        Ok((res_amount, res_data, res_to))
+        fn start(price_impact_pct: f64) -> MockRouters {
+            let mock = MockRouters::start(Fixtures {
+                price_impact_pct,
+                ..Fixtures::default()
+            })
+            .unwrap();
+            env::set_var("PARASWAP_API_URL", mock.url());
+            mock
+        }
+
+        #[tokio::test]
+        async fn price_converts_decimals_at_the_route_rate() {
+            let _env = ROUTER_ENV.lock().await;
+            let mock = start(2.0);
+            mock.set_rate(USDT, DAI, 0.5).await;
+
+            let quote = price(&params(1_000_000_000)).await.unwrap();
+            assert_eq!(quote.amount, BigInt::from(500u64) * BigInt::from(10u64).pow(18));
+            assert!((quote.price_impact_pct - 2.0).abs() < 1e-9);
+            // PARASWAP_SLIPPAGE_BPS defaults to 100
+            assert_eq!(
+                quote.limit_with_slippage(),
+                BigInt::from(495u64) * BigInt::from(10u64).pow(18)
+            );
+        }
+
+        #[tokio::test]
+        async fn transaction_gets_the_limit_and_the_route_untouched() {
+            let _env = ROUTER_ENV.lock().await;
+            let mock = start(0.0);
+            // a route without srcUSD / destUSD, they must not come back as null
+            mock.set_response(
+                "GET /prices",
+                200,
+                json!({ "priceRoute": {
+                    "srcAmount": "1000000",
+                    "destAmount": "1000000000000000000",
+                    "side": "SELL",
+                    "bestRoute": [],
+                }}),
+            )
+            .await;
+
+            let (amount, _, to, price_impact_pct) =
+                simulate_swap_paraswap(params(1_000_000)).await.unwrap();
+            assert_eq!(amount, BigInt::from(10u64).pow(18));
+            assert_eq!(price_impact_pct, 0f64);
+            assert_eq!(
+                format!("{:?}", to),
+                "0x6a000f20005980200259b80c5102003040001068"
+            );
+
+            let requests = mock.requests().await;
+            let transaction = &requests[1]["body"];
+            assert_eq!(transaction["destAmount"], "990000000000000000");
+            assert_eq!(
+                transaction["priceRoute"],
+                json!({
+                    "srcAmount": "1000000",
+                    "destAmount": "1000000000000000000",
+                    "side": "SELL",
+                    "bestRoute": [],
+                })
+            );
+        }
+
+        #[tokio::test]
+        async fn api_errors_are_surfaced() {
+            let _env = ROUTER_ENV.lock().await;
+            let mock = start(0.0);
+
+            // Paraswap reports some errors with a 200
+            mock.set_response("GET /prices", 200, json!({ "error": "No routes found" }))
+                .await;
+            match price(&params(1_000_000)).await {
+                Err(Error::ParaswapApi(e)) => assert_eq!(e, "No routes found"),
+                other => panic!("expected a Paraswap error, got {other:?}"),
+            }
+
+            mock.set_response("GET /prices", 503, json!({ "message": "down" }))
+                .await;
+            match price(&params(1_000_000)).await {
+                Err(Error::ParaswapApi(e)) => assert!(e.starts_with("503"), "{e}"),
+                other => panic!("expected a Paraswap error, got {other:?}"),
+            }
+
+            // a quote we can't build a transaction for is not a swap
+            mock.set_response("GET /prices", 200, json!({ "priceRoute": { "srcAmount": "1" } }))
+                .await;
+            assert!(matches!(
+                simulate_swap_paraswap(params(1_000_000)).await,
+                Err(Error::SerdeJson(_))
+            ));
+        }
     }
 }
@@ -0,0 +1,709 @@
+pub mod solana_amm_router {
+    use crate::rpc::svm_broadcast;
+    use crate::signer::SolanaSigner;
+    use lazy_static::lazy_static;
+    use num_bigint::BigUint;
+    use num_traits::{One, ToPrimitive};
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+    use solana_sdk::hash::hash;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Signer;
+    use solana_sdk::transaction::Transaction;
+    use spl_associated_token_account::get_associated_token_address_with_program_id;
+    use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+    use std::env;
+    use std::str::FromStr;
+
+    pub const WHIRLPOOL_PROGRAM: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+    pub const RAYDIUM_CPMM_PROGRAM: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+
+    // ticks per Whirlpool tick array
+    const TICK_ARRAY_SIZE: i32 = 88;
+    // initialized (1) + liquidity_net (16) + liquidity_gross (16) + fee growths (32) + rewards (48)
+    const TICK_SIZE: usize = 113;
+    const MIN_SQRT_PRICE: u128 = 4295048016;
+    const MAX_SQRT_PRICE: u128 = 79226673515401279992447579055;
+
+    lazy_static! {
+        // SOLANA_AMM_POOLS="whirlpool:<pool>,raydium_cpmm:<pool>", the pools we
+        // can quote and swap on without Jupiter
+        static ref POOLS: Vec<(String, Pubkey)> = env::var("SOLANA_AMM_POOLS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|pool| !pool.trim().is_empty())
+            .filter_map(|pool| {
+                let (kind, address) = pool.trim().split_once(':')?;
+                match Pubkey::from_str(address) {
+                    Ok(address) => Some((kind.to_lowercase(), address)),
+                    Err(_) => {
+                        eprintln!("Invalid SOLANA_AMM_POOLS entry: {pool}");
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        static ref SLIPPAGE_BPS: u64 = env::var("SOLANA_AMM_SLIPPAGE_BPS")
+            .ok()
+            .and_then(|bps| bps.parse().ok())
+            .unwrap_or(100);
+
+        // sqrt(1.0001)^-(2^i) as Q128.128, the factors of a tick's sqrt price
+        static ref TICK_FACTORS: Vec<BigUint> = [
+            "fffcb933bd6fad37aa2d162d1a594001",
+            "fff97272373d413259a46990580e213a",
+            "fff2e50f5f656932ef12357cf3c7fdcc",
+            "ffe5caca7e10e4e61c3624eaa0941cd0",
+            "ffcb9843d60f6159c9db58835c926644",
+            "ff973b41fa98c081472e6896dfb254c0",
+            "ff2ea16466c96a3843ec78b326b52861",
+            "fe5dee046a99a2a811c461f1969c3053",
+            "fcbe86c7900a88aedcffc83b479aa3a4",
+            "f987a7253ac413176f2b074cf7815e54",
+            "f3392b0822b70005940c7a398e4b70f3",
+            "e7159475a2c29b7443b29c7fa6e889d9",
+            "d097f3bdfd2022b8845ad8f792aa5825",
+            "a9f746462d870fdf8a65dc1f90e061e5",
+            "70d869a156d2a1b890bb3df62baf32f7",
+            "31be135f97d08fd981231505542fcfa6",
+            "9aa508b5b7a84e1c677de54f3e99bc9",
+            "5d6af8dedb81196699c329225ee604",
+            "2216e584f5fa1ea926041bedfe98",
+            "48a170391f7dc42444e8fa2",
+        ]
+        .iter()
+        .map(|factor| BigUint::parse_bytes(factor.as_bytes(), 16).unwrap())
+        .collect();
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Whirlpool {
+        pub address: Pubkey,
+        pub tick_spacing: u16,
+        // hundredths of a bps
+        pub fee_rate: u16,
+        pub liquidity: u128,
+        pub sqrt_price: u128,
+        pub tick_current_index: i32,
+        pub mint_a: Pubkey,
+        pub vault_a: Pubkey,
+        pub mint_b: Pubkey,
+        pub vault_b: Pubkey,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct CpmmPool {
+        pub address: Pubkey,
+        pub amm_config: Pubkey,
+        pub vault_0: Pubkey,
+        pub vault_1: Pubkey,
+        pub mint_0: Pubkey,
+        pub mint_1: Pubkey,
+        pub token_0_program: Pubkey,
+        pub token_1_program: Pubkey,
+        pub observation: Pubkey,
+        // protocol + fund fees sitting in the vaults, not part of the reserves
+        pub fees_0: u64,
+        pub fees_1: u64,
+        // parts per million
+        pub trade_fee_rate: u64,
+    }
+
+    #[derive(Debug, Clone)]
+    pub enum AmmPool {
+        Whirlpool(Whirlpool),
+        RaydiumCpmm(CpmmPool),
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct AmmQuote {
+        pub pool: AmmPool,
+        pub amount_out: u64,
+        // Whirlpool tick arrays the swap walks through
+        pub tick_arrays: Vec<Pubkey>,
+    }
+
+    impl AmmQuote {
+        /// Least token_out the swap may return, within SOLANA_AMM_SLIPPAGE_BPS of the quote.
+        pub fn min_amount_out(&self) -> u64 {
+            (self.amount_out as u128 * (10_000 - (*SLIPPAGE_BPS).min(10_000)) as u128 / 10_000) as u64
+        }
+    }
+
+    fn pubkey_at(data: &[u8], offset: usize) -> Result<Pubkey, String> {
+        data.get(offset..offset + 32)
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .map(Pubkey::new_from_array)
+            .ok_or_else(|| format!("Account too short for a pubkey at {offset}"))
+    }
+
+    fn bytes_at<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], String> {
+        data.get(offset..offset + N)
+            .and_then(|bytes| <[u8; N]>::try_from(bytes).ok())
+            .ok_or_else(|| format!("Account too short for {N} bytes at {offset}"))
+    }
+
+    fn anchor_discriminator(name: &str) -> [u8; 8] {
+        let mut discriminator = [0u8; 8];
+        discriminator.copy_from_slice(&hash(format!("global:{name}").as_bytes()).to_bytes()[..8]);
+        discriminator
+    }
+
+    async fn account_data(client: &RpcClient, address: &Pubkey) -> Result<Vec<u8>, String> {
+        client
+            .get_account_data(address)
+            .await
+            .map_err(|e| format!("Failed to load account {address}: {e}"))
+    }
+
+    // amount of an SPL token account
+    async fn token_amount(client: &RpcClient, address: &Pubkey) -> Result<u64, String> {
+        let data = account_data(client, address).await?;
+        Ok(u64::from_le_bytes(bytes_at(&data, 64)?))
+    }
+
+    pub async fn load_whirlpool(client: &RpcClient, address: Pubkey) -> Result<Whirlpool, String> {
+        let data = account_data(client, &address).await?;
+
+        Ok(Whirlpool {
+            address,
+            tick_spacing: u16::from_le_bytes(bytes_at(&data, 41)?),
+            fee_rate: u16::from_le_bytes(bytes_at(&data, 45)?),
+            liquidity: u128::from_le_bytes(bytes_at(&data, 49)?),
+            sqrt_price: u128::from_le_bytes(bytes_at(&data, 65)?),
+            tick_current_index: i32::from_le_bytes(bytes_at(&data, 81)?),
+            mint_a: pubkey_at(&data, 101)?,
+            vault_a: pubkey_at(&data, 133)?,
+            mint_b: pubkey_at(&data, 181)?,
+            vault_b: pubkey_at(&data, 213)?,
+        })
+    }
+
+    pub async fn load_cpmm_pool(client: &RpcClient, address: Pubkey) -> Result<CpmmPool, String> {
+        let data = account_data(client, &address).await?;
+        let fee = |offset: usize| -> Result<u64, String> {
+            Ok(u64::from_le_bytes(bytes_at(&data, offset)?))
+        };
+
+        let amm_config = pubkey_at(&data, 8)?;
+        let config = account_data(client, &amm_config).await?;
+
+        Ok(CpmmPool {
+            address,
+            amm_config,
+            vault_0: pubkey_at(&data, 72)?,
+            vault_1: pubkey_at(&data, 104)?,
+            mint_0: pubkey_at(&data, 168)?,
+            mint_1: pubkey_at(&data, 200)?,
+            token_0_program: pubkey_at(&data, 232)?,
+            token_1_program: pubkey_at(&data, 264)?,
+            observation: pubkey_at(&data, 296)?,
+            fees_0: fee(341)? + fee(357)?,
+            fees_1: fee(349)? + fee(365)?,
+            trade_fee_rate: u64::from_le_bytes(bytes_at(&config, 12)?),
+        })
+    }
+
+    fn tick_array_start(tick: i32, tick_spacing: u16) -> i32 {
+        let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+        tick.div_euclid(ticks_in_array) * ticks_in_array
+    }
+
+    fn tick_array_address(whirlpool: &Pubkey, start: i32) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"tick_array", whirlpool.as_ref(), start.to_string().as_bytes()],
+            &Pubkey::from_str(WHIRLPOOL_PROGRAM).unwrap(),
+        )
+        .0
+    }
+
+    // sqrt price of a tick as Q64.64, like the Uniswap V3 TickMath it is derived from
+    fn sqrt_price_at_tick(tick: i32) -> u128 {
+        let abs_tick = tick.unsigned_abs();
+        let mut ratio = BigUint::one() << 128;
+        for (bit, factor) in TICK_FACTORS.iter().enumerate() {
+            if abs_tick & (1 << bit) != 0 {
+                ratio = (ratio * factor) >> 128;
+            }
+        }
+        if tick > 0 {
+            ratio = ((BigUint::one() << 256) - 1u32) / ratio;
+        }
+        (ratio >> 64).to_u128().unwrap_or(MAX_SQRT_PRICE)
+    }
+
+    fn div(numerator: BigUint, denominator: BigUint, round_up: bool) -> BigUint {
+        if round_up {
+            (numerator + &denominator - 1u32) / denominator
+        } else {
+            numerator / denominator
+        }
+    }
+
+    fn to_u128(amount: BigUint) -> Result<u128, String> {
+        amount.to_u128().ok_or_else(|| format!("Whirlpool amount {amount} overflows u128"))
+    }
+
+    // token A between two sqrt prices: L * (upper - lower) / (upper * lower)
+    fn delta_a(liquidity: u128, price_0: u128, price_1: u128, round_up: bool) -> Result<u128, String> {
+        let (lower, upper) = (price_0.min(price_1), price_0.max(price_1));
+        if lower == 0 {
+            return Err("Whirlpool sqrt price is zero".to_string());
+        }
+        to_u128(div(
+            (BigUint::from(liquidity) * (upper - lower)) << 64,
+            BigUint::from(lower) * upper,
+            round_up,
+        ))
+    }
+
+    // token B between two sqrt prices: L * (upper - lower)
+    fn delta_b(liquidity: u128, price_0: u128, price_1: u128, round_up: bool) -> Result<u128, String> {
+        let (lower, upper) = (price_0.min(price_1), price_0.max(price_1));
+        to_u128(div(
+            BigUint::from(liquidity) * (upper - lower),
+            BigUint::one() << 64,
+            round_up,
+        ))
+    }
+
+    // sqrt price after `amount_in` (after fees) goes into the pool, rounded
+    // against the swapper like the program does
+    fn next_sqrt_price(
+        liquidity: u128,
+        sqrt_price: u128,
+        amount_in: u128,
+        a_to_b: bool,
+    ) -> Result<u128, String> {
+        if a_to_b {
+            let liquidity = BigUint::from(liquidity) << 64;
+            let denominator = &liquidity + BigUint::from(amount_in) * sqrt_price;
+            to_u128(div(liquidity * sqrt_price, denominator, true))
+        } else {
+            let delta = (BigUint::from(amount_in) << 64) / liquidity;
+            to_u128(delta + sqrt_price)
+        }
+    }
+
+    /// Exact-in output of a Whirlpool swap, walking the initialized ticks of the
+    /// three tick arrays a swap can touch. Prices are Q64.64 like on chain and
+    /// amounts are rounded against us, though the fee is taken once up front
+    /// rather than per tick crossed.
+    async fn quote_whirlpool(
+        client: &RpcClient,
+        pool: &Whirlpool,
+        a_to_b: bool,
+        amount_in: u64,
+    ) -> Result<(u64, Vec<Pubkey>), String> {
+        let ticks_in_array = TICK_ARRAY_SIZE * pool.tick_spacing as i32;
+        let first = tick_array_start(pool.tick_current_index, pool.tick_spacing);
+        let starts: Vec<i32> = (0..3)
+            .map(|i| if a_to_b { first - i * ticks_in_array } else { first + i * ticks_in_array })
+            .collect();
+
+        // (tick, liquidity_net) of the initialized ticks on the way
+        let mut tick_arrays = Vec::new();
+        let mut ticks: Vec<(i32, i128)> = Vec::new();
+        let mut last_start = first;
+        for start in starts {
+            let address = tick_array_address(&pool.address, start);
+            let data = match client.get_account_data(&address).await {
+                Ok(data) => data,
+                // uninitialized array, the swap can't go past it
+                Err(_) => break,
+            };
+            tick_arrays.push(address);
+            last_start = start;
+
+            for i in 0..TICK_ARRAY_SIZE as usize {
+                let offset = 12 + i * TICK_SIZE;
+                if data.get(offset) != Some(&1) {
+                    continue;
+                }
+                let tick = start + i as i32 * pool.tick_spacing as i32;
+                let liquidity_net = i128::from_le_bytes(bytes_at(&data, offset + 1)?);
+                ticks.push((tick, liquidity_net));
+            }
+        }
+        if tick_arrays.is_empty() {
+            return Err(format!("No tick arrays for whirlpool {}", pool.address));
+        }
+        // the program wants 3 tick arrays, repeating the last one is accepted
+        while tick_arrays.len() < 3 {
+            tick_arrays.push(*tick_arrays.last().unwrap());
+        }
+
+        if a_to_b {
+            ticks.retain(|(tick, _)| *tick <= pool.tick_current_index);
+            ticks.sort_by(|a, b| b.0.cmp(&a.0));
+        } else {
+            ticks.retain(|(tick, _)| *tick > pool.tick_current_index);
+            ticks.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+
+        let fee = (amount_in as u128 * pool.fee_rate as u128 + 999_999) / 1_000_000;
+        let mut remaining = amount_in as u128 - fee.min(amount_in as u128);
+        let mut liquidity = pool.liquidity;
+        let mut sqrt_price = pool.sqrt_price;
+        let mut amount_out = 0u128;
+
+        // (amount in to reach `target`, rounded up, and amount out, rounded down)
+        let step = |liquidity: u128, sqrt_price: u128, target: u128| -> Result<(u128, u128), String> {
+            if a_to_b {
+                Ok((
+                    delta_a(liquidity, sqrt_price, target, true)?,
+                    delta_b(liquidity, sqrt_price, target, false)?,
+                ))
+            } else {
+                Ok((
+                    delta_b(liquidity, sqrt_price, target, true)?,
+                    delta_a(liquidity, sqrt_price, target, false)?,
+                ))
+            }
+        };
+
+        for (tick, liquidity_net) in ticks {
+            let target = sqrt_price_at_tick(tick);
+            let (max_in, out) = step(liquidity, sqrt_price, target)?;
+
+            if remaining <= max_in {
+                break;
+            }
+
+            amount_out += out;
+            remaining -= max_in;
+            sqrt_price = target;
+            let liquidity_net = if a_to_b { -liquidity_net } else { liquidity_net };
+            liquidity = liquidity
+                .checked_add_signed(liquidity_net)
+                .filter(|liquidity| *liquidity > 0)
+                .ok_or_else(|| format!("Whirlpool {} runs out of liquidity", pool.address))?;
+        }
+
+        // what is left fits before the next initialized tick we know of, unless
+        // it goes past the tick arrays we loaded
+        let next_sqrt_price = next_sqrt_price(liquidity, sqrt_price, remaining, a_to_b)?;
+        let past_tick_arrays = if a_to_b {
+            next_sqrt_price < sqrt_price_at_tick(last_start)
+        } else {
+            next_sqrt_price > sqrt_price_at_tick(last_start + ticks_in_array)
+        };
+        if past_tick_arrays {
+            return Err(format!("Swap goes past the tick arrays of whirlpool {}", pool.address));
+        }
+        amount_out += step(liquidity, sqrt_price, next_sqrt_price)?.1;
+
+        let amount_out = u64::try_from(amount_out)
+            .map_err(|_| format!("Whirlpool {} quote overflows u64", pool.address))?;
+        Ok((amount_out, tick_arrays))
+    }
+
+    // constant product after the trade fee, as the program rounds it
+    fn quote_cpmm(pool: &CpmmPool, reserve_in: u64, reserve_out: u64, amount_in: u64) -> u64 {
+        let fee = (amount_in as u128 * pool.trade_fee_rate as u128 + 999_999) / 1_000_000;
+        let amount_in = amount_in as u128 - fee.min(amount_in as u128);
+        (amount_in * reserve_out as u128 / (reserve_in as u128 + amount_in)) as u64
+    }
+
+    async fn quote_pool(
+        client: &RpcClient,
+        kind: &str,
+        address: Pubkey,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_in: u64,
+    ) -> Result<Option<AmmQuote>, String> {
+        match kind {
+            "whirlpool" => {
+                let pool = load_whirlpool(client, address).await?;
+                let a_to_b = if (pool.mint_a, pool.mint_b) == (*token_in, *token_out) {
+                    true
+                } else if (pool.mint_b, pool.mint_a) == (*token_in, *token_out) {
+                    false
+                } else {
+                    return Ok(None);
+                };
+
+                let (amount_out, tick_arrays) =
+                    quote_whirlpool(client, &pool, a_to_b, amount_in).await?;
+                Ok(Some(AmmQuote {
+                    pool: AmmPool::Whirlpool(pool),
+                    amount_out,
+                    tick_arrays,
+                }))
+            }
+            "raydium_cpmm" => {
+                let pool = load_cpmm_pool(client, address).await?;
+                let (vault_in, fees_in, vault_out, fees_out) =
+                    if (pool.mint_0, pool.mint_1) == (*token_in, *token_out) {
+                        (pool.vault_0, pool.fees_0, pool.vault_1, pool.fees_1)
+                    } else if (pool.mint_1, pool.mint_0) == (*token_in, *token_out) {
+                        (pool.vault_1, pool.fees_1, pool.vault_0, pool.fees_0)
+                    } else {
+                        return Ok(None);
+                    };
+
+                let reserve_in = token_amount(client, &vault_in).await?.saturating_sub(fees_in);
+                let reserve_out = token_amount(client, &vault_out).await?.saturating_sub(fees_out);
+
+                Ok(Some(AmmQuote {
+                    amount_out: quote_cpmm(&pool, reserve_in, reserve_out, amount_in),
+                    pool: AmmPool::RaydiumCpmm(pool),
+                    tick_arrays: Vec::new(),
+                }))
+            }
+            _ => Err(format!("Unknown AMM {kind}")),
+        }
+    }
+
+    /// Best exact-in quote among the SOLANA_AMM_POOLS trading token_in -> token_out.
+    pub async fn quote(
+        client: &RpcClient,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_in: u64,
+    ) -> Result<AmmQuote, String> {
+        let mut best: Option<AmmQuote> = None;
+
+        for (kind, address) in POOLS.iter() {
+            match quote_pool(client, kind, *address, token_in, token_out, amount_in).await {
+                Ok(Some(quote)) => {
+                    if best.as_ref().map_or(true, |best| quote.amount_out > best.amount_out) {
+                        best = Some(quote);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Failed to quote {kind} pool {address}: {e}"),
+            }
+        }
+
+        best.filter(|quote| quote.amount_out > 0)
+            .ok_or_else(|| format!("No AMM pool for {token_in} -> {token_out}"))
+    }
+
+    /// Instructions swapping `amount_in` token_in for at least `min_amount_out`
+    /// token_out along `quote`, creating the user's token_out account if needed.
+    pub fn swap_instructions(
+        quote: &AmmQuote,
+        user: &Pubkey,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Vec<Instruction> {
+        match &quote.pool {
+            AmmPool::Whirlpool(pool) => {
+                let program = Pubkey::from_str(WHIRLPOOL_PROGRAM).unwrap();
+                let a_to_b = pool.mint_a == *token_in;
+                let oracle =
+                    Pubkey::find_program_address(&[b"oracle", pool.address.as_ref()], &program).0;
+
+                let mut data = anchor_discriminator("swap").to_vec();
+                data.extend_from_slice(&amount_in.to_le_bytes());
+                data.extend_from_slice(&min_amount_out.to_le_bytes());
+                data.extend_from_slice(
+                    &(if a_to_b { MIN_SQRT_PRICE } else { MAX_SQRT_PRICE }).to_le_bytes(),
+                );
+                data.push(1); // amount_specified_is_input
+                data.push(a_to_b as u8);
+
+                let mut accounts = vec![
+                    AccountMeta::new_readonly(spl_token::ID, false),
+                    AccountMeta::new_readonly(*user, true),
+                    AccountMeta::new(pool.address, false),
+                    AccountMeta::new(
+                        get_associated_token_address_with_program_id(user, &pool.mint_a, &spl_token::ID),
+                        false,
+                    ),
+                    AccountMeta::new(pool.vault_a, false),
+                    AccountMeta::new(
+                        get_associated_token_address_with_program_id(user, &pool.mint_b, &spl_token::ID),
+                        false,
+                    ),
+                    AccountMeta::new(pool.vault_b, false),
+                ];
+                accounts.extend(quote.tick_arrays.iter().map(|address| AccountMeta::new(*address, false)));
+                accounts.push(AccountMeta::new_readonly(oracle, false));
+
+                vec![
+                    create_associated_token_account_idempotent(user, user, token_out, &spl_token::ID),
+                    Instruction {
+                        program_id: program,
+                        accounts,
+                        data,
+                    },
+                ]
+            }
+            AmmPool::RaydiumCpmm(pool) => {
+                let program = Pubkey::from_str(RAYDIUM_CPMM_PROGRAM).unwrap();
+                let authority =
+                    Pubkey::find_program_address(&[b"vault_and_lp_mint_auth_seed"], &program).0;
+                let (vault_in, program_in, vault_out, program_out) = if pool.mint_0 == *token_in {
+                    (pool.vault_0, pool.token_0_program, pool.vault_1, pool.token_1_program)
+                } else {
+                    (pool.vault_1, pool.token_1_program, pool.vault_0, pool.token_0_program)
+                };
+
+                let mut data = anchor_discriminator("swap_base_input").to_vec();
+                data.extend_from_slice(&amount_in.to_le_bytes());
+                data.extend_from_slice(&min_amount_out.to_le_bytes());
+
+                vec![
+                    create_associated_token_account_idempotent(user, user, token_out, &program_out),
+                    Instruction {
+                        program_id: program,
+                        accounts: vec![
+                            AccountMeta::new_readonly(*user, true),
+                            AccountMeta::new_readonly(authority, false),
+                            AccountMeta::new_readonly(pool.amm_config, false),
+                            AccountMeta::new(pool.address, false),
+                            AccountMeta::new(
+                                get_associated_token_address_with_program_id(user, token_in, &program_in),
+                                false,
+                            ),
+                            AccountMeta::new(
+                                get_associated_token_address_with_program_id(user, token_out, &program_out),
+                                false,
+                            ),
+                            AccountMeta::new(vault_in, false),
+                            AccountMeta::new(vault_out, false),
+                            AccountMeta::new_readonly(program_in, false),
+                            AccountMeta::new_readonly(program_out, false),
+                            AccountMeta::new_readonly(*token_in, false),
+                            AccountMeta::new_readonly(*token_out, false),
+                            AccountMeta::new(pool.observation, false),
+                        ],
+                        data,
+                    },
+                ]
+            }
+        }
+    }
+
+    /// Swaps on the best SOLANA_AMM_POOLS pool, the Jupiter-less counterpart of
+    /// `jupiter_swap` for exact-in swaps. The swap fails below the quote minus
+    /// SOLANA_AMM_SLIPPAGE_BPS, or below `min_amount_out` when that is higher.
+    pub async fn amm_swap(
+        client: &RpcClient,
+        keypair: &SolanaSigner,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<(), String> {
+        let quote = quote(client, token_in, token_out, amount_in).await?;
+        if quote.amount_out < min_amount_out {
+            return Err(format!(
+                "AMM pools only give {} of the {} needed",
+                quote.amount_out, min_amount_out
+            ));
+        }
+        let min_amount_out = quote.min_amount_out().max(min_amount_out);
+
+        let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(400_000)];
+        instructions.extend(swap_instructions(
+            &quote,
+            &keypair.pubkey(),
+            token_in,
+            token_out,
+            amount_in,
+            min_amount_out,
+        ));
+
+        let recent_blockhash = client
+            .get_latest_blockhash()
+            .await
+            .map_err(|e| format!("Failed to get latest blockhash: {}", e))?;
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&keypair.pubkey()),
+            &[keypair],
+            recent_blockhash,
+        );
+
+        let signature = svm_broadcast("solana", &transaction)
+            .await
+            .map_err(|e| format!("AMM swap failed: {}", e))?;
+        client
+            .confirm_transaction_with_spinner(&signature, &recent_blockhash, client.commitment())
+            .await
+            .map_err(|e| format!("AMM swap failed: {}", e))?;
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const ONE: u128 = 1 << 64;
+
+        #[test]
+        fn sqrt_price_at_tick_matches_tick_math() {
+            assert_eq!(sqrt_price_at_tick(0), ONE);
+            // Uniswap TickMath getSqrtRatioAtTick(±1) as Q64.96, 32 bits less precise
+            assert_eq!(sqrt_price_at_tick(1), 79232123823359799118286999568u128 >> 32);
+            assert_eq!(sqrt_price_at_tick(-1), 79224201403219477170569942574u128 >> 32);
+            // the Whirlpool min tick
+            assert_eq!(sqrt_price_at_tick(-443636), MIN_SQRT_PRICE);
+        }
+
+        #[test]
+        fn deltas_round_as_asked() {
+            // sqrt prices 1 and 1.5
+            let (lower, upper) = (ONE, 3 * ONE / 2);
+            assert_eq!(delta_b(1000, lower, upper, false), Ok(500));
+            assert_eq!(delta_b(1000, upper, lower, true), Ok(500));
+            // 1000 * 0.5 / 1.5
+            assert_eq!(delta_a(1000, lower, upper, false), Ok(333));
+            assert_eq!(delta_a(1000, lower, upper, true), Ok(334));
+
+            assert_eq!(delta_b(1000, ONE, ONE + 1, false), Ok(0));
+            assert_eq!(delta_b(1000, ONE, ONE + 1, true), Ok(1));
+            assert!(delta_a(1000, 0, ONE, false).is_err());
+        }
+
+        #[test]
+        fn next_sqrt_price_moves_by_the_amount_in() {
+            // b -> a: 500 / 1000 more sqrt price
+            assert_eq!(next_sqrt_price(1000, ONE, 500, false), Ok(3 * ONE / 2));
+            // a -> b: 1 / (1 + 1000 / 1000)
+            assert_eq!(next_sqrt_price(1000, ONE, 1000, true), Ok(ONE / 2));
+            assert_eq!(delta_a(1000, ONE / 2, ONE, true), Ok(1000));
+
+            // rounded up, never moving further than the amount pays for
+            let next = next_sqrt_price(1000, ONE, 333, true).unwrap();
+            assert_eq!(next, (1000 * ONE + 1332) / 1333);
+            assert!(delta_a(1000, next, ONE, true).unwrap() <= 333);
+        }
+
+        #[test]
+        fn cpmm_quote_takes_the_fee_rounded_up() {
+            let pool = CpmmPool {
+                address: Pubkey::default(),
+                amm_config: Pubkey::default(),
+                vault_0: Pubkey::default(),
+                vault_1: Pubkey::default(),
+                mint_0: Pubkey::default(),
+                mint_1: Pubkey::default(),
+                token_0_program: Pubkey::default(),
+                token_1_program: Pubkey::default(),
+                observation: Pubkey::default(),
+                fees_0: 0,
+                fees_1: 0,
+                // 0.25%
+                trade_fee_rate: 2500,
+            };
+
+            // fee ceil(2.5) = 3, 997 * 2_000_000 / 1_000_997 = 1992.01
+            assert_eq!(quote_cpmm(&pool, 1_000_000, 2_000_000, 1000), 1992);
+            // exactly 1 fee, then 2
+            assert_eq!(quote_cpmm(&pool, 1_000_000, 1_000_000, 400), 398);
+            assert_eq!(quote_cpmm(&pool, 1_000_000, 1_000_000, 401), 398);
+            // the fee eats it all
+            assert_eq!(quote_cpmm(&pool, 1_000_000, 2_000_000, 1), 0);
+        }
+    }
+}
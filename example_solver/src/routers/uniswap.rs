@@ -0,0 +1,191 @@
+pub mod uniswap_router {
+    use ethers::prelude::*;
+    use ethers::providers::{Http, Provider};
+    use lazy_static::lazy_static;
+    use num_bigint::BigInt;
+    use std::env;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    abigen!(
+        QuoterV2,
+        r#"[
+            function quoteExactInputSingle((address,address,uint256,uint24,uint160) params) external returns (uint256, uint160, uint32, uint256)
+        ]"#
+    );
+
+    abigen!(
+        SwapRouter02,
+        r#"[
+            function exactInputSingle((address,address,uint24,address,uint256,uint256,uint160) params) external payable returns (uint256)
+        ]"#
+    );
+
+    abigen!(
+        UniswapV2Router,
+        r#"[
+            function getAmountsOut(uint256 amountIn, address[] path) external view returns (uint256[])
+            function swapExactTokensForTokens(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline) external returns (uint256[])
+        ]"#
+    );
+
+    fn address_from_env(var: &str, default: &str) -> Address {
+        Address::from_str(&env::var(var).unwrap_or_else(|_| default.to_string()))
+            .unwrap_or_else(|_| panic!("{var} must be an address"))
+    }
+
+    lazy_static! {
+        // mainnet deployments by default, override them to point at forks or other V2/V3 clones
+        pub static ref QUOTER_V2: Address =
+            address_from_env("UNISWAP_QUOTER_V2", "0x61fFE014bA17989E743c5F6cB21bF9697530B21e");
+        pub static ref SWAP_ROUTER_02: Address =
+            address_from_env("UNISWAP_SWAP_ROUTER_02", "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45");
+        pub static ref V2_ROUTER: Address =
+            address_from_env("UNISWAP_V2_ROUTER", "0x7a250d5630B4cF539739dF2C5dAcc4c659F2488D");
+        static ref WETH: Address =
+            address_from_env("UNISWAP_WETH", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+
+        static ref FEE_TIERS: Vec<u32> = env::var("UNISWAP_FEE_TIERS")
+            .unwrap_or_else(|_| String::from("100,500,3000,10000"))
+            .split(',')
+            .filter_map(|fee| fee.trim().parse::<u32>().ok())
+            .collect();
+
+        static ref SLIPPAGE_BPS: u32 = env::var("UNISWAP_SLIPPAGE_BPS")
+            .ok()
+            .and_then(|bps| bps.parse().ok())
+            .unwrap_or(100);
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Pool {
+        V2 { path: Vec<Address> },
+        V3 { fee: u32 },
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct UniswapQuote {
+        pub pool: Pool,
+        pub amount_out: BigInt,
+    }
+
+    impl UniswapQuote {
+        /// Contract the swap is sent to, and the solver must approve token_in to.
+        pub fn router(&self) -> Address {
+            match self.pool {
+                Pool::V2 { .. } => *V2_ROUTER,
+                Pool::V3 { .. } => *SWAP_ROUTER_02,
+            }
+        }
+
+        /// Least token_out the swap may return, within UNISWAP_SLIPPAGE_BPS of the quote.
+        pub fn min_amount_out(&self) -> BigInt {
+            &self.amount_out * (10_000 - (*SLIPPAGE_BPS).min(10_000)) / 10_000
+        }
+    }
+
+    fn to_u256(amount: &BigInt) -> Result<U256, String> {
+        U256::from_dec_str(&amount.to_string()).map_err(|e| format!("Invalid amount {amount}: {e}"))
+    }
+
+    fn to_bigint(amount: U256) -> BigInt {
+        BigInt::from_str(&amount.to_string()).unwrap()
+    }
+
+    /// Best exact-in quote among the V3 fee tiers (single hop) and V2 pairs
+    /// (direct or through WETH), read with eth_call only.
+    pub async fn quote(
+        provider: Arc<Provider<Http>>,
+        token_in: Address,
+        token_out: Address,
+        amount_in: &BigInt,
+    ) -> Result<UniswapQuote, String> {
+        let amount = to_u256(amount_in)?;
+        let mut best: Option<UniswapQuote> = None;
+        let mut keep_best = |quote: UniswapQuote| {
+            if best.as_ref().map_or(true, |best| quote.amount_out > best.amount_out) {
+                best = Some(quote);
+            }
+        };
+
+        // the quoter reverts on tiers without a pool or liquidity, those are just skipped
+        let quoter = QuoterV2::new(*QUOTER_V2, provider.clone());
+        for fee in FEE_TIERS.iter() {
+            let call = quoter.quote_exact_input_single((token_in, token_out, amount, *fee, U256::zero()));
+            if let Ok((amount_out, _, _, _)) = call.call().await {
+                keep_best(UniswapQuote {
+                    pool: Pool::V3 { fee: *fee },
+                    amount_out: to_bigint(amount_out),
+                });
+            }
+        }
+
+        let router = UniswapV2Router::new(*V2_ROUTER, provider);
+        let mut paths = vec![vec![token_in, token_out]];
+        if token_in != *WETH && token_out != *WETH {
+            paths.push(vec![token_in, *WETH, token_out]);
+        }
+        for path in paths {
+            if let Ok(amounts) = router.get_amounts_out(amount, path.clone()).call().await {
+                if let Some(amount_out) = amounts.last() {
+                    keep_best(UniswapQuote {
+                        pool: Pool::V2 { path },
+                        amount_out: to_bigint(*amount_out),
+                    });
+                }
+            }
+        }
+
+        best.filter(|quote| quote.amount_out > BigInt::from(0))
+            .ok_or_else(|| format!("No Uniswap pool for {:?} -> {:?}", token_in, token_out))
+    }
+
+    /// Calldata of the swap in `quote`, paying at least `min_amount_out` to
+    /// `recipient`. Returns (calldata, router) like the Paraswap router does.
+    pub fn build_swap(
+        provider: Arc<Provider<Http>>,
+        quote: &UniswapQuote,
+        token_in: Address,
+        token_out: Address,
+        amount_in: &BigInt,
+        min_amount_out: &BigInt,
+        recipient: Address,
+    ) -> Result<(String, Address), String> {
+        let amount_in = to_u256(amount_in)?;
+        let min_amount_out = to_u256(min_amount_out)?;
+
+        let calldata = match &quote.pool {
+            Pool::V3 { fee } => SwapRouter02::new(*SWAP_ROUTER_02, provider)
+                .exact_input_single((
+                    token_in,
+                    token_out,
+                    *fee,
+                    recipient,
+                    amount_in,
+                    min_amount_out,
+                    U256::zero(),
+                ))
+                .calldata(),
+            Pool::V2 { path } => {
+                let deadline = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+                    + 300;
+                UniswapV2Router::new(*V2_ROUTER, provider)
+                    .swap_exact_tokens_for_tokens(
+                        amount_in,
+                        min_amount_out,
+                        path.clone(),
+                        recipient,
+                        U256::from(deadline),
+                    )
+                    .calldata()
+            }
+        }
+        .ok_or("Failed to encode Uniswap swap")?;
+
+        Ok((format!("{}", calldata), quote.router()))
+    }
+}
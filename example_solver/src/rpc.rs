@@ -0,0 +1,496 @@
+use ethers::prelude::*;
+use ethers::providers::RpcError as _;
+use ethers::providers::{Http, Provider};
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
+use lazy_static::lazy_static;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::SerializableTransaction;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use std::env;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Why a request run with failover failed. Only `Transport` errors, where the
+/// endpoint could not be reached or did not answer, move on to the next
+/// endpoint: an `Answer` such as a revert would be the same on all of them.
+#[derive(Debug)]
+pub enum RpcFailure {
+    Transport(String),
+    Answer(String),
+}
+
+impl RpcFailure {
+    pub fn ethereum(context: &str, e: &ProviderError) -> Self {
+        let transport = match e {
+            ProviderError::JsonRpcClientError(e) => e.as_error_response().is_none(),
+            ProviderError::HTTPError(_) => true,
+            _ => false,
+        };
+        RpcFailure::new(transport, format!("{context}: {e}"))
+    }
+
+    pub fn contract<M: Middleware>(context: &str, e: &ContractError<M>) -> Self {
+        match e.as_provider_error() {
+            Some(provider_error) => RpcFailure::ethereum(context, provider_error),
+            None => RpcFailure::Answer(format!("{context}: {e}")),
+        }
+    }
+
+    pub fn svm(context: &str, e: &ClientError) -> Self {
+        let transport = matches!(e.kind(), ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_));
+        RpcFailure::new(transport, format!("{context}: {e}"))
+    }
+
+    fn new(transport: bool, message: String) -> Self {
+        if transport {
+            RpcFailure::Transport(message)
+        } else {
+            RpcFailure::Answer(message)
+        }
+    }
+}
+
+// errors that never reached an endpoint, e.g. an invalid address
+impl From<String> for RpcFailure {
+    fn from(message: String) -> Self {
+        RpcFailure::Answer(message)
+    }
+}
+
+impl From<RpcFailure> for String {
+    fn from(failure: RpcFailure) -> Self {
+        match failure {
+            RpcFailure::Transport(message) | RpcFailure::Answer(message) => message,
+        }
+    }
+}
+
+pub struct RpcNode<C> {
+    pub url: String,
+    pub client: Arc<C>,
+    healthy: AtomicBool,
+    latency_ms: AtomicU64,
+}
+
+/// The endpoints of one chain: reads go to the healthiest, fastest one and
+/// transactions can be broadcast to all the broadcast endpoints at once.
+pub struct RpcPool<C> {
+    // env var the pool was read from, for error messages
+    name: &'static str,
+    read: Vec<RpcNode<C>>,
+    broadcast: Vec<RpcNode<C>>,
+    connect: fn(&str) -> Result<C, String>,
+}
+
+fn ethereum_connect(url: &str) -> Result<Provider<Http>, String> {
+    Provider::<Http>::try_from(url).map_err(|e| format!("Invalid Ethereum RPC {url}: {}", e))
+}
+
+fn solana_connect(url: &str) -> Result<RpcClient, String> {
+    Ok(RpcClient::new_with_commitment(
+        url.to_string(),
+        CommitmentConfig::confirmed(),
+    ))
+}
+
+impl<C> RpcPool<C> {
+    // VAR is a comma separated list of endpoints, VAR_BROADCAST (defaults to VAR)
+    // the ones transactions are sent to
+    fn from_env(name: &'static str, connect: fn(&str) -> Result<C, String>) -> Self {
+        let nodes = |var: &str| -> Vec<RpcNode<C>> {
+            env::var(var)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .filter_map(|url| match connect(url) {
+                    Ok(client) => Some(RpcNode {
+                        url: url.to_string(),
+                        client: Arc::new(client),
+                        healthy: AtomicBool::new(true),
+                        latency_ms: AtomicU64::new(0),
+                    }),
+                    Err(e) => {
+                        eprintln!("Skipping {var} endpoint: {e}");
+                        None
+                    }
+                })
+                .collect()
+        };
+
+        let read = nodes(name);
+        let mut broadcast = nodes(&format!("{name}_BROADCAST"));
+        if broadcast.is_empty() {
+            broadcast = nodes(name);
+        }
+
+        RpcPool {
+            name,
+            read,
+            broadcast,
+            connect,
+        }
+    }
+
+    // healthy endpoints first, fastest first
+    fn ranked(&self) -> Vec<&RpcNode<C>> {
+        let mut nodes: Vec<&RpcNode<C>> = self.read.iter().collect();
+        nodes.sort_by_key(|node| {
+            (
+                !node.healthy.load(Ordering::Relaxed),
+                node.latency_ms.load(Ordering::Relaxed),
+            )
+        });
+        nodes
+    }
+
+    pub fn best(&self) -> Result<&RpcNode<C>, String> {
+        self.ranked()
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("{} must be set", self.name))
+    }
+
+    /// Shared client of the best endpoint.
+    pub fn client(&self) -> Result<Arc<C>, String> {
+        self.best().map(|node| node.client.clone())
+    }
+
+    pub fn url(&self) -> Result<String, String> {
+        self.best().map(|node| node.url.clone())
+    }
+
+    /// (url, healthy, latency_ms) of every read endpoint, as of the last health check.
+    pub fn status(&self) -> Vec<(String, bool, u64)> {
+        self.read
+            .iter()
+            .map(|node| {
+                (
+                    node.url.clone(),
+                    node.healthy.load(Ordering::Relaxed),
+                    node.latency_ms.load(Ordering::Relaxed),
+                )
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.read.iter().chain(self.broadcast.iter()).any(|node| node.url == url)
+    }
+
+    /// Shared client for `url`, for code that was handed an endpoint. Urls
+    /// outside the pool get a client of their own.
+    pub fn client_for(&self, url: &str) -> Result<Arc<C>, String> {
+        match self.read.iter().chain(self.broadcast.iter()).find(|node| node.url == url) {
+            Some(node) => Ok(node.client.clone()),
+            None => (self.connect)(url).map(Arc::new),
+        }
+    }
+
+    /// Takes `url` out of rotation until the next health check says otherwise.
+    pub fn report_failure(&self, url: &str) {
+        for node in self.read.iter().filter(|node| node.url == url) {
+            if node.healthy.swap(false, Ordering::Relaxed) {
+                eprintln!("{} endpoint {url} failed, failing over", self.name);
+            }
+        }
+    }
+
+    /// Runs `request` on the best endpoint, then on the next ones while it
+    /// can't reach them. Requests are retried, so they must only read: send
+    /// signed transactions with `ethereum_broadcast` / `svm_broadcast`.
+    pub async fn with_failover<T, F, Fut>(&self, request: F) -> Result<T, String>
+    where
+        F: Fn(Arc<C>) -> Fut,
+        Fut: Future<Output = Result<T, RpcFailure>>,
+    {
+        let mut last_error = format!("{} must be set", self.name);
+
+        for node in self.ranked() {
+            match request(node.client.clone()).await {
+                Ok(result) => return Ok(result),
+                Err(RpcFailure::Transport(e)) => {
+                    self.report_failure(&node.url);
+                    last_error = e;
+                }
+                Err(RpcFailure::Answer(e)) => return Err(e),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// `with_failover` for code that was handed an endpoint: endpoints of the
+    /// pool fail over to the others, urls outside it are only tried themselves.
+    pub async fn with_failover_for<T, F, Fut>(&self, url: &str, request: F) -> Result<T, String>
+    where
+        F: Fn(Arc<C>) -> Fut,
+        Fut: Future<Output = Result<T, RpcFailure>>,
+    {
+        if self.contains(url) {
+            self.with_failover(request).await
+        } else {
+            Ok(request(self.client_for(url)?).await?)
+        }
+    }
+
+    fn broadcast_nodes(&self) -> Result<&Vec<RpcNode<C>>, String> {
+        if self.broadcast.is_empty() {
+            return Err(format!("{} must be set", self.name));
+        }
+        Ok(&self.broadcast)
+    }
+
+    // updates health and latency from (height, latency) of every read endpoint,
+    // endpoints more than max_lag behind the highest one count as unhealthy
+    fn record_checks(&self, checks: Vec<Option<(u64, Duration)>>, max_lag: u64) {
+        let top = checks.iter().flatten().map(|(height, _)| *height).max().unwrap_or(0);
+
+        for (node, check) in self.read.iter().zip(checks) {
+            let healthy = match check {
+                Some((height, latency)) => {
+                    node.latency_ms.store(latency.as_millis() as u64, Ordering::Relaxed);
+                    height + max_lag >= top
+                }
+                None => false,
+            };
+
+            if node.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+                println!(
+                    "{} endpoint {} is {}",
+                    self.name,
+                    node.url,
+                    if healthy { "back up" } else { "down or lagging" }
+                );
+            }
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref ETHEREUM: RpcPool<Provider<Http>> =
+        RpcPool::from_env("ETHEREUM_RPC", ethereum_connect);
+    pub static ref SOLANA: RpcPool<RpcClient> = RpcPool::from_env("SOLANA_RPC", solana_connect);
+    pub static ref MANTIS: RpcPool<RpcClient> = RpcPool::from_env("MANTIS_RPC", solana_connect);
+}
+
+/// SOLANA or MANTIS, both are SVM chains.
+pub fn svm_pool(chain: &str) -> &'static RpcPool<RpcClient> {
+    if chain == "mantis" {
+        &MANTIS
+    } else {
+        &SOLANA
+    }
+}
+
+/// Shared client for an endpoint of either SVM pool.
+pub fn svm_client_for(url: &str) -> Result<Arc<RpcClient>, String> {
+    if MANTIS.contains(url) {
+        MANTIS.client_for(url)
+    } else {
+        SOLANA.client_for(url)
+    }
+}
+
+/// Sends a signed transaction to every ETHEREUM_RPC_BROADCAST endpoint and
+/// returns the hash as soon as one of them accepted it.
+pub async fn ethereum_broadcast(raw_transaction: Bytes) -> Result<H256, String> {
+    let nodes = ETHEREUM.broadcast_nodes()?;
+    first_accepted(nodes.iter().map(|node| {
+        let raw_transaction = raw_transaction.clone();
+        async move {
+            node.client
+                .request::<_, H256>("eth_sendRawTransaction", [raw_transaction])
+                .await
+                .map_err(|e| format!("{}: {}", node.url, e))
+        }
+    }))
+    .await
+}
+
+/// Same as `ethereum_broadcast` for SOLANA_RPC_BROADCAST / MANTIS_RPC_BROADCAST.
+pub async fn svm_broadcast<T: SerializableTransaction>(
+    chain: &str,
+    transaction: &T,
+) -> Result<Signature, String> {
+    let nodes = svm_pool(chain).broadcast_nodes()?;
+    first_accepted(nodes.iter().map(|node| async move {
+        node.client
+            .send_transaction(transaction)
+            .await
+            .map_err(|e| format!("{}: {}", node.url, e))
+    }))
+    .await
+}
+
+// a transaction counts as sent once any endpoint took it, the slower sends are
+// dropped then since the network has it already
+async fn first_accepted<T, Fut>(sends: impl Iterator<Item = Fut>) -> Result<T, String>
+where
+    Fut: Future<Output = Result<T, String>>,
+{
+    let mut sends: FuturesUnordered<Fut> = sends.collect();
+    let mut errors = Vec::new();
+    while let Some(result) = sends.next().await {
+        match result {
+            Ok(sent) => return Ok(sent),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    Err(format!("No endpoint accepted the transaction: {}", errors.join(", ")))
+}
+
+/// Receipt of a sent transaction, polled on the healthy ETHEREUM_RPC endpoints
+/// until it is mined or ETHEREUM_RECEIPT_TIMEOUT_SECS (default 300) passed,
+/// e.g. because the transaction was dropped.
+pub async fn ethereum_wait_for_receipt(tx_hash: H256) -> Result<TransactionReceipt, String> {
+    let timeout = Duration::from_secs(
+        env::var("ETHEREUM_RECEIPT_TIMEOUT_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .unwrap_or(300),
+    );
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let receipt = ETHEREUM
+            .with_failover(|provider| async move {
+                provider
+                    .get_transaction_receipt(tx_hash)
+                    .await
+                    .map_err(|e| RpcFailure::ethereum("Error while fetching transaction receipt", &e))
+            })
+            .await?;
+
+        match receipt {
+            Some(receipt) => return Ok(receipt),
+            None if Instant::now() >= deadline => {
+                return Err(format!(
+                    "Transaction {tx_hash:?} not mined after {}s",
+                    timeout.as_secs()
+                ))
+            }
+            None => tokio::time::sleep(Duration::from_secs(1)).await,
+        }
+    }
+}
+
+async fn check_ethereum() {
+    let checks = join_all(ETHEREUM.read.iter().map(|node| async move {
+        let started = Instant::now();
+        let block = tokio::time::timeout(Duration::from_secs(5), node.client.get_block_number())
+            .await
+            .ok()?
+            .ok()?;
+        Some((block.as_u64(), started.elapsed()))
+    }))
+    .await;
+
+    ETHEREUM.record_checks(checks, 3);
+}
+
+async fn check_svm(pool: &RpcPool<RpcClient>) {
+    let checks = join_all(pool.read.iter().map(|node| async move {
+        let started = Instant::now();
+        let slot = tokio::time::timeout(Duration::from_secs(5), node.client.get_slot())
+            .await
+            .ok()?
+            .ok()?;
+        Some((slot, started.elapsed()))
+    }))
+    .await;
+
+    pool.record_checks(checks, 50);
+}
+
+/// Probes every read endpoint each RPC_HEALTH_INTERVAL_SECS for liveness,
+/// latency and how far behind the chain head it is.
+pub async fn run_rpc_health_checks() {
+    let interval = Duration::from_secs(
+        env::var("RPC_HEALTH_INTERVAL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .unwrap_or(10),
+    );
+
+    loop {
+        check_rpc_health().await;
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// One round of the RPC_HEALTH_INTERVAL_SECS checks, on every pool.
+pub async fn check_rpc_health() {
+    tokio::join!(check_ethereum(), check_svm(&SOLANA), check_svm(&MANTIS));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn pool(urls: &[&str]) -> RpcPool<String> {
+        let nodes = || -> Vec<RpcNode<String>> {
+            urls.iter()
+                .map(|url| RpcNode {
+                    url: url.to_string(),
+                    client: Arc::new(url.to_string()),
+                    healthy: AtomicBool::new(true),
+                    latency_ms: AtomicU64::new(0),
+                })
+                .collect()
+        };
+        RpcPool {
+            name: "TEST_RPC",
+            read: nodes(),
+            broadcast: nodes(),
+            connect: |url| Ok(url.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn transport_errors_fail_over() {
+        let pool = pool(&["a", "b"]);
+        let tried = Mutex::new(Vec::new());
+
+        let result = pool
+            .with_failover(|url| {
+                tried.lock().unwrap().push(url.to_string());
+                async move {
+                    match url.as_str() {
+                        "a" => Err(RpcFailure::Transport(String::from("connection refused"))),
+                        _ => Ok(url.to_string()),
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result, Ok(String::from("b")));
+        assert_eq!(*tried.lock().unwrap(), ["a", "b"]);
+        assert_eq!(pool.best().unwrap().url, "b");
+    }
+
+    #[tokio::test]
+    async fn answers_are_not_retried() {
+        let pool = pool(&["a", "b"]);
+        let tried = Mutex::new(Vec::new());
+
+        let result: Result<(), String> = pool
+            .with_failover(|url| {
+                tried.lock().unwrap().push(url.to_string());
+                async move { Err(RpcFailure::Answer(String::from("execution reverted"))) }
+            })
+            .await;
+
+        assert_eq!(result, Err(String::from("execution reverted")));
+        assert_eq!(*tried.lock().unwrap(), ["a"]);
+        // a revert says nothing about the endpoint
+        assert!(pool.status().iter().all(|(_, healthy, _)| *healthy));
+    }
+}
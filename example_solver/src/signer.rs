@@ -0,0 +1,414 @@
+use async_trait::async_trait;
+use ethers::prelude::*;
+use ethers::signers::{LocalWallet, Signer as EthersSigner, WalletError};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::transaction::eip712::Eip712;
+use ethers::utils::hash_message;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature as SolanaSignature};
+use solana_sdk::signer::{Signer as SolanaSignerTrait, SignerError};
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use zeroize::Zeroizing;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("wallet: {0}")]
+    Wallet(#[from] WalletError),
+
+    #[error("remote signer: {0}")]
+    Remote(String),
+
+    #[error("eip712: {0}")]
+    Eip712(String),
+}
+
+/// A signer process listening on a local unix socket, holding the keys so this
+/// process never does. One JSON request per line:
+/// `{"key": "ethereum"|"identity"|"identity_previous"|"solana", "method": ..., "payload": hex}`,
+/// answered by `{"result": ...}` or `{"error": ...}`. Methods are `address` /
+/// `sign_hash` (32 bytes -> 65 bytes r || s || v) for the secp256k1 keys and
+/// `pubkey` / `sign_message` (-> base58 signature) for solana.
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    socket: PathBuf,
+    key: String,
+}
+
+impl RemoteSigner {
+    fn request_blocking(&self, method: &str, payload: &[u8]) -> Result<String, String> {
+        let mut stream = UnixStream::connect(&self.socket)
+            .map_err(|e| format!("Failed to connect to {}: {e}", self.socket.display()))?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .map_err(|e| e.to_string())?;
+
+        let request = json!({
+            "key": self.key,
+            "method": method,
+            "payload": format!("0x{}", hex::encode(payload)),
+        });
+        writeln!(stream, "{request}").map_err(|e| format!("Failed to write request: {e}"))?;
+
+        let mut line = String::new();
+        BufReader::new(stream)
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read response: {e}"))?;
+        let response: Value = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+
+        if let Some(error) = response.get("error") {
+            return Err(error.to_string());
+        }
+        response
+            .get("result")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .ok_or_else(|| format!("Invalid response from signer: {response}"))
+    }
+
+    // for the sync callers (Solana's Signer trait, loading the signers), keeps
+    // the socket IO from stalling the other tasks of a runtime worker
+    fn request(&self, method: &str, payload: &[u8]) -> Result<String, String> {
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| self.request_blocking(method, payload))
+            }
+            _ => self.request_blocking(method, payload),
+        }
+    }
+
+    async fn ethereum_sign_hash(&self, hash: H256) -> Result<Signature, Error> {
+        let signer = self.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            signer.request_blocking("sign_hash", hash.as_bytes())
+        })
+        .await
+        .map_err(|e| Error::Remote(e.to_string()))?
+        .map_err(Error::Remote)?;
+        let bytes = hex::decode(result.trim_start_matches("0x"))
+            .map_err(|e| Error::Remote(e.to_string()))?;
+        if bytes.len() != 65 {
+            return Err(Error::Remote(format!("expected 65 signature bytes, got {}", bytes.len())));
+        }
+
+        // v as a recovery id or already as 27 / 28
+        let recovery_id = if bytes[64] >= 27 { bytes[64] - 27 } else { bytes[64] };
+        Ok(Signature {
+            r: U256::from_big_endian(&bytes[..32]),
+            s: U256::from_big_endian(&bytes[32..64]),
+            v: recovery_id as u64 + 27,
+        })
+    }
+}
+
+fn remote_signer(key: &str) -> Result<RemoteSigner, String> {
+    env::var("SIGNER_SOCKET")
+        .map(|socket| RemoteSigner {
+            socket: PathBuf::from(socket),
+            key: key.to_string(),
+        })
+        .map_err(|_| "SIGNER_SOCKET must be set for a remote signer".to_string())
+}
+
+// the keystore password, from <PREFIX>_KEYSTORE_PASSWORD_FILE or <PREFIX>_KEYSTORE_PASSWORD
+fn keystore_password(prefix: &str) -> Result<Zeroizing<String>, String> {
+    let path = env::var(format!("{prefix}_KEYSTORE_PASSWORD_FILE")).unwrap_or_default();
+    if !path.is_empty() {
+        let password = Zeroizing::new(
+            fs::read_to_string(&path).map_err(|e| format!("Failed to read {path}: {e}"))?,
+        );
+        return Ok(Zeroizing::new(password.trim_end().to_string()));
+    }
+
+    let password = env::var(format!("{prefix}_KEYSTORE_PASSWORD"))
+        .map(Zeroizing::new)
+        .map_err(|_| format!("{prefix}_KEYSTORE_PASSWORD or {prefix}_KEYSTORE_PASSWORD_FILE must be set"))?;
+    env::remove_var(format!("{prefix}_KEYSTORE_PASSWORD"));
+    Ok(password)
+}
+
+fn decrypt_keystore(prefix: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    let path = env::var(format!("{prefix}_KEYSTORE"))
+        .map_err(|_| format!("{prefix}_KEYSTORE must be set"))?;
+    let password = keystore_password(prefix)?;
+
+    eth_keystore::decrypt_key(&path, password.as_bytes())
+        .map(Zeroizing::new)
+        .map_err(|e| format!("Failed to decrypt {path}: {e}"))
+}
+
+/// A secp256k1 key (the Ethereum execution key or the auctioneer identity), in
+/// memory or behind a remote signer. Cheap to clone, clones share the key.
+#[derive(Debug, Clone)]
+pub enum EthereumSigner {
+    Local(Arc<LocalWallet>),
+    Remote {
+        signer: RemoteSigner,
+        address: Address,
+        chain_id: u64,
+    },
+}
+
+impl EthereumSigner {
+    /// <PREFIX>_SIGNER selects where the key lives: "env" (<PREFIX>_PKEY),
+    /// "keystore" (a geth / EIP-2335 JSON keystore at <PREFIX>_KEYSTORE), "remote"
+    /// (SIGNER_SOCKET, key named after the prefix) or "none".
+    fn from_env(prefix: &str, default_source: &str) -> Result<Option<Self>, String> {
+        let source = env::var(format!("{prefix}_SIGNER"))
+            .ok()
+            .filter(|source| !source.is_empty())
+            .unwrap_or_else(|| default_source.to_string());
+
+        let signer = match source.as_str() {
+            "none" => return Ok(None),
+            "env" => {
+                let key = Zeroizing::new(
+                    env::var(format!("{prefix}_PKEY"))
+                        .map_err(|_| format!("{prefix}_PKEY must be set"))?,
+                );
+                // nothing else needs it, and child processes shouldn't inherit it
+                env::remove_var(format!("{prefix}_PKEY"));
+                EthereumSigner::Local(Arc::new(
+                    key.parse::<LocalWallet>()
+                        .map_err(|e| format!("Invalid {prefix}_PKEY: {e}"))?,
+                ))
+            }
+            "keystore" => {
+                let secret = decrypt_keystore(prefix)?;
+                EthereumSigner::Local(Arc::new(
+                    LocalWallet::from_bytes(&secret)
+                        .map_err(|e| format!("Invalid key in {prefix}_KEYSTORE: {e}"))?,
+                ))
+            }
+            "remote" => {
+                let signer = remote_signer(&prefix.to_lowercase())?;
+                let address = signer.request("address", &[])?;
+                EthereumSigner::Remote {
+                    signer,
+                    address: Address::from_str(&address)
+                        .map_err(|e| format!("Invalid address from signer: {e}"))?,
+                    chain_id: 1,
+                }
+            }
+            _ => return Err(format!("Unknown {prefix}_SIGNER {source}")),
+        };
+
+        Ok(Some(signer.with_chain_id(1u64)))
+    }
+}
+
+#[async_trait]
+impl EthersSigner for EthereumSigner {
+    type Error = Error;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            EthereumSigner::Local(wallet) => Ok(wallet.sign_message(message).await?),
+            EthereumSigner::Remote { signer, .. } => {
+                signer.ethereum_sign_hash(hash_message(message)).await
+            }
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            EthereumSigner::Local(wallet) => Ok(wallet.sign_transaction(tx).await?),
+            EthereumSigner::Remote {
+                signer, chain_id, ..
+            } => {
+                let mut tx = tx.clone();
+                let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(*chain_id);
+                tx.set_chain_id(chain_id);
+
+                // EIP-155 v, as LocalWallet does, typed transactions normalize it back
+                let mut signature = signer.ethereum_sign_hash(tx.sighash()).await?;
+                signature.v = signature.v - 27 + 35 + chain_id * 2;
+                Ok(signature)
+            }
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            EthereumSigner::Local(wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            EthereumSigner::Remote { signer, .. } => {
+                let hash = payload
+                    .encode_eip712()
+                    .map_err(|e| Error::Eip712(e.to_string()))?;
+                signer.ethereum_sign_hash(H256::from(hash)).await
+            }
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            EthereumSigner::Local(wallet) => wallet.address(),
+            EthereumSigner::Remote { address, .. } => *address,
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            EthereumSigner::Local(wallet) => wallet.chain_id(),
+            EthereumSigner::Remote { chain_id, .. } => *chain_id,
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            EthereumSigner::Local(wallet) => {
+                EthereumSigner::Local(Arc::new((*wallet).clone().with_chain_id(chain_id)))
+            }
+            EthereumSigner::Remote {
+                signer, address, ..
+            } => EthereumSigner::Remote {
+                signer,
+                address,
+                chain_id: chain_id.into(),
+            },
+        }
+    }
+}
+
+/// The Solana (and Mantis) execution key, in memory or behind a remote signer.
+#[derive(Debug)]
+pub enum SolanaSigner {
+    Local(Keypair),
+    Remote { signer: RemoteSigner, pubkey: Pubkey },
+}
+
+impl SolanaSigner {
+    /// SOLANA_SIGNER selects where the key lives: "env" (SOLANA_KEYPAIR, the
+    /// default), "keystore" (the 64 keypair bytes in a JSON keystore, same format
+    /// as Ethereum's, at SOLANA_KEYSTORE) or "remote" (SIGNER_SOCKET).
+    fn from_env() -> Result<Self, String> {
+        let source = env::var("SOLANA_SIGNER").unwrap_or_else(|_| String::from("env"));
+
+        match source.as_str() {
+            "env" => {
+                let key = Zeroizing::new(
+                    env::var("SOLANA_KEYPAIR").map_err(|_| "SOLANA_KEYPAIR must be set")?,
+                );
+                env::remove_var("SOLANA_KEYPAIR");
+                let bytes = Zeroizing::new(
+                    solana_sdk::bs58::decode(key.as_str())
+                        .into_vec()
+                        .map_err(|e| format!("Invalid SOLANA_KEYPAIR: {e}"))?,
+                );
+                Keypair::from_bytes(&bytes)
+                    .map(SolanaSigner::Local)
+                    .map_err(|e| format!("Invalid SOLANA_KEYPAIR: {e}"))
+            }
+            "keystore" => {
+                let secret = decrypt_keystore("SOLANA")?;
+                Keypair::from_bytes(&secret)
+                    .map(SolanaSigner::Local)
+                    .map_err(|e| format!("Invalid key in SOLANA_KEYSTORE: {e}"))
+            }
+            "remote" => {
+                let signer = remote_signer("solana")?;
+                let pubkey = signer.request("pubkey", &[])?;
+                Ok(SolanaSigner::Remote {
+                    signer,
+                    pubkey: Pubkey::from_str(&pubkey)
+                        .map_err(|e| format!("Invalid pubkey from signer: {e}"))?,
+                })
+            }
+            _ => Err(format!("Unknown SOLANA_SIGNER {source}")),
+        }
+    }
+}
+
+impl SolanaSignerTrait for SolanaSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        match self {
+            SolanaSigner::Local(keypair) => keypair.try_pubkey(),
+            SolanaSigner::Remote { pubkey, .. } => Ok(*pubkey),
+        }
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<SolanaSignature, SignerError> {
+        match self {
+            SolanaSigner::Local(keypair) => keypair.try_sign_message(message),
+            SolanaSigner::Remote { signer, .. } => {
+                let signature = signer
+                    .request("sign_message", message)
+                    .map_err(SignerError::Custom)?;
+                SolanaSignature::from_str(&signature)
+                    .map_err(|e| SignerError::Custom(format!("Invalid signature from signer: {e}")))
+            }
+        }
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+lazy_static! {
+    static ref ETHEREUM_SIGNER: Result<EthereumSigner, String> =
+        EthereumSigner::from_env("ETHEREUM", "env")
+            .and_then(|signer| signer.ok_or_else(|| "ETHEREUM_SIGNER can't be none".to_string()));
+    static ref SOLANA_SIGNER: Result<Arc<SolanaSigner>, String> =
+        SolanaSigner::from_env().map(Arc::new);
+    // signs everything sent to the auctioneer, holds no funds
+    static ref IDENTITY_SIGNER: Result<EthereumSigner, String> =
+        match EthereumSigner::from_env("IDENTITY", "none") {
+            Ok(Some(signer)) => Ok(signer),
+            // the identity key is exposed to the bidding path, so holding funds
+            // behind it has to be asked for
+            Ok(None) if env::var("ALLOW_ETHEREUM_KEY_IDENTITY").as_deref() == Ok("1") => {
+                eprintln!("IDENTITY_SIGNER is not set, signing auctioneer messages with the Ethereum execution key");
+                ETHEREUM_SIGNER.clone()
+            }
+            Ok(None) => Err(
+                "IDENTITY_SIGNER must be set, or ALLOW_ETHEREUM_KEY_IDENTITY=1 to sign auctioneer messages with the Ethereum key"
+                    .to_string(),
+            ),
+            Err(e) => Err(e),
+        };
+    // the identity being rotated away from, vouches for the new one at registration
+    static ref PREVIOUS_IDENTITY_SIGNER: Result<Option<EthereumSigner>, String> =
+        EthereumSigner::from_env("IDENTITY_PREVIOUS", "none");
+}
+
+/// Builds every signer, which takes their keys out of the environment. Run at
+/// startup so the keys don't sit there until the first fill.
+pub fn load_signers() -> Result<(), String> {
+    ethereum_signer()?;
+    solana_signer()?;
+    identity_signer()?;
+    previous_identity_signer()?;
+    Ok(())
+}
+
+pub fn ethereum_signer() -> Result<EthereumSigner, String> {
+    ETHEREUM_SIGNER.clone()
+}
+
+pub fn identity_signer() -> Result<EthereumSigner, String> {
+    IDENTITY_SIGNER.clone()
+}
+
+pub fn previous_identity_signer() -> Result<Option<EthereumSigner>, String> {
+    PREVIOUS_IDENTITY_SIGNER.clone()
+}
+
+pub fn solana_signer() -> Result<Arc<SolanaSigner>, String> {
+    SOLANA_SIGNER.clone()
+}
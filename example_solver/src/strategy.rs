@@ -0,0 +1,475 @@
+use crate::chains::{OperationOutput, PostIntentInfo};
+use crate::routers::IntentQuote;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use tokio::sync::RwLock;
+
+// results the win-rate controller looks back on
+const WIN_RATE_WINDOW: usize = 50;
+// dst_chain rates the volatility is measured on, per pair
+const VOLATILITY_WINDOW: usize = 20;
+
+/// What a strategy knows when pricing a bid.
+pub struct BidInput<'a> {
+    pub intent: &'a PostIntentInfo,
+    pub quote: &'a IntentQuote,
+    // token_out we can provide, after FLAT_FEES + COMISSION
+    pub quote_amount: BigInt,
+    // token_out the user asks for at least
+    pub amount_out_min: BigInt,
+}
+
+/// Part of the quote a strategy keeps for itself, and why.
+pub struct Margin {
+    pub bps: u32,
+    pub strategy: String,
+    pub inputs: Value,
+}
+
+pub struct Bid {
+    pub amount: BigInt,
+    pub margin: Margin,
+}
+
+#[async_trait]
+pub trait BidStrategy: Send + Sync {
+    async fn margin(&self, input: &BidInput<'_>) -> Margin;
+
+    /// Code 4 of an intent we bid on.
+    async fn record_result(&self, _intent: &PostIntentInfo, _won: bool) {}
+}
+
+/// Bids the whole quote, like the solver always did.
+pub struct FullQuote;
+
+#[async_trait]
+impl BidStrategy for FullQuote {
+    async fn margin(&self, _input: &BidInput<'_>) -> Margin {
+        Margin {
+            bps: 0,
+            strategy: String::from("full"),
+            inputs: json!({}),
+        }
+    }
+}
+
+pub struct FixedMargin {
+    pub bps: u32,
+}
+
+#[async_trait]
+impl BidStrategy for FixedMargin {
+    async fn margin(&self, _input: &BidInput<'_>) -> Margin {
+        Margin {
+            bps: self.bps,
+            strategy: String::from("fixed"),
+            inputs: json!({}),
+        }
+    }
+}
+
+/// base_bps plus bps_per_1000 for every 1000 bridge tokens the fill needs on
+/// dst_chain, up to max_bps: bigger fills keep more inventory at risk.
+pub struct SizeScaledMargin {
+    pub base_bps: u32,
+    pub bps_per_1000: u32,
+    pub max_bps: u32,
+}
+
+#[async_trait]
+impl BidStrategy for SizeScaledMargin {
+    async fn margin(&self, input: &BidInput<'_>) -> Margin {
+        // bridge tokens (USDT, USDC) have 6 decimals
+        let size = input.quote.bridge_amount_dst.to_f64().unwrap_or_default() / 1e6;
+        let bps = self.base_bps as f64 + self.bps_per_1000 as f64 * size / 1000f64;
+
+        Margin {
+            bps: (bps as u32).min(self.max_bps),
+            strategy: String::from("size"),
+            inputs: json!({ "size": size }),
+        }
+    }
+}
+
+/// base_bps plus multiplier times the recent volatility (in bps) of the
+/// bridge token -> token_out rate on dst_chain, up to max_bps: that rate can
+/// move between the bid and the fill.
+pub struct VolatilityScaledMargin {
+    pub base_bps: u32,
+    pub multiplier: f64,
+    pub max_bps: u32,
+    // <(dst_chain, bridge token, token_out), last rates>
+    rates: RwLock<HashMap<(String, String, String), VecDeque<f64>>>,
+}
+
+impl VolatilityScaledMargin {
+    pub fn new(base_bps: u32, multiplier: f64, max_bps: u32) -> Self {
+        VolatilityScaledMargin {
+            base_bps,
+            multiplier,
+            max_bps,
+            rates: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+// standard deviation of the relative changes between consecutive rates, in bps
+fn volatility_bps(rates: &VecDeque<f64>) -> f64 {
+    let changes: Vec<f64> = rates
+        .iter()
+        .zip(rates.iter().skip(1))
+        .filter(|(previous, _)| **previous > 0f64)
+        .map(|(previous, rate)| (rate - previous) / previous)
+        .collect();
+    if changes.len() < 2 {
+        return 0f64;
+    }
+
+    let mean = changes.iter().sum::<f64>() / changes.len() as f64;
+    let variance = changes
+        .iter()
+        .map(|change| (change - mean).powi(2))
+        .sum::<f64>()
+        / (changes.len() - 1) as f64;
+    variance.sqrt() * 10_000f64
+}
+
+#[async_trait]
+impl BidStrategy for VolatilityScaledMargin {
+    async fn margin(&self, input: &BidInput<'_>) -> Margin {
+        let token_out = match &input.intent.outputs {
+            OperationOutput::SwapTransfer(output) => output.token_out.to_lowercase(),
+            _ => String::new(),
+        };
+        let key = (
+            input.quote.dst_chain.clone(),
+            input.quote.bridge_token_dst.to_lowercase(),
+            token_out,
+        );
+        let rate = input.quote_amount.to_f64().unwrap_or_default()
+            / input.quote.bridge_amount_dst.to_f64().unwrap_or(1f64);
+
+        let volatility = {
+            let mut rates = self.rates.write().await;
+            let rates = rates.entry(key).or_default();
+            rates.push_back(rate);
+            if rates.len() > VOLATILITY_WINDOW {
+                rates.pop_front();
+            }
+            volatility_bps(rates)
+        };
+        let bps = self.base_bps as f64 + self.multiplier * volatility;
+
+        Margin {
+            bps: (bps as u32).min(self.max_bps),
+            strategy: String::from("volatility"),
+            inputs: json!({ "volatility_bps": volatility }),
+        }
+    }
+}
+
+/// Adapts the margin from the last WIN_RATE_WINDOW auction results: raises it
+/// by step_bps while winning more than target_win_rate, lowers it otherwise.
+pub struct WinRateController {
+    pub target_win_rate: f64,
+    pub min_bps: u32,
+    pub max_bps: u32,
+    pub step_bps: u32,
+    // (current margin, last results)
+    state: RwLock<(u32, VecDeque<bool>)>,
+}
+
+impl WinRateController {
+    pub fn new(target_win_rate: f64, min_bps: u32, max_bps: u32, step_bps: u32) -> Self {
+        WinRateController {
+            target_win_rate,
+            min_bps,
+            max_bps,
+            step_bps,
+            state: RwLock::new((min_bps, VecDeque::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl BidStrategy for WinRateController {
+    async fn margin(&self, _input: &BidInput<'_>) -> Margin {
+        let state = self.state.read().await;
+        let wins = state.1.iter().filter(|won| **won).count();
+
+        Margin {
+            bps: state.0,
+            strategy: String::from("win_rate"),
+            inputs: json!({ "results": state.1.len(), "wins": wins }),
+        }
+    }
+
+    async fn record_result(&self, _intent: &PostIntentInfo, won: bool) {
+        let mut state = self.state.write().await;
+        state.1.push_back(won);
+        if state.1.len() > WIN_RATE_WINDOW {
+            state.1.pop_front();
+        }
+
+        let win_rate = state.1.iter().filter(|won| **won).count() as f64 / state.1.len() as f64;
+        state.0 = if win_rate > self.target_win_rate {
+            (state.0 + self.step_bps).min(self.max_bps)
+        } else {
+            state.0.saturating_sub(self.step_bps).max(self.min_bps)
+        };
+    }
+}
+
+/// A strategy per (src_chain, dst_chain), the default one for other routes.
+pub struct RouteOverrides {
+    pub default: Box<dyn BidStrategy>,
+    pub routes: HashMap<(String, String), Box<dyn BidStrategy>>,
+}
+
+impl RouteOverrides {
+    fn strategy(&self, intent: &PostIntentInfo) -> &dyn BidStrategy {
+        self.routes
+            .get(&(intent.src_chain.clone(), intent.dst_chain.clone()))
+            .unwrap_or(&self.default)
+            .as_ref()
+    }
+}
+
+#[async_trait]
+impl BidStrategy for RouteOverrides {
+    async fn margin(&self, input: &BidInput<'_>) -> Margin {
+        self.strategy(input.intent).margin(input).await
+    }
+
+    async fn record_result(&self, intent: &PostIntentInfo, won: bool) {
+        self.strategy(intent).record_result(intent, won).await
+    }
+}
+
+/// Parses "full", "fixed:<bps>", "size:<base_bps>:<bps_per_1000>:<max_bps>",
+/// "volatility:<base_bps>:<multiplier>:<max_bps>" or
+/// "win_rate:<target %>:<min_bps>:<max_bps>:<step_bps>".
+pub fn parse_strategy(spec: &str) -> Result<Box<dyn BidStrategy>, String> {
+    let parts: Vec<&str> = spec.trim().split(':').collect();
+    let arg = |i: usize| -> Result<f64, String> {
+        parts
+            .get(i)
+            .and_then(|arg| arg.trim().parse::<f64>().ok())
+            .ok_or_else(|| format!("Invalid bid strategy {spec}"))
+    };
+
+    Ok(match parts[0] {
+        "full" => Box::new(FullQuote),
+        "fixed" => Box::new(FixedMargin {
+            bps: arg(1)? as u32,
+        }),
+        "size" => Box::new(SizeScaledMargin {
+            base_bps: arg(1)? as u32,
+            bps_per_1000: arg(2)? as u32,
+            max_bps: arg(3)? as u32,
+        }),
+        "volatility" => Box::new(VolatilityScaledMargin::new(
+            arg(1)? as u32,
+            arg(2)?,
+            arg(3)? as u32,
+        )),
+        "win_rate" => Box::new(WinRateController::new(
+            arg(1)? / 100f64,
+            arg(2)? as u32,
+            arg(3)? as u32,
+            arg(4)? as u32,
+        )),
+        _ => return Err(format!("Unknown bid strategy {spec}")),
+    })
+}
+
+/// BID_STRATEGY (default "full"), with BID_STRATEGY_ROUTES overriding it on some
+/// routes, e.g. "ethereum-solana=fixed:30,solana-solana=win_rate:30:0:300:5".
+pub fn strategy_from_env() -> Result<Box<dyn BidStrategy>, String> {
+    let default =
+        parse_strategy(&env::var("BID_STRATEGY").unwrap_or_else(|_| String::from("full")))?;
+
+    let mut routes = HashMap::new();
+    for entry in env::var("BID_STRATEGY_ROUTES")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+    {
+        let (route, spec) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid BID_STRATEGY_ROUTES entry {entry}"))?;
+        let (src_chain, dst_chain) = route
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid BID_STRATEGY_ROUTES route {route}"))?;
+        routes.insert(
+            (src_chain.to_string(), dst_chain.to_string()),
+            parse_strategy(spec)?,
+        );
+    }
+
+    Ok(if routes.is_empty() {
+        default
+    } else {
+        Box::new(RouteOverrides { default, routes })
+    })
+}
+
+lazy_static! {
+    pub static ref BID_STRATEGY: Box<dyn BidStrategy> =
+        strategy_from_env().expect("Invalid BID_STRATEGY / BID_STRATEGY_ROUTES");
+}
+
+/// Prices a bid with `strategy`. A margin eating all the room above the user's
+/// minimum still bids just above it, the margin inputs then say so and what
+/// was actually kept.
+pub async fn price_bid(strategy: &dyn BidStrategy, input: &BidInput<'_>) -> Bid {
+    let mut margin = strategy.margin(input).await;
+    let mut amount = &input.quote_amount * (10_000 - margin.bps.min(10_000)) / 10_000;
+    if amount <= input.amount_out_min && input.quote_amount > input.amount_out_min {
+        amount = &input.amount_out_min + 1;
+        let kept_bps = (&input.quote_amount - &amount) * 10_000 / &input.quote_amount;
+        if let Value::Object(inputs) = &mut margin.inputs {
+            inputs.insert(String::from("floored_at_amount_out_min"), json!(true));
+            inputs.insert(
+                String::from("kept_bps"),
+                json!(kept_bps.to_u32().unwrap_or_default()),
+            );
+        }
+    }
+
+    Bid { amount, margin }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chains::{OperationInput, SwapTransferInput, SwapTransferOutput};
+
+    fn intent(src_chain: &str, dst_chain: &str) -> PostIntentInfo {
+        PostIntentInfo {
+            function_name: String::from("transfer"),
+            src_chain: src_chain.to_string(),
+            dst_chain: dst_chain.to_string(),
+            inputs: OperationInput::SwapTransfer(SwapTransferInput {
+                token_in: String::from("token_in"),
+                amount_in: String::from("1000"),
+                src_chain_user: String::from("user"),
+                timeout: String::from("0"),
+            }),
+            outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+                token_out: String::from("token_out"),
+                amount_out: String::from("990"),
+                dst_chain_user: String::from("user"),
+            }),
+        }
+    }
+
+    async fn bid(strategy: &dyn BidStrategy, quote_amount: u64, amount_out_min: u64) -> Bid {
+        let intent = intent("ethereum", "solana");
+        let quote = IntentQuote::default();
+        price_bid(
+            strategy,
+            &BidInput {
+                intent: &intent,
+                quote: &quote,
+                quote_amount: BigInt::from(quote_amount),
+                amount_out_min: BigInt::from(amount_out_min),
+            },
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn margin_is_taken_off_the_quote() {
+        let priced = bid(&FixedMargin { bps: 100 }, 100_000, 90_000).await;
+        assert_eq!(priced.amount, BigInt::from(99_000));
+        assert_eq!(priced.margin.bps, 100);
+        assert_eq!(priced.margin.inputs, json!({}));
+    }
+
+    #[tokio::test]
+    async fn bid_floor_is_recorded() {
+        // 100 bps would bid 99_000, below the 99_900 the user wants
+        let priced = bid(&FixedMargin { bps: 100 }, 100_000, 99_900).await;
+        assert_eq!(priced.amount, BigInt::from(99_901));
+        assert_eq!(priced.margin.bps, 100);
+        assert_eq!(
+            priced.margin.inputs,
+            json!({ "floored_at_amount_out_min": true, "kept_bps": 9 })
+        );
+
+        // no room above the minimum at all, nothing to bid
+        let priced = bid(&FullQuote, 99_900, 99_900).await;
+        assert_eq!(priced.amount, BigInt::from(99_900));
+        assert_eq!(priced.margin.inputs, json!({}));
+    }
+
+    #[tokio::test]
+    async fn win_rate_controller_raises_and_lowers_within_bounds() {
+        let controller = WinRateController::new(0.5, 10, 30, 10);
+        let intent = intent("ethereum", "solana");
+        async fn margin(controller: &WinRateController) -> u32 {
+            bid(controller, 100_000, 0).await.margin.bps
+        }
+        assert_eq!(margin(&controller).await, 10);
+
+        // winning above the target keeps more, up to max_bps
+        for expected in [20, 30, 30] {
+            controller.record_result(&intent, true).await;
+            assert_eq!(margin(&controller).await, expected);
+        }
+
+        // 3 wins out of 6 is no longer above 50%, back down to min_bps
+        for expected in [30, 30, 20, 10, 10] {
+            controller.record_result(&intent, false).await;
+            assert_eq!(margin(&controller).await, expected);
+        }
+    }
+
+    #[test]
+    fn volatility_of_steady_rates_is_zero() {
+        assert_eq!(volatility_bps(&VecDeque::from(vec![1.0, 1.0, 1.0, 1.0])), 0f64);
+        // one change says nothing about its spread
+        assert_eq!(volatility_bps(&VecDeque::from(vec![1.0, 2.0])), 0f64);
+        // +1%, -1%: a standard deviation of about 1.41% relative change
+        let volatility = volatility_bps(&VecDeque::from(vec![1.0, 1.01, 0.9999]));
+        assert!((volatility - 141.42).abs() < 0.1, "{volatility}");
+    }
+
+    #[test]
+    fn invalid_specs_are_refused() {
+        for spec in ["fixed", "fixed:abc", "size:1:2", "win_rate:50:0:300", "margin:30"] {
+            assert!(parse_strategy(spec).is_err(), "{spec}");
+        }
+        assert!(parse_strategy(" fixed:30 ").is_ok());
+        assert!(parse_strategy("volatility:10:1.5:200").is_ok());
+    }
+
+    #[tokio::test]
+    async fn routes_override_the_default() {
+        let strategy = RouteOverrides {
+            default: Box::new(FullQuote),
+            routes: HashMap::from([(
+                (String::from("ethereum"), String::from("solana")),
+                Box::new(FixedMargin { bps: 30 }) as Box<dyn BidStrategy>,
+            )]),
+        };
+        let quote = IntentQuote::default();
+        for (src_chain, dst_chain, bps) in [("ethereum", "solana", 30), ("solana", "ethereum", 0)] {
+            let intent = intent(src_chain, dst_chain);
+            let input = BidInput {
+                intent: &intent,
+                quote: &quote,
+                quote_amount: BigInt::from(100_000),
+                amount_out_min: BigInt::from(0),
+            };
+            assert_eq!(strategy.margin(&input).await.bps, bps);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use ethers::abi::Abi;
+use ethers::types::Bytes;
+use serde_json::Value;
+use std::process::Command;
+use std::str::FromStr;
+
+// (abi, runtime bytecode) of `name` in tests/contracts
+pub fn compile(name: &str) -> (Abi, Bytes) {
+    let output = Command::new("solc")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["--combined-json", "abi,bin-runtime"])
+        .arg(format!("tests/contracts/{name}.sol"))
+        .output()
+        .expect("solc must be installed");
+    assert!(
+        output.status.success(),
+        "solc failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let contract = &json["contracts"][format!("tests/contracts/{name}.sol:{name}")];
+    // older solc versions return the abi as a JSON string
+    let abi = match &contract["abi"] {
+        Value::String(abi) => serde_json::from_str(abi).unwrap(),
+        abi => serde_json::from_value(abi.clone()).unwrap(),
+    };
+    let code = Bytes::from_str(contract["bin-runtime"].as_str().unwrap()).unwrap();
+
+    (abi, code)
+}
@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+interface IERC20 {
+    function transfer(address to, uint256 amount) external returns (bool);
+    function transferFrom(address from, address to, uint256 amount) external returns (bool);
+}
+
+// Same ABI as the solver's `Escrow` abigen (intents, sendFundsToUser), placed at
+// ESCROW_SC_ETHEREUM. escrowFunds stands in for the user deposit plus the
+// auctioneer recording the winner.
+contract MockEscrow {
+    struct Intent {
+        address tokenIn;
+        uint256 amountIn;
+        address srcUser;
+        string dstUser;
+        string tokenOut;
+        string amountOut;
+        string winnerSolver;
+        uint256 timeout;
+    }
+
+    struct SolverTransferData {
+        uint256 intentId;
+        address tokenOut;
+        uint256 amountOut;
+        address dstUser;
+        bool singleDomain;
+        string solverOut;
+    }
+
+    mapping(uint256 => Intent) public intents;
+
+    function escrowFunds(uint256 intentId, Intent calldata intent) external {
+        require(intent.srcUser == msg.sender, "not the user");
+        IERC20(intent.tokenIn).transferFrom(msg.sender, address(this), intent.amountIn);
+        intents[intentId] = intent;
+    }
+
+    // token_out from the solver to the user, and on a single domain the escrowed
+    // token_in to the solver in the same transaction
+    function sendFundsToUser(SolverTransferData calldata data) external payable {
+        IERC20(data.tokenOut).transferFrom(msg.sender, data.dstUser, data.amountOut);
+
+        if (data.singleDomain) {
+            Intent memory intent = intents[data.intentId];
+            require(intent.tokenIn != address(0), "unknown intent");
+            delete intents[data.intentId];
+            IERC20(intent.tokenIn).transfer(msg.sender, intent.amountIn);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+interface IMintable {
+    function transferFrom(address from, address to, uint256 amount) external returns (bool);
+    function mint(address to, uint256 amount) external;
+}
+
+// QuoterV2 and SwapRouter02 in one, placed at UNISWAP_QUOTER_V2 and
+// UNISWAP_SWAP_ROUTER_02. Quotes 1:1, swaps pay `drift` bps less than the
+// quote (the price moving between quote and swap) by minting token_out.
+contract MockUniswap {
+    uint256 public drift;
+
+    struct QuoteExactInputSingleParams {
+        address tokenIn;
+        address tokenOut;
+        uint256 amountIn;
+        uint24 fee;
+        uint160 sqrtPriceLimitX96;
+    }
+
+    struct ExactInputSingleParams {
+        address tokenIn;
+        address tokenOut;
+        uint24 fee;
+        address recipient;
+        uint256 amountIn;
+        uint256 amountOutMinimum;
+        uint160 sqrtPriceLimitX96;
+    }
+
+    function setDrift(uint256 bps) external {
+        drift = bps;
+    }
+
+    function quoteExactInputSingle(QuoteExactInputSingleParams calldata params)
+        external
+        pure
+        returns (uint256, uint160, uint32, uint256)
+    {
+        return (params.amountIn, 0, 0, 0);
+    }
+
+    function exactInputSingle(ExactInputSingleParams calldata params)
+        external
+        payable
+        returns (uint256 amountOut)
+    {
+        amountOut = params.amountIn * (10000 - drift) / 10000;
+        require(amountOut >= params.amountOutMinimum, "Too little received");
+        IMintable(params.tokenIn).transferFrom(msg.sender, address(this), params.amountIn);
+        IMintable(params.tokenOut).mint(params.recipient, amountOut);
+    }
+}
@@ -0,0 +1,235 @@
+//! handle_ethereum_execution against a local anvil node, with MockUSDT and
+//! MockEscrow (tests/contracts) placed at the mainnet USDT address and
+//! ESCROW_SC_ETHEREUM. Needs `anvil` and `solc` in PATH:
+//!
+//!     cargo test --test ethereum_execution -- --ignored
+
+use ethers::prelude::*;
+use ethers::utils::{Anvil, AnvilInstance};
+use example_solver::chains::ethereum::ethereum_chain::{
+    approve_erc20, ethereum_verify_intent, handle_ethereum_execution, ESCROW_SC_ETHEREUM,
+};
+use example_solver::chains::{
+    OperationInput, OperationOutput, PostIntentInfo, SwapTransferInput, SwapTransferOutput,
+    SOLVER_ADDRESSES,
+};
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod common;
+use common::compile;
+
+const USDT: &str = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+// 1000 USDT
+const FUNDS: u64 = 1_000_000_000;
+
+type Client = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+struct Chain {
+    anvil: AnvilInstance,
+    solver: Arc<Client>,
+    user: Arc<Client>,
+    usdt: Contract<Client>,
+}
+
+async fn start() -> Chain {
+    let anvil = Anvil::new().chain_id(1u64).spawn();
+
+    // read once by the solver's lazy statics, so before anything touches them
+    env::set_var("ETHEREUM_RPC", anvil.endpoint());
+    env::set_var("ETHEREUM_SIGNER", "env");
+    env::set_var("ETHEREUM_PKEY", hex::encode(anvil.keys()[0].to_bytes()));
+    env::set_var("TIMEOUT_MARGIN_ETHEREUM", "0");
+
+    let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+    let wallet = |i: usize| {
+        Arc::new(SignerMiddleware::new(
+            provider.clone(),
+            LocalWallet::from(anvil.keys()[i].clone()).with_chain_id(1u64),
+        ))
+    };
+
+    let (usdt_abi, usdt_code) = compile("MockUSDT");
+    let (_, escrow_code) = compile("MockEscrow");
+    for (address, code) in [(USDT, usdt_code), (ESCROW_SC_ETHEREUM, escrow_code)] {
+        provider
+            .request::<_, ()>("anvil_setCode", (Address::from_str(address).unwrap(), code))
+            .await
+            .unwrap();
+    }
+
+    let solver = wallet(0);
+    let user = wallet(1);
+    let usdt = Contract::new(Address::from_str(USDT).unwrap(), usdt_abi, solver.clone());
+    for account in [solver.address(), user.address()] {
+        usdt.method::<_, bool>("mint", (account, U256::from(FUNDS)))
+            .unwrap()
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+
+    Chain {
+        anvil,
+        solver,
+        user,
+        usdt,
+    }
+}
+
+async fn balance(chain: &Chain, account: Address) -> U256 {
+    chain
+        .usdt
+        .method::<_, U256>("balanceOf", account)
+        .unwrap()
+        .call()
+        .await
+        .unwrap()
+}
+
+fn intent(
+    src_chain: &str,
+    src_user: &str,
+    dst_user: Address,
+    amount_in: u64,
+    amount_out: u64,
+) -> PostIntentInfo {
+    let timeout = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 600;
+
+    PostIntentInfo {
+        function_name: String::from("transfer"),
+        src_chain: src_chain.to_string(),
+        dst_chain: String::from("ethereum"),
+        inputs: OperationInput::SwapTransfer(SwapTransferInput {
+            token_in: USDT.to_string(),
+            amount_in: amount_in.to_string(),
+            src_chain_user: src_user.to_string(),
+            timeout: timeout.to_string(),
+        }),
+        outputs: OperationOutput::SwapTransfer(SwapTransferOutput {
+            token_out: USDT.to_string(),
+            amount_out: amount_out.to_string(),
+            dst_chain_user: format!("{dst_user:?}"),
+        }),
+    }
+}
+
+// what the user and the auctioneer do on the src chain before we get code 4
+async fn escrow(chain: &Chain, intent_id: u64, intent: &PostIntentInfo, amount: &str) {
+    let (OperationInput::SwapTransfer(input), OperationOutput::SwapTransfer(output)) =
+        (&intent.inputs, &intent.outputs)
+    else {
+        unreachable!()
+    };
+    let amount_in = U256::from_dec_str(&input.amount_in).unwrap();
+
+    let usdt = chain.usdt.connect(chain.user.clone());
+    usdt.method::<_, bool>(
+        "approve",
+        (Address::from_str(ESCROW_SC_ETHEREUM).unwrap(), amount_in),
+    )
+    .unwrap()
+    .send()
+    .await
+    .unwrap()
+    .await
+    .unwrap();
+
+    let (escrow_abi, _) = compile("MockEscrow");
+    let escrow = Contract::new(
+        Address::from_str(ESCROW_SC_ETHEREUM).unwrap(),
+        escrow_abi,
+        chain.user.clone(),
+    );
+    let stored = (
+        Address::from_str(&input.token_in).unwrap(),
+        amount_in,
+        chain.user.address(),
+        output.dst_chain_user.clone(),
+        output.token_out.clone(),
+        amount.to_string(),
+        SOLVER_ADDRESSES[0].to_string(),
+        U256::from_dec_str(&input.timeout).unwrap(),
+    );
+    escrow
+        .method::<_, ()>("escrowFunds", (U256::from(intent_id), stored))
+        .unwrap()
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and solc"]
+async fn ethereum_execution() {
+    let chain = start().await;
+    let solver = chain.solver.address();
+    let user = chain.user.address();
+
+    // the solver approves the escrow once, handle_ethereum_execution only
+    // approves tokens it swapped into
+    approve_erc20(
+        &chain.anvil.endpoint(),
+        USDT,
+        ESCROW_SC_ETHEREUM,
+        &FUNDS.to_string(),
+    )
+    .await
+    .unwrap();
+
+    // single domain: user escrows 100 USDT, we send 99 USDT and get the 100
+    let single = intent(
+        "ethereum",
+        &format!("{user:?}"),
+        user,
+        100_000_000,
+        99_000_000,
+    );
+    escrow(&chain, 1, &single, "99000000").await;
+
+    assert!(ethereum_verify_intent("1", &single, "99000000")
+        .await
+        .is_ok());
+    assert!(ethereum_verify_intent("1", &single, "98000000")
+        .await
+        .is_err());
+    assert!(ethereum_verify_intent("7", &single, "99000000")
+        .await
+        .is_err());
+
+    let (solver_before, user_before) = (balance(&chain, solver).await, balance(&chain, user).await);
+    handle_ethereum_execution(&single, U256::from(1), "99000000", true, USDT)
+        .await
+        .unwrap();
+    assert_eq!(balance(&chain, solver).await, solver_before + 1_000_000);
+    assert_eq!(balance(&chain, user).await, user_before + 99_000_000);
+    // the escrow forgets filled intents
+    assert!(ethereum_verify_intent("1", &single, "99000000")
+        .await
+        .is_err());
+
+    // cross domain: token_in is escrowed on solana, here we only pay the user
+    let cross = intent(
+        "solana",
+        "6zYgJTTuHZZ3G7qNje7RbCSnNtVtGKsxN5YKopPP6cqL",
+        user,
+        50_000_000,
+        49_500_000,
+    );
+    let (solver_before, user_before) = (balance(&chain, solver).await, balance(&chain, user).await);
+    handle_ethereum_execution(&cross, U256::from(2), "49500000", false, USDT)
+        .await
+        .unwrap();
+    assert_eq!(balance(&chain, solver).await, solver_before - 49_500_000);
+    assert_eq!(balance(&chain, user).await, user_before + 49_500_000);
+}
@@ -0,0 +1,218 @@
+//! amm_swap against a local solana-test-validator holding a copy of a live
+//! pool, checking the quote against what the program actually pays. Needs
+//! `solana-test-validator` in PATH and access to the cluster it clones from:
+//!
+//! - SOLANA_AMM_TEST_POOL: the pool, as in SOLANA_AMM_POOLS, default the Orca
+//!   SOL/USDC whirlpool. Its second mint is sold for the first one.
+//! - SOLANA_AMM_TEST_CLUSTER: where the pool is cloned from, default mainnet-beta
+//!
+//!     cargo test --test solana_amm -- --ignored
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use example_solver::routers::solana_amm::solana_amm_router::{
+    amm_swap, load_cpmm_pool, load_whirlpool, quote, swap_instructions, RAYDIUM_CPMM_PROGRAM,
+    WHIRLPOOL_PROGRAM,
+};
+use example_solver::signer::SolanaSigner;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::solana_program::program_option::COption;
+use spl_token::solana_program::program_pack::Pack;
+use spl_token::state::{Account, AccountState};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+
+const DEFAULT_POOL: &str = "whirlpool:Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE";
+const DEFAULT_CLUSTER: &str = "https://api.mainnet-beta.solana.com";
+const RPC_URL: &str = "http://127.0.0.1:8899";
+const AMOUNT_IN: u64 = 100_000_000;
+
+// killed with the test, even when an assertion fails
+struct Validator(Child);
+
+impl Drop for Validator {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+// token account of `owner` holding `amount` of `mint`, at its associated address
+fn token_account(owner: &Pubkey, mint: &Pubkey, amount: u64) -> PathBuf {
+    let mut data = vec![0u8; Account::LEN];
+    Account::pack(
+        Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        },
+        &mut data,
+    )
+    .unwrap();
+
+    let path = env::temp_dir().join("example_solver_amm_token_account.json");
+    let account = serde_json::json!({
+        "pubkey": get_associated_token_address(owner, mint).to_string(),
+        "account": {
+            "lamports": LAMPORTS_PER_SOL,
+            "data": [STANDARD.encode(&data), "base64"],
+            "owner": spl_token::id().to_string(),
+            "executable": false,
+            "rentEpoch": 0,
+            "space": Account::LEN,
+        }
+    });
+    fs::write(&path, account.to_string()).unwrap();
+    path
+}
+
+async fn token_balance(client: &RpcClient, owner: &Pubkey, mint: &Pubkey) -> u64 {
+    match client
+        .get_token_account_balance(&get_associated_token_address(owner, mint))
+        .await
+    {
+        Ok(balance) => balance.amount.parse().unwrap(),
+        Err(_) => 0,
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs solana-test-validator and network access"]
+async fn amm_swap_against_cloned_pool() {
+    let pool = env::var("SOLANA_AMM_TEST_POOL").unwrap_or_else(|_| DEFAULT_POOL.to_string());
+    let cluster =
+        env::var("SOLANA_AMM_TEST_CLUSTER").unwrap_or_else(|_| DEFAULT_CLUSTER.to_string());
+    let (kind, address) = pool.split_once(':').expect("SOLANA_AMM_TEST_POOL is <kind>:<pool>");
+    let address = Pubkey::from_str(address).unwrap();
+
+    // read once by the solver's lazy statics, so before anything touches them
+    env::set_var("SOLANA_AMM_POOLS", &pool);
+    env::set_var("SOLANA_AMM_SLIPPAGE_BPS", "100");
+    env::set_var("SOLANA_RPC", RPC_URL);
+
+    let remote = RpcClient::new_with_commitment(cluster.clone(), CommitmentConfig::confirmed());
+    let (program, token_out, token_in) = match kind {
+        "whirlpool" => {
+            let pool = load_whirlpool(&remote, address).await.unwrap();
+            (WHIRLPOOL_PROGRAM, pool.mint_a, pool.mint_b)
+        }
+        "raydium_cpmm" => {
+            let pool = load_cpmm_pool(&remote, address).await.unwrap();
+            (RAYDIUM_CPMM_PROGRAM, pool.mint_0, pool.mint_1)
+        }
+        _ => panic!("Unknown AMM {kind}"),
+    };
+
+    // every account the swap touches, except the solver's own
+    let solver = Keypair::new();
+    let remote_quote = quote(&remote, &token_in, &token_out, AMOUNT_IN).await.unwrap();
+    let own = [
+        solver.pubkey(),
+        get_associated_token_address(&solver.pubkey(), &token_in),
+        get_associated_token_address(&solver.pubkey(), &token_out),
+    ];
+    let mut accounts: Vec<Pubkey> = swap_instructions(
+        &remote_quote,
+        &solver.pubkey(),
+        &token_in,
+        &token_out,
+        AMOUNT_IN,
+        0,
+    )
+    .iter()
+    .flat_map(|instruction| instruction.accounts.iter().map(|meta| meta.pubkey))
+    .filter(|account| !own.contains(account))
+    .collect();
+    accounts.sort();
+    accounts.dedup();
+    let found = remote.get_multiple_accounts(&accounts).await.unwrap();
+    let clones: Vec<Pubkey> = accounts
+        .into_iter()
+        .zip(found)
+        .filter_map(|(account, found)| found.filter(|found| !found.executable).map(|_| account))
+        .collect();
+
+    let ledger = env::temp_dir().join("example_solver_amm_test_ledger");
+    let mut command = Command::new("solana-test-validator");
+    command
+        .arg("--reset")
+        .arg("--quiet")
+        .arg("--ledger")
+        .arg(&ledger)
+        .args(["--url", &cluster])
+        .args(["--clone-upgradeable-program", program])
+        .arg("--account")
+        .arg(own[1].to_string())
+        .arg(token_account(&solver.pubkey(), &token_in, 10 * AMOUNT_IN))
+        .stdout(Stdio::null());
+    for account in &clones {
+        command.args(["--clone", &account.to_string()]);
+    }
+    let _validator = Validator(
+        command
+            .spawn()
+            .expect("solana-test-validator must be installed"),
+    );
+
+    let client = RpcClient::new_with_commitment(RPC_URL.to_string(), CommitmentConfig::confirmed());
+    let mut started = false;
+    for _ in 0..120 {
+        if client.get_health().await.is_ok() {
+            started = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    assert!(started, "solana-test-validator did not start");
+
+    let airdrop = client
+        .request_airdrop(&solver.pubkey(), 10 * LAMPORTS_PER_SOL)
+        .await
+        .unwrap();
+    while !client.confirm_transaction(&airdrop).await.unwrap() {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    let signer = SolanaSigner::Local(solver.insecure_clone());
+    let quoted = quote(&client, &token_in, &token_out, AMOUNT_IN).await.unwrap();
+    assert!(quoted.min_amount_out() < quoted.amount_out);
+
+    // a floor the pool can't pay fails before anything is sent
+    assert!(amm_swap(&client, &signer, &token_in, &token_out, AMOUNT_IN, quoted.amount_out * 2)
+        .await
+        .is_err());
+    assert_eq!(
+        token_balance(&client, &solver.pubkey(), &token_in).await,
+        10 * AMOUNT_IN
+    );
+
+    amm_swap(&client, &signer, &token_in, &token_out, AMOUNT_IN, 0)
+        .await
+        .unwrap();
+    let received = token_balance(&client, &solver.pubkey(), &token_out).await;
+    assert_eq!(
+        token_balance(&client, &solver.pubkey(), &token_in).await,
+        9 * AMOUNT_IN
+    );
+
+    // the quote is rounded against us and the program checked min_amount_out
+    assert!(received >= quoted.min_amount_out());
+    assert!(
+        received.abs_diff(quoted.amount_out) * 10_000 <= quoted.amount_out,
+        "quoted {}, received {received}",
+        quoted.amount_out
+    );
+}
@@ -0,0 +1,262 @@
+//! handle_solana_execution against a local solana-test-validator running the
+//! bridge_escrow program. Needs `solana-test-validator` in PATH and:
+//!
+//! - BRIDGE_ESCROW_SO: the bridge_escrow program, loaded at bridge_escrow::ID
+//! - SOLANA_ESCROW_FIXTURES: a directory with `accounts/`, the escrow accounts
+//!   dumped with `solana account --output json` (intent PDAs, auctioneer state
+//!   and its token accounts), and `intents.json`:
+//!   `{"solver_keypair": "<base58, the winner_solver of the dumped intents>",
+//!     "intents": [{"intent_id", "amount", "intent": <PostIntentInfo>}]}`
+//!
+//! The USDT mint is created by the test, so intents must use USDT as token_in
+//! and token_out (no router is reachable from the validator).
+//!
+//! Only single domain intents are covered. A cross domain fill sends the IBC
+//! message through solana_ibc, whose storage, trie and chain accounts can't be
+//! built offline, so that path of handle_solana_execution is not tested.
+//! `examples/solana_escrow_fixtures.rs` writes the fixtures:
+//!
+//!     ID=$(cargo run -q --example solana_escrow_fixtures -- fixtures/)
+//!     solana program dump -u m $ID bridge_escrow.so
+//!     BRIDGE_ESCROW_SO=bridge_escrow.so SOLANA_ESCROW_FIXTURES=fixtures/ \
+//!         cargo test --test solana_execution -- --ignored
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use example_solver::chains::solana::solana_chain::{handle_solana_execution, solana_verify_intent};
+use example_solver::chains::{OperationInput, OperationOutput, PostIntentInfo};
+use serde::Deserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use spl_associated_token_account::get_associated_token_address;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token::solana_program::program_option::COption;
+use spl_token::solana_program::program_pack::Pack;
+use spl_token::state::Mint;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+
+const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+const RPC_URL: &str = "http://127.0.0.1:8899";
+// 1000 USDT
+const FUNDS: u64 = 1_000_000_000;
+
+#[derive(Deserialize)]
+struct Fixtures {
+    solver_keypair: String,
+    intents: Vec<FixtureIntent>,
+}
+
+#[derive(Deserialize)]
+struct FixtureIntent {
+    intent_id: String,
+    amount: String,
+    intent: PostIntentInfo,
+}
+
+// killed with the test, even when an assertion fails
+struct Validator(Child);
+
+impl Drop for Validator {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+// USDT mint account owned by the token program, with `authority` as mint authority
+fn usdt_mint_account(dir: &Path, authority: &Pubkey) -> PathBuf {
+    let mut data = vec![0u8; Mint::LEN];
+    Mint::pack(
+        Mint {
+            mint_authority: COption::Some(*authority),
+            supply: 0,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut data,
+    )
+    .unwrap();
+
+    let path = dir.join("usdt_mint.json");
+    let account = serde_json::json!({
+        "pubkey": USDT,
+        "account": {
+            "lamports": LAMPORTS_PER_SOL,
+            "data": [STANDARD.encode(&data), "base64"],
+            "owner": spl_token::id().to_string(),
+            "executable": false,
+            "rentEpoch": 0,
+            "space": Mint::LEN,
+        }
+    });
+    fs::write(&path, account.to_string()).unwrap();
+    path
+}
+
+async fn start(fixtures: &Path, mint_authority: &Pubkey) -> Validator {
+    let ledger = env::temp_dir().join("example_solver_test_ledger");
+    let program = env::var("BRIDGE_ESCROW_SO").expect("BRIDGE_ESCROW_SO must be set");
+
+    let mut command = Command::new("solana-test-validator");
+    command
+        .arg("--reset")
+        .arg("--quiet")
+        .arg("--ledger")
+        .arg(&ledger)
+        .args(["--bpf-program", &bridge_escrow::ID.to_string(), &program])
+        .arg("--account")
+        .arg(USDT)
+        .arg(usdt_mint_account(&env::temp_dir(), mint_authority))
+        .arg("--account-dir")
+        .arg(fixtures.join("accounts"))
+        .stdout(Stdio::null());
+    let validator = Validator(
+        command
+            .spawn()
+            .expect("solana-test-validator must be installed"),
+    );
+
+    let client = RpcClient::new(RPC_URL.to_string());
+    for _ in 0..60 {
+        if client.get_health().await.is_ok() {
+            return validator;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    panic!("solana-test-validator did not start");
+}
+
+async fn send(client: &RpcClient, instructions: &[Instruction], signers: &[&Keypair]) {
+    let blockhash = client.get_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&signers[0].pubkey()),
+        signers,
+        blockhash,
+    );
+    client.send_and_confirm_transaction(&tx).await.unwrap();
+}
+
+async fn balance(client: &RpcClient, owner: &str, mint: &str) -> u64 {
+    let account = get_associated_token_address(
+        &Pubkey::from_str(owner).unwrap(),
+        &Pubkey::from_str(mint).unwrap(),
+    );
+    match client.get_token_account_balance(&account).await {
+        Ok(balance) => balance.amount.parse().unwrap(),
+        Err(_) => 0,
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs solana-test-validator and bridge_escrow fixtures"]
+async fn solana_execution() {
+    let dir = PathBuf::from(
+        env::var("SOLANA_ESCROW_FIXTURES").expect("SOLANA_ESCROW_FIXTURES must be set"),
+    );
+    let fixtures: Fixtures =
+        serde_json::from_slice(&fs::read(dir.join("intents.json")).unwrap()).unwrap();
+
+    let solver = Keypair::from_base58_string(&fixtures.solver_keypair);
+    let mint_authority = Keypair::new();
+    let _validator = start(&dir, &mint_authority.pubkey()).await;
+
+    // read once by the solver's lazy statics, so before anything touches them
+    env::set_var("SOLANA_RPC", RPC_URL);
+    env::set_var("SOLANA_SIGNER", "env");
+    env::set_var("SOLANA_KEYPAIR", solver.to_base58_string());
+    env::set_var("TIMEOUT_MARGIN_SOLANA", "0");
+
+    let client = RpcClient::new_with_commitment(RPC_URL.to_string(), CommitmentConfig::confirmed());
+    let airdrop = client
+        .request_airdrop(&solver.pubkey(), 10 * LAMPORTS_PER_SOL)
+        .await
+        .unwrap();
+    while !client.confirm_transaction(&airdrop).await.unwrap() {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    let usdt = Pubkey::from_str(USDT).unwrap();
+    let mut instructions = vec![create_associated_token_account_idempotent(
+        &solver.pubkey(),
+        &solver.pubkey(),
+        &usdt,
+        &spl_token::id(),
+    )];
+    instructions.push(
+        spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &usdt,
+            &get_associated_token_address(&solver.pubkey(), &usdt),
+            &mint_authority.pubkey(),
+            &[],
+            FUNDS,
+        )
+        .unwrap(),
+    );
+    // the escrow pays users into their token_out account
+    for fixture in &fixtures.intents {
+        if let OperationOutput::SwapTransfer(output) = &fixture.intent.outputs {
+            instructions.push(create_associated_token_account_idempotent(
+                &solver.pubkey(),
+                &Pubkey::from_str(&output.dst_chain_user).unwrap(),
+                &Pubkey::from_str(&output.token_out).unwrap(),
+                &spl_token::id(),
+            ));
+        }
+    }
+    send(&client, &instructions, &[&solver, &mint_authority]).await;
+
+    let solver_pubkey = solver.pubkey().to_string();
+    for fixture in &fixtures.intents {
+        let intent = &fixture.intent;
+        let (OperationInput::SwapTransfer(input), OperationOutput::SwapTransfer(output)) =
+            (&intent.inputs, &intent.outputs)
+        else {
+            panic!("intent {} is not a swap transfer", fixture.intent_id);
+        };
+        assert_eq!(
+            intent.src_chain, intent.dst_chain,
+            "intent {}: only single domain fixtures are supported",
+            fixture.intent_id
+        );
+
+        solana_verify_intent("solana", &fixture.intent_id, intent, &fixture.amount)
+            .await
+            .unwrap();
+
+        let user_before = balance(&client, &output.dst_chain_user, &output.token_out).await;
+        let solver_before = balance(&client, &solver_pubkey, &output.token_out).await;
+
+        handle_solana_execution(intent, &fixture.intent_id, &fixture.amount, USDT)
+            .await
+            .unwrap();
+
+        let amount: u64 = fixture.amount.parse().unwrap();
+        assert_eq!(
+            balance(&client, &output.dst_chain_user, &output.token_out).await,
+            user_before + amount,
+            "user of intent {}",
+            fixture.intent_id
+        );
+        // token_in and token_out are both USDT here, so the solver ends up with
+        // amount_in - amount more
+        let amount_in: u64 = input.amount_in.parse().unwrap();
+        assert_eq!(
+            balance(&client, &solver_pubkey, &output.token_out).await,
+            solver_before + amount_in - amount,
+            "solver on intent {}",
+            fixture.intent_id
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! uniswap_swap against a local anvil node, with MockUniswap (tests/contracts)
+//! as QuoterV2 and SwapRouter02, MockUSDT code for both tokens and the gas
+//! oracle served by mock_routers. Needs `anvil` and `solc` in PATH:
+//!
+//!     cargo test --features test-support --test uniswap_fallback -- --ignored
+
+use ethers::prelude::*;
+use ethers::utils::Anvil;
+use example_solver::chains::ethereum::ethereum_chain::uniswap_swap;
+use example_solver::mock_routers::{Fixtures, MockRouters};
+use num_bigint::BigInt;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+
+mod common;
+use common::compile;
+
+const TOKEN_IN: &str = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+const TOKEN_OUT: &str = "0x1000000000000000000000000000000000000001";
+const UNISWAP: &str = "0x2000000000000000000000000000000000000002";
+const AMOUNT: u64 = 100_000_000;
+
+type Client = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+async fn set_drift(uniswap: &Contract<Client>, bps: u64) {
+    uniswap
+        .method::<_, ()>("setDrift", U256::from(bps))
+        .unwrap()
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+}
+
+async fn balance(token: &Contract<Client>, account: Address) -> U256 {
+    token
+        .method::<_, U256>("balanceOf", account)
+        .unwrap()
+        .call()
+        .await
+        .unwrap()
+}
+
+async fn swap(rpc_url: &str, min_amount_out: u64) -> Result<(), String> {
+    uniswap_swap(
+        rpc_url,
+        Address::from_str(TOKEN_IN).unwrap(),
+        Address::from_str(TOKEN_OUT).unwrap(),
+        &BigInt::from(AMOUNT),
+        &BigInt::from(min_amount_out),
+    )
+    .await
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and solc"]
+async fn uniswap_swap_min_amount_out() {
+    let mock_routers = MockRouters::start(Fixtures::default()).unwrap();
+    let anvil = Anvil::new().chain_id(1u64).spawn();
+
+    // read once by the solver's lazy statics, so before anything touches them
+    env::set_var("ETHEREUM_RPC", anvil.endpoint());
+    env::set_var("ETHEREUM_SIGNER", "env");
+    env::set_var("ETHEREUM_PKEY", hex::encode(anvil.keys()[0].to_bytes()));
+    env::set_var("GAS_ORACLE_URL", mock_routers.gas_oracle_url());
+    env::set_var("UNISWAP_QUOTER_V2", UNISWAP);
+    env::set_var("UNISWAP_SWAP_ROUTER_02", UNISWAP);
+    env::set_var("UNISWAP_FEE_TIERS", "3000");
+    env::set_var("UNISWAP_SLIPPAGE_BPS", "100");
+
+    let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+    let solver = Arc::new(SignerMiddleware::new(
+        provider.clone(),
+        LocalWallet::from(anvil.keys()[0].clone()).with_chain_id(1u64),
+    ));
+
+    let (token_abi, token_code) = compile("MockUSDT");
+    let (uniswap_abi, uniswap_code) = compile("MockUniswap");
+    for (address, code) in [
+        (TOKEN_IN, token_code.clone()),
+        (TOKEN_OUT, token_code),
+        (UNISWAP, uniswap_code),
+    ] {
+        provider
+            .request::<_, ()>("anvil_setCode", (Address::from_str(address).unwrap(), code))
+            .await
+            .unwrap();
+    }
+
+    let token = |address: &str| {
+        Contract::new(Address::from_str(address).unwrap(), token_abi.clone(), solver.clone())
+    };
+    let (token_in, token_out) = (token(TOKEN_IN), token(TOKEN_OUT));
+    token_in
+        .method::<_, bool>("mint", (solver.address(), U256::from(10 * AMOUNT)))
+        .unwrap()
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let uniswap = Contract::new(Address::from_str(UNISWAP).unwrap(), uniswap_abi, solver.clone());
+    let rpc_url = anvil.endpoint();
+
+    // the price moved less than UNISWAP_SLIPPAGE_BPS since the quote
+    set_drift(&uniswap, 50).await;
+    let before = balance(&token_out, solver.address()).await;
+    swap(&rpc_url, 0).await.unwrap();
+    assert_eq!(balance(&token_out, solver.address()).await, before + AMOUNT * 9_950 / 10_000);
+
+    // more than UNISWAP_SLIPPAGE_BPS: the swap reverts instead of paying anything
+    set_drift(&uniswap, 200).await;
+    let before = balance(&token_out, solver.address()).await;
+    assert!(swap(&rpc_url, 0).await.is_err());
+    assert_eq!(balance(&token_out, solver.address()).await, before);
+
+    // within slippage, but below what we promised the user
+    set_drift(&uniswap, 50).await;
+    assert!(swap(&rpc_url, AMOUNT * 9_990 / 10_000).await.is_err());
+    assert_eq!(balance(&token_out, solver.address()).await, before);
+
+    // the quote itself doesn't cover the promise, nothing is sent
+    assert!(swap(&rpc_url, AMOUNT + 1).await.is_err());
+}